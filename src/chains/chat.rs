@@ -0,0 +1,43 @@
+use langchain_rust::{
+    chain::{LLMChain, LLMChainBuilder},
+    language_models::llm::LLM,
+    prompt::HumanMessagePromptTemplate,
+    template_jinja2,
+};
+
+use super::ChainPrompt;
+
+const PROMPT: &str = r#"You are a command line assistant in a terminal on {{os}}.
+The commands run in this environment:
+{{environment}}
+Answer briefly, in markdown format, with commands, options and paths in backticks.
+When you suggest a command, give it alone in a ```sh code block at the end of your answer.
+
+Conversation so far:
+{{history}}
+
+User: {{input}}
+"#;
+
+pub const CHAT_PROMPT: ChainPrompt = ChainPrompt {
+    name: "chat",
+    description: "Answers a message of the chat, with the conversation so far",
+    template: PROMPT,
+    variables: &["os", "environment", "history", "input"],
+    required: &["history", "input"],
+};
+
+/// This function creates a chain that answers a chat message.
+pub fn chat_chain<VALUE: LLM + 'static>(llm: VALUE, prompt: Option<&str>) -> LLMChain {
+    LLMChainBuilder::new()
+        .llm(llm)
+        .prompt(HumanMessagePromptTemplate::new(template_jinja2!(
+            prompt.unwrap_or(PROMPT),
+            "os",
+            "environment",
+            "history",
+            "input"
+        )))
+        .build()
+        .unwrap() //safe to unwrap
+}
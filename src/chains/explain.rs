@@ -5,25 +5,44 @@ use langchain_rust::{
     template_jinja2,
 };
 
+use super::ChainPrompt;
+
 const PROMPT: &str = r#"Explain the following {{os}} command: {{command}}.
-You should explain the command using bullet points, in  markdown format.
+The command runs in this environment:
+{{environment}}
+Local documentation of its programs and of the options it uses:
+{{documentation}}
+Describe the options as the documentation does, and say so when an option is not in it.
+You should explain the command using bullet points, in markdown format.
 
-Exmaple Command: ls -la
+Example Command: ls -la
 
 Example Result
-• `ls`  Lists the contents of a directory. displays the names of files and directories in the current directory.
+• `ls` Lists the contents of a directory. Displays the names of files and directories in the current directory.
   • `-l` (part of `-la`) Displays the listing in long format, providing detailed information such as permissions, number of links, owner, group, size, and timestamp for each file and directory.
   • `-a` (part of `-la`) Includes entries that start with a dot (.), showing hidden files alongside the regular listings.
 
 
 "#;
 
+pub const EXPLAIN_PROMPT: ChainPrompt = ChainPrompt {
+    name: "explain",
+    description: "Explains a command with bullet points",
+    template: PROMPT,
+    variables: &["os", "environment", "documentation", "command"],
+    required: &["command"],
+};
+
 /// This function creates a chain that explains a command.
-pub fn explain_command_chain<VALUE: LLM + 'static>(llm: VALUE) -> LLMChain {
+pub fn explain_command_chain<VALUE: LLM + 'static>(llm: VALUE, prompt: Option<&str>) -> LLMChain {
     LLMChainBuilder::new()
         .llm(llm)
         .prompt(HumanMessagePromptTemplate::new(template_jinja2!(
-            PROMPT, "os", "command"
+            prompt.unwrap_or(PROMPT),
+            "os",
+            "environment",
+            "documentation",
+            "command"
         )))
         .build()
         .unwrap() //safe to unwrap
@@ -0,0 +1,52 @@
+use langchain_rust::{
+    chain::{LLMChain, LLMChainBuilder},
+    language_models::llm::LLM,
+    prompt::HumanMessagePromptTemplate,
+    template_jinja2,
+};
+
+use super::ChainPrompt;
+
+const PROMPT: &str = r#"Explain the following {{kind}} section by section. It runs on {{os}}, in this environment:
+{{environment}}
+{{part}}
+Its lines are numbered, and every section starts with a `# Lines <start>-<end>: <title>` heading:
+
+{{script}}
+
+For every section, write its heading as `Lines <start>-<end>: <title>` on a line of its own, then explain what the section does using bullet points, in markdown format. Refer to single lines as `line <number>` when it helps, and point out what reaches the network, writes files or needs root.
+
+Example Section:
+# Lines 3-4: Install dependencies
+   3| apt-get update
+   4| apt-get install -y curl
+
+Example Result
+Lines 3-4: Install dependencies
+• `apt-get update` refreshes the package lists, downloading them from the network.
+• `apt-get install -y curl` installs `curl` without asking for confirmation, which needs root.
+"#;
+
+pub const EXPLAIN_SCRIPT_PROMPT: ChainPrompt = ChainPrompt {
+    name: "explain_script",
+    description: "Explains a script, Makefile, Dockerfile or CI configuration section by section",
+    template: PROMPT,
+    variables: &["os", "environment", "kind", "part", "script"],
+    required: &["script"],
+};
+
+/// This function creates a chain that explains a script section by section.
+pub fn explain_script_chain<VALUE: LLM + 'static>(llm: VALUE, prompt: Option<&str>) -> LLMChain {
+    LLMChainBuilder::new()
+        .llm(llm)
+        .prompt(HumanMessagePromptTemplate::new(template_jinja2!(
+            prompt.unwrap_or(PROMPT),
+            "os",
+            "environment",
+            "kind",
+            "part",
+            "script"
+        )))
+        .build()
+        .unwrap() //safe to unwrap
+}
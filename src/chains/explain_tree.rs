@@ -0,0 +1,60 @@
+use langchain_rust::{
+    chain::{LLMChain, LLMChainBuilder},
+    language_models::llm::LLM,
+    prompt::HumanMessagePromptTemplate,
+    template_jinja2,
+};
+
+use super::ChainPrompt;
+
+const PROMPT: &str = r#"Explain the parts of the following {{os}} command: {{command}}
+The command runs in this environment:
+{{environment}}
+Local documentation of its programs and of the options it uses:
+{{documentation}}
+Describe the options as the documentation does.
+
+These are the parts of the command, numbered and nested as they were parsed:
+{{parts}}
+
+Explain every part in one short sentence, in the context of the command: what a program does here, what an option or argument means to its command, where a redirect reads or writes, what an operator chains.
+Answer with one line per part, formatted as `<number>: <explanation>`, in the same order and with nothing else.
+
+Example Parts:
+1. pipeline `ls -la | wc -l`
+  2. command `ls`
+    3. option `-la`
+  4. command `wc`
+    5. option `-l`
+
+Example Result:
+1: Counts the entries of the current directory, hidden ones included.
+2: Lists the contents of the current directory.
+3: Uses the long listing format (`-l`) and includes hidden entries (`-a`).
+4: Counts what it reads from the pipe.
+5: Counts lines only.
+"#;
+
+pub const EXPLAIN_TREE_PROMPT: ChainPrompt = ChainPrompt {
+    name: "explain_tree",
+    description: "Explains each part of a parsed command, one line per part",
+    template: PROMPT,
+    variables: &["os", "environment", "documentation", "command", "parts"],
+    required: &["parts"],
+};
+
+/// This function creates a chain that explains the parts of a parsed command.
+pub fn explain_tree_chain<VALUE: LLM + 'static>(llm: VALUE, prompt: Option<&str>) -> LLMChain {
+    LLMChainBuilder::new()
+        .llm(llm)
+        .prompt(HumanMessagePromptTemplate::new(template_jinja2!(
+            prompt.unwrap_or(PROMPT),
+            "os",
+            "environment",
+            "documentation",
+            "command",
+            "parts"
+        )))
+        .build()
+        .unwrap() //safe to unwrap
+}
@@ -3,79 +3,205 @@ use langchain_rust::language_models::llm::LLM;
 use langchain_rust::prompt::HumanMessagePromptTemplate;
 use langchain_rust::template_jinja2;
 
+use super::ChainPrompt;
+
 const PROMPT: &str = r#"
-"{{input}}
+{{input}}
 
 You Should:
-- Create a conventional commit message reflecting these modifications.
-- Ensure the message adheres to the conventional commit format,
-  including identifying the type of change (feat, fix, docs, style, refactor, test, chore),
-  a brief and imperative summary of the changes, and optionally a scope.
+- Create a commit message reflecting these modifications.
+{{style}}
 - YOU SHOULD ONLY RETURN ONE COMMIT MESSAGE.
+{{rules}}
 
 Answer Example:
-feat(parser): add ability to parse JSON files.
+{{examples}}
 "#;
 
 const PROMPT_WITH_CONTEXT: &str = r#"
 Given file changes described as "{{input}}" and additional context provided by "{{context}}"
 
 You Should:
-- Create a conventional commit message reflecting these modifications.
-- Ensure the message adheres to the conventional commit format, which includes identifying the type of
-  change (feat, fix, docs, style, refactor, test, chore), providing a brief and imperative
-  summary of the changes, specifying a scope (if applicable), and incorporating the
-  provided context to better explain the modifications. 
+- Create a commit message reflecting these modifications, incorporating the provided
+  context to better explain the modifications.
+{{style}}
 - YOU SHOULD ONLY RETURN ONE COMMIT MESSAGE.
+{{rules}}
 
 Answer Example:
-feat(parser):add ability to parse JSON files.
+{{examples}}
 
 "#;
-pub fn git_commit_chain<VALUE: LLM + 'static>(llm: VALUE) -> LLMChain {
+
+/// How commit messages are written when the history of the repository says nothing.
+pub const DEFAULT_COMMIT_STYLE: &str = r#"- Ensure the message adheres to the conventional commit format,
+  including identifying the type of change (feat, fix, docs, style, refactor, perf, test,
+  build, ci, chore), a brief and imperative summary of the changes, and optionally a scope.
+- Keep the subject line within 72 characters, without a final period."#;
+
+/// The answer example when the history of the repository has none to offer.
+pub const DEFAULT_COMMIT_EXAMPLE: &str = "feat(parser): add ability to parse JSON files";
+
+/// The answer example of full messages when the history of the repository has none to offer.
+pub const DEFAULT_FULL_COMMIT_EXAMPLE: &str = r#"feat(parser): add ability to parse JSON files
+
+Configuration could only be written in TOML, while most of our users
+already generate JSON from their deployment tooling.
+
+The format is picked from the file extension, TOML stays the default."#;
+
+pub const GIT_COMMIT_PROMPT: ChainPrompt = ChainPrompt {
+    name: "commit",
+    description: "Writes a commit message for the staged changes",
+    template: PROMPT,
+    variables: &["input", "style", "examples", "rules"],
+    required: &["input"],
+};
+
+pub const GIT_COMMIT_WITH_CONTEXT_PROMPT: ChainPrompt = ChainPrompt {
+    name: "commit_with_context",
+    description: "Writes a commit message for the staged changes and the given --context",
+    template: PROMPT_WITH_CONTEXT,
+    variables: &["input", "context", "style", "examples", "rules"],
+    required: &["input", "context"],
+};
+
+pub fn git_commit_chain<VALUE: LLM + 'static>(llm: VALUE, prompt: Option<&str>) -> LLMChain {
+    LLMChainBuilder::new()
+        .llm(llm)
+        .prompt(HumanMessagePromptTemplate::new(template_jinja2!(
+            prompt.unwrap_or(PROMPT),
+            "input",
+            "style",
+            "examples",
+            "rules"
+        )))
+        .build()
+        .unwrap() //safe to unwrap
+}
+
+pub fn git_commit_chain_with_context<VALUE: LLM + 'static>(
+    llm: VALUE,
+    prompt: Option<&str>,
+) -> LLMChain {
     LLMChainBuilder::new()
         .llm(llm)
         .prompt(HumanMessagePromptTemplate::new(template_jinja2!(
-            PROMPT, "input"
+            prompt.unwrap_or(PROMPT_WITH_CONTEXT),
+            "input",
+            "context",
+            "style",
+            "examples",
+            "rules"
         )))
         .build()
         .unwrap() //safe to unwrap
 }
 
-pub fn git_commit_chain_with_context<VALUE: LLM + 'static>(llm: VALUE) -> LLMChain {
+const PROMPT_FULL: &str = r#"
+{{input}}
+
+Additional context from the author: {{context}}
+
+You Should:
+- Create a full commit message reflecting these modifications, made of:
+  - a subject line,
+  - a blank line, then a body of one or more short paragraphs explaining why the
+    change was made and what it changes, not how,
+  - only when the changes break compatibility, a blank line and a footer
+    `BREAKING CHANGE: <what breaks and how to migrate>`.
+{{style}}
+- Do not add any other footer, nor any text before or after the message.
+- YOU SHOULD ONLY RETURN ONE COMMIT MESSAGE.
+{{rules}}
+
+Answer Example:
+{{examples}}
+"#;
+
+pub const GIT_COMMIT_FULL_PROMPT: ChainPrompt = ChainPrompt {
+    name: "commit_full",
+    description: "Writes a commit message with a body and footers for the staged changes",
+    template: PROMPT_FULL,
+    variables: &["input", "context", "style", "examples", "rules"],
+    required: &["input"],
+};
+
+pub fn git_commit_full_chain<VALUE: LLM + 'static>(llm: VALUE, prompt: Option<&str>) -> LLMChain {
     LLMChainBuilder::new()
         .llm(llm)
         .prompt(HumanMessagePromptTemplate::new(template_jinja2!(
-            PROMPT_WITH_CONTEXT,
+            prompt.unwrap_or(PROMPT_FULL),
             "input",
-            "context"
+            "context",
+            "style",
+            "examples",
+            "rules"
         )))
         .build()
         .unwrap() //safe to unwrap
 }
 
 const PROMPT_REVISE_GIT: &str = r#"
-Giving this git commit :{{commit}}, modify it to {{to}}.
-You sould just return the complete commit message, nothing more.
+Given this git commit: {{commit}}, modify it to {{to}}.
+You should just return the complete commit message, nothing more.
 
 Example input Commit:
-feat(parser):add ability to parse JSON files.
+feat(parser): add ability to parse JSON files.
 
 Example To change:
 other option
 
-Example anwer:
-feat(parser):add new JSON parse function to parse files
+Example answer:
+feat(parser): add new JSON parse function to parse files
 
 "#;
-pub fn revise_commit_chain<VALUE: LLM + 'static>(llm: VALUE) -> LLMChain {
+
+pub const REVISE_COMMIT_PROMPT: ChainPrompt = ChainPrompt {
+    name: "revise_commit",
+    description: "Revises a commit message as the user asks",
+    template: PROMPT_REVISE_GIT,
+    variables: &["to", "commit"],
+    required: &["to", "commit"],
+};
+
+pub fn revise_commit_chain<VALUE: LLM + 'static>(llm: VALUE, prompt: Option<&str>) -> LLMChain {
     LLMChainBuilder::new()
         .llm(llm)
         .prompt(HumanMessagePromptTemplate::new(template_jinja2!(
-            PROMPT_REVISE_GIT,
+            prompt.unwrap_or(PROMPT_REVISE_GIT),
             "to",
             "commit"
         )))
         .build()
         .unwrap() //safe to unwrap
 }
+
+const PROMPT_SUMMARIZE_DIFF: &str = r#"
+These are the staged changes of a single file:
+{{diff}}
+
+Summarize what changed in this file in one or two sentences, for someone writing the
+commit message. Mention the functions, types or settings that changed.
+You should just return the summary, nothing more.
+"#;
+
+pub const SUMMARIZE_DIFF_PROMPT: ChainPrompt = ChainPrompt {
+    name: "summarize_diff",
+    description: "Summarizes the changes of a file too large to send whole with the commit",
+    template: PROMPT_SUMMARIZE_DIFF,
+    variables: &["diff"],
+    required: &["diff"],
+};
+
+/// This function creates a chain that summarizes the staged changes of a file.
+pub fn summarize_diff_chain<VALUE: LLM + 'static>(llm: VALUE, prompt: Option<&str>) -> LLMChain {
+    LLMChainBuilder::new()
+        .llm(llm)
+        .prompt(HumanMessagePromptTemplate::new(template_jinja2!(
+            prompt.unwrap_or(PROMPT_SUMMARIZE_DIFF),
+            "diff"
+        )))
+        .build()
+        .unwrap() //safe to unwrap
+}
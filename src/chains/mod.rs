@@ -1,11 +1,23 @@
 mod explain;
 pub use explain::*;
 
+mod explain_tree;
+pub use explain_tree::*;
+
+mod explain_script;
+pub use explain_script::*;
+
 mod recommend;
 pub use recommend::*;
 
 mod revise_command;
 pub use revise_command::*;
 
+mod chat;
+pub use chat::*;
+
 mod git_commit;
 pub use git_commit::*;
+
+mod prompt;
+pub use prompt::*;
@@ -0,0 +1,69 @@
+use regex::Regex;
+
+use super::{
+    CHAT_PROMPT, EXPLAIN_PROMPT, EXPLAIN_SCRIPT_PROMPT, EXPLAIN_TREE_PROMPT, FIX_COMMAND_PROMPT,
+    GIT_COMMIT_FULL_PROMPT, GIT_COMMIT_PROMPT, GIT_COMMIT_WITH_CONTEXT_PROMPT,
+    RECOMMEND_GITHUB_PROMPT, RECOMMEND_GIT_PROMPT, RECOMMEND_PROMPT, REVISE_COMMAND_PROMPT,
+    REVISE_COMMIT_PROMPT, SUMMARIZE_DIFF_PROMPT,
+};
+
+/// The built-in prompt of a chain, with the variables the chain fills in.
+pub struct ChainPrompt {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub template: &'static str,
+    /// Every variable the chain passes to the template.
+    pub variables: &'static [&'static str],
+    /// Variables a template must use to make sense for the chain.
+    pub required: &'static [&'static str],
+}
+
+/// Every chain whose prompt can be overridden.
+pub const CHAIN_PROMPTS: [&ChainPrompt; 14] = [
+    &EXPLAIN_PROMPT,
+    &EXPLAIN_TREE_PROMPT,
+    &EXPLAIN_SCRIPT_PROMPT,
+    &RECOMMEND_PROMPT,
+    &RECOMMEND_GIT_PROMPT,
+    &RECOMMEND_GITHUB_PROMPT,
+    &REVISE_COMMAND_PROMPT,
+    &FIX_COMMAND_PROMPT,
+    &CHAT_PROMPT,
+    &GIT_COMMIT_PROMPT,
+    &GIT_COMMIT_WITH_CONTEXT_PROMPT,
+    &GIT_COMMIT_FULL_PROMPT,
+    &REVISE_COMMIT_PROMPT,
+    &SUMMARIZE_DIFF_PROMPT,
+];
+
+pub fn find_chain_prompt(name: &str) -> Option<&'static ChainPrompt> {
+    CHAIN_PROMPTS.iter().find(|p| p.name == name).copied()
+}
+
+impl ChainPrompt {
+    /// Checks a user template against the variables of the chain and returns it with
+    /// `{{ var }}` normalized to `{{var}}`, the only form the template engine fills in.
+    pub fn validate(&self, template: &str) -> Result<String, String> {
+        let re = Regex::new(r"\{\{\s*([^{}]*?)\s*\}\}").unwrap();
+        let mut used = Vec::new();
+        for cap in re.captures_iter(template) {
+            let variable = cap[1].to_string();
+            if !self.variables.contains(&variable.as_str()) {
+                return Err(format!(
+                    "Prompt '{}' uses unknown variable '{{{{{}}}}}', available variables: {}",
+                    self.name,
+                    variable,
+                    self.variables.join(", ")
+                ));
+            }
+            used.push(variable);
+        }
+        if let Some(missing) = self.required.iter().find(|r| !used.iter().any(|u| u == *r)) {
+            return Err(format!(
+                "Prompt '{}' must use the variable '{{{{{}}}}}'",
+                self.name, missing
+            ));
+        }
+        Ok(re.replace_all(template, "{{$1}}").to_string())
+    }
+}
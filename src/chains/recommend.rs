@@ -5,8 +5,13 @@ use langchain_rust::{
     template_jinja2,
 };
 
+use super::ChainPrompt;
+
 const PROMPT: &str = r#"Recommend a terminal command for {{os}} to : {{command}}.
-You sould just return the command or command, nothing more.
+The command will run in this environment:
+{{environment}}
+Use the syntax of that shell and only the tools available there.
+You should just return the command or commands, nothing more.
 Example1:
 ls
 
@@ -18,19 +23,32 @@ git remote add origin <repository_url>
 git push -u origin main
 "#;
 
-/// This function creates a chain that explains a command.
-pub fn recomend_command_chain<VALUE: LLM + 'static>(llm: VALUE) -> LLMChain {
+pub const RECOMMEND_PROMPT: ChainPrompt = ChainPrompt {
+    name: "recommend",
+    description: "Suggests a shell command",
+    template: PROMPT,
+    variables: &["os", "environment", "command"],
+    required: &["command"],
+};
+
+/// This function creates a chain that recommends a shell command.
+pub fn recomend_command_chain<VALUE: LLM + 'static>(llm: VALUE, prompt: Option<&str>) -> LLMChain {
     LLMChainBuilder::new()
         .llm(llm)
         .prompt(HumanMessagePromptTemplate::new(template_jinja2!(
-            PROMPT, "os", "command"
+            prompt.unwrap_or(PROMPT),
+            "os",
+            "environment",
+            "command"
         )))
         .build()
         .unwrap() //safe to unwrap
 }
 
 const PROMPT_GIT: &str = r#"Recommend a git command or commands to : {{command}}.
-You sould just return the command or command, nothing more, give all the necesary command to acomplish.
+The commands will run in this environment:
+{{environment}}
+You should just return the command or commands, nothing more, give all the necessary commands to accomplish it.
 Example:
 git init 
 git add .
@@ -39,30 +57,59 @@ git remote add origin <repository_url>
 git push -u origin main
 "#;
 
-/// This function creates a chain that explains a command.
-pub fn recomend_command_git_chain<VALUE: LLM + 'static>(llm: VALUE) -> LLMChain {
+pub const RECOMMEND_GIT_PROMPT: ChainPrompt = ChainPrompt {
+    name: "recommend_git",
+    description: "Suggests git commands",
+    template: PROMPT_GIT,
+    variables: &["os", "environment", "command"],
+    required: &["command"],
+};
+
+/// This function creates a chain that recommends git commands.
+pub fn recomend_command_git_chain<VALUE: LLM + 'static>(
+    llm: VALUE,
+    prompt: Option<&str>,
+) -> LLMChain {
     LLMChainBuilder::new()
         .llm(llm)
         .prompt(HumanMessagePromptTemplate::new(template_jinja2!(
-            PROMPT_GIT, "command"
+            prompt.unwrap_or(PROMPT_GIT),
+            "os",
+            "environment",
+            "command"
         )))
         .build()
         .unwrap() //safe to unwrap
 }
 
 const PROMPT_GITHUB: &str = r#"Recommend a github cli command or commands to : {{command}}.
-You sould just return the command or command, nothing more, give all the necesary command to acomplish.
+The commands will run in this environment:
+{{environment}}
+You should just return the command or commands, nothing more, give all the necessary commands to accomplish it.
 
 Example:
 gh issue list
 "#;
 
-/// This function creates a chain that explains a command.
-pub fn recomend_command_github_chain<VALUE: LLM + 'static>(llm: VALUE) -> LLMChain {
+pub const RECOMMEND_GITHUB_PROMPT: ChainPrompt = ChainPrompt {
+    name: "recommend_github",
+    description: "Suggests GitHub CLI commands",
+    template: PROMPT_GITHUB,
+    variables: &["os", "environment", "command"],
+    required: &["command"],
+};
+
+/// This function creates a chain that recommends GitHub CLI commands.
+pub fn recomend_command_github_chain<VALUE: LLM + 'static>(
+    llm: VALUE,
+    prompt: Option<&str>,
+) -> LLMChain {
     LLMChainBuilder::new()
         .llm(llm)
         .prompt(HumanMessagePromptTemplate::new(template_jinja2!(
-            PROMPT_GITHUB,
+            prompt.unwrap_or(PROMPT_GITHUB),
+            "os",
+            "environment",
             "command"
         )))
         .build()
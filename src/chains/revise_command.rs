@@ -5,17 +5,73 @@ use langchain_rust::{
     template_jinja2,
 };
 
+use super::ChainPrompt;
+
 const PROMPT: &str = r#"
-Giving this command:{{command}}, modify it to {{to}}.
-You sould just return the command, nothing more.
+Given this command: {{command}}, modify it to {{to}}.
+You should just return the command, nothing more.
+"#;
+
+pub const REVISE_COMMAND_PROMPT: ChainPrompt = ChainPrompt {
+    name: "revise_command",
+    description: "Revises a suggested command as the user asks",
+    template: PROMPT,
+    variables: &["to", "command"],
+    required: &["to", "command"],
+};
+
+/// This function creates a chain that revises a command.
+pub fn revise_command_chain<VALUE: LLM + 'static>(llm: VALUE, prompt: Option<&str>) -> LLMChain {
+    LLMChainBuilder::new()
+        .llm(llm)
+        .prompt(HumanMessagePromptTemplate::new(template_jinja2!(
+            prompt.unwrap_or(PROMPT),
+            "to",
+            "command"
+        )))
+        .build()
+        .unwrap() //safe to unwrap
+}
+
+const PROMPT_FIX: &str = r#"This {{os}} command failed: {{command}}
+The command ran in this environment:
+{{environment}}
+Its exit status: {{status}}
+Its error output:
+{{error}}
+Explain why the command failed using a few bullet points, in markdown format.
+Then give the corrected command alone in a ```sh code block, with nothing after it.
+
+Example Command: grep -r TODO src --include *.rs
+
+Example Result
+• `*.rs` is expanded by the shell before `grep` runs, so `--include` gets the names of the files in the current directory instead of the pattern.
+• Quote the pattern so `grep` receives it as is.
+
+```sh
+grep -r TODO src --include '*.rs'
+```
 "#;
 
-/// This function creates a chain that explains a command.
-pub fn revise_command_chain<VALUE: LLM + 'static>(llm: VALUE) -> LLMChain {
+pub const FIX_COMMAND_PROMPT: ChainPrompt = ChainPrompt {
+    name: "fix_command",
+    description: "Diagnoses a failed command and corrects it",
+    template: PROMPT_FIX,
+    variables: &["os", "environment", "command", "status", "error"],
+    required: &["command"],
+};
+
+/// This function creates a chain that diagnoses a failed command and corrects it.
+pub fn fix_command_chain<VALUE: LLM + 'static>(llm: VALUE, prompt: Option<&str>) -> LLMChain {
     LLMChainBuilder::new()
         .llm(llm)
         .prompt(HumanMessagePromptTemplate::new(template_jinja2!(
-            PROMPT, "to", "command"
+            prompt.unwrap_or(PROMPT_FIX),
+            "os",
+            "environment",
+            "command",
+            "status",
+            "error"
         )))
         .build()
         .unwrap() //safe to unwrap
@@ -1,17 +1,32 @@
-use std::process::Command;
+use std::fs;
 
 use async_recursion::async_recursion;
 use clap::{App, Arg};
 use clipboard::{ClipboardContext, ClipboardProvider};
-use dialoguer::{console::style, theme::ColorfulTheme, Input, Select};
-use langchain_rust::{chain::Chain, prompt_args};
+use dialoguer::{console::style, theme::ColorfulTheme, Confirm, Input, Select};
+use langchain_rust::prompt_args;
 
 use crate::{
     chains::{
-        explain_command_chain, recomend_command_chain, recomend_command_git_chain,
-        recomend_command_github_chain, revise_command_chain, revise_commit_chain,
+        revise_command_chain, revise_commit_chain, CHAIN_PROMPTS, REVISE_COMMAND_PROMPT,
+        REVISE_COMMIT_PROMPT,
+    },
+    commands::{
+        explain_command, generate_suggestion, past_suggestion, verify_suggestion, SuggestionKind,
+    },
+    error::CopilotError,
+    util::{
+        commit::finish_commit_message,
+        git,
+        history::{self, Action},
+        output::Answer,
+        placeholder::{detect_placeholders, substitute},
+        plan::Plan,
+        safety::{analyze, RiskLevel},
+        shared::SharedState,
+        stream::{invoke_chain, run_chain, show_chain},
+        style::CommitStyle,
     },
-    util::shared::{apply_styles_to_backticks, SharedState},
 };
 
 pub fn init_clap() -> clap::ArgMatches {
@@ -19,22 +34,197 @@ pub fn init_clap() -> clap::ArgMatches {
         .version("0.1.0")
         .author("Luis Fernando luisfmiranda8@gmail.com")
         .about("GitHub Copilot Clone")
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .short('f')
+                .help("Output format, json and raw imply --no-interactive")
+                .takes_value(true)
+                .possible_values(["text", "json", "raw"])
+                .default_value("text")
+                .global(true),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .short('p')
+                .help("The config profile to use instead of the default one")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("no-stream")
+                .long("no-stream")
+                .help("Print answers once they are complete instead of token by token")
+                .global(true),
+        )
+        .arg(
+            Arg::new("no-cache")
+                .long("no-cache")
+                .help("Ask the provider again instead of reusing a cached answer")
+                .global(true),
+        )
+        .arg(
+            Arg::new("no-interactive")
+                .long("no-interactive")
+                .help("Print the result and exit without showing any menu")
+                .global(true),
+        )
         .subcommand(
             clap::Command::new("explain")
                 .about("Exmplain a command")
                 .arg(
                     Arg::new("command")
-                        .help("The command to explain")
-                        .required(true)
+                        .help("The command to explain, or - to explain a script read from stdin")
+                        .required_unless_present("file")
+                        .conflicts_with("file")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("file")
+                        .long("file")
+                        .help("A script, Makefile, Dockerfile or CI configuration to explain")
                         .takes_value(true),
                 ),
         ) // Aquí se usan los nuevos métodos.
         .subcommand(
             clap::Command::new("suggest")
                 .about("Recommend a command")
-                .arg(Arg::new("input").help("The input to suggest a command for")),
+                .arg(Arg::new("input").help("The input to suggest a command for"))
+                .arg(
+                    Arg::new("type")
+                        .long("type")
+                        .short('t')
+                        .help("The kind of command to suggest")
+                        .takes_value(true)
+                        .possible_values(["shell", "git", "gh"])
+                        .default_value("shell"),
+                )
+                .arg(
+                    Arg::new("installed-only")
+                        .long("installed-only")
+                        .help("Regenerate suggestions that use programs which are not installed"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("fix")
+                .about("Diagnose a failed command and suggest a fix")
+                .arg(
+                    Arg::new("command")
+                        .help("The failed command, the last one of the shell with ghcf"),
+                )
+                .arg(
+                    Arg::new("exit-code")
+                        .long("exit-code")
+                        .help("The exit status of the failed command")
+                        .takes_value(true)
+                        .allow_hyphen_values(true),
+                )
+                .arg(
+                    Arg::new("stderr")
+                        .long("stderr")
+                        .help("A file with the error output of the command, or - for stdin")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("rerun")
+                        .long("rerun")
+                        .help("Run a low risk command again to capture its error output"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("chat")
+                .about("Talk with the model in a conversation it remembers")
+                .alias("repl")
+                .arg(
+                    Arg::new("session")
+                        .long("session")
+                        .short('s')
+                        .help("Resume the session saved with this name, and keep saving it")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("config")
+                .about("Configure your LLM provider")
+                .arg(
+                    Arg::new("for")
+                        .long("for")
+                        .help("Subcommands that should use this profile, e.g. explain commit")
+                        .takes_value(true)
+                        .multiple_values(true)
+                        .possible_values(["explain", "suggest", "fix", "chat", "commit"]),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("prompts")
+                .about("Manage the prompt templates of every chain")
+                .subcommand_required(true)
+                .subcommand(clap::Command::new("list").about("List the prompts and their source"))
+                .subcommand(
+                    clap::Command::new("show")
+                        .about("Show the template used for a prompt")
+                        .arg(prompt_name_arg()),
+                )
+                .subcommand(
+                    clap::Command::new("edit")
+                        .about("Edit a prompt in $EDITOR")
+                        .arg(prompt_name_arg()),
+                )
+                .subcommand(
+                    clap::Command::new("reset")
+                        .about("Remove the prompt file and go back to the built-in prompt")
+                        .arg(prompt_name_arg()),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("history")
+                .about("Browse the answers generated before")
+                .subcommand_required(true)
+                .subcommand(
+                    clap::Command::new("list")
+                        .about("List the latest answers")
+                        .arg(history_limit_arg()),
+                )
+                .subcommand(
+                    clap::Command::new("search")
+                        .about("Find answers by their request or content")
+                        .arg(
+                            Arg::new("query")
+                                .help("The text to look for")
+                                .required(true),
+                        )
+                        .arg(history_limit_arg()),
+                )
+                .subcommand(
+                    clap::Command::new("show")
+                        .about("Show an answer and what was done with it")
+                        .arg(history_id_arg()),
+                )
+                .subcommand(
+                    clap::Command::new("rerun")
+                        .about("Run a suggested command again")
+                        .arg(history_id_arg()),
+                )
+                .subcommand(clap::Command::new("clear").about("Delete the whole history")),
+        )
+        .subcommand(
+            clap::Command::new("cache")
+                .about("Inspect or empty the cache of answers")
+                .subcommand_required(true)
+                .subcommand(clap::Command::new("stats").about("Show what the cache holds"))
+                .subcommand(clap::Command::new("clear").about("Delete every cached answer")),
+        )
+        .subcommand(
+            clap::Command::new("shell-init")
+                .about("Print shell functions that run accepted suggestions in your shell")
+                .arg(
+                    Arg::new("shell")
+                        .help("The shell to integrate with")
+                        .required(true)
+                        .possible_values(["bash", "zsh", "fish"]),
+                ),
         )
-        .subcommand(clap::Command::new("config").about("Configure your LLM provider"))
         .subcommand(
             clap::Command::new("commit")
                 .about("Auto Commit Message")
@@ -54,13 +244,54 @@ pub fn init_clap() -> clap::ArgMatches {
                         .help("Optional context for the commit")
                         .takes_value(true)
                         .required(false),
+                )
+                .arg(
+                    Arg::new("full")
+                        .long("full")
+                        .help("Write a body explaining the why, and footers, after the subject"),
+                )
+                .arg(
+                    Arg::new("refs")
+                        .long("refs")
+                        .help("Issues or tickets for a `Refs:` footer, e.g. #123")
+                        .takes_value(true)
+                        .multiple_occurrences(true),
+                )
+                .arg(
+                    Arg::new("co-author")
+                        .long("co-author")
+                        .help("A `Co-authored-by:` footer, e.g. \"Jane Doe <jane@example.com>\"")
+                        .takes_value(true)
+                        .multiple_occurrences(true),
                 ),
         )
         .get_matches()
 }
 
-pub async fn choose_sugestion_options(shared_state: &SharedState) {
-    let opciones = vec![
+fn history_id_arg() -> Arg<'static> {
+    Arg::new("id")
+        .help("The id of the entry, see `history list`")
+        .required(true)
+}
+
+fn history_limit_arg() -> Arg<'static> {
+    Arg::new("limit")
+        .long("limit")
+        .short('n')
+        .help("How many entries to show")
+        .takes_value(true)
+        .default_value("20")
+}
+
+fn prompt_name_arg() -> Arg<'static> {
+    Arg::new("name")
+        .help("The chain the prompt belongs to")
+        .required(true)
+        .possible_values(CHAIN_PROMPTS.iter().map(|p| p.name))
+}
+
+pub async fn choose_sugestion_options(shared_state: &SharedState) -> Result<(), CopilotError> {
+    let opciones = [
         "Generic Shell Command",
         "Git Command",
         "GitHub Command",
@@ -76,92 +307,266 @@ pub async fn choose_sugestion_options(shared_state: &SharedState) {
         .with_prompt(&prompt)
         .default(0)
         .items(&opciones[..])
-        .interact()
-        .unwrap();
+        .interact_opt()?
+        .ok_or(CopilotError::Cancelled)?;
 
-    match seleccion {
-        0 => {
-            println!(
-                "\n{}\n",
-                style("What would you like the command to do?")
-                    .white()
-                    .bold()
-                    .bright()
-                    .to_string(),
-            );
-            let command = Input::<String>::new()
-                .with_prompt(style("> ").blue().bold().to_string())
-                .interact()
-                .unwrap();
-            let suggest_chain = recomend_command_chain(shared_state.llm());
-            let suggestion = suggest_chain
-                .invoke(prompt_args! {
-                    "os"=>shared_state.os(),
-                    "command"=>command
-                })
-                .await
-                .unwrap();
-            println!("Sugestion:\n");
-            println!("{}\n", style(suggestion.clone()).yellow().bold());
-            choose_options(shared_state, &suggestion).await
+    let (kind, question) = match seleccion {
+        0 => (
+            SuggestionKind::Shell,
+            "What would you like the command to do?",
+        ),
+        1 => (
+            SuggestionKind::Git,
+            "What would you like the git command to do?",
+        ),
+        2 => (
+            SuggestionKind::GitHub,
+            "What would you like the GitHub command to do?",
+        ),
+        3 => return Ok(()),
+        _ => {
+            eprintln!("Invalid option.");
+            return Ok(());
         }
-        1 => {
-            println!(
-                "\n{}\n",
-                style("What would you like the git command to do?")
-                    .white()
-                    .bold()
-                    .bright()
-                    .to_string(),
-            );
-            let git_command = Input::<String>::new()
-                .with_prompt(style("> ").blue().bold().to_string())
-                .interact()
-                .unwrap();
-            let suggest_chain = recomend_command_git_chain(shared_state.llm());
-            let suggestion = suggest_chain
-                .invoke(prompt_args! {
-                    "command"=>git_command
-                })
-                .await
-                .unwrap();
-            println!("Sugestion:\n");
-            println!("{}\n", style(suggestion.clone()).yellow().bold());
-            choose_options(shared_state, &suggestion).await
+    };
+
+    println!("\n{}\n", style(question).white().bold().bright());
+    let command = Input::<String>::new()
+        .with_prompt(style("> ").blue().bold().to_string())
+        .interact()?;
+    if let Some(past) = past_suggestion(shared_state, kind, &command)? {
+        return choose_options(shared_state, &past).await;
+    }
+    let suggestion = generate_suggestion(shared_state, kind, &command).await?;
+    if !shared_state.streaming() {
+        Answer::Suggestion.print(&suggestion);
+    }
+    let suggestion = verify_suggestion(shared_state, &suggestion, false).await?;
+    history::record(
+        shared_state,
+        "suggest",
+        kind.chain().name,
+        Some(&command),
+        &suggestion,
+    );
+    choose_options(shared_state, &suggestion).await
+}
+
+/// How the commands of a suggestion were run.
+enum Execution {
+    NotRun,
+    /// Handed to the shell wrapper, which runs them after we exit.
+    InShell,
+    Ran(Option<i32>),
+}
+
+/// Fills in the placeholders of a suggestion and runs its commands, recording the exit
+/// status in the history. Returns false when nothing was run.
+pub fn execute_plan(shared_state: &SharedState, input: &str) -> Result<bool, CopilotError> {
+    let (filled, kept) = fill_placeholders(input)?;
+    let exit_status = match run_plan(shared_state, &filled, &kept)? {
+        Execution::NotRun => return Ok(false),
+        Execution::InShell => None,
+        Execution::Ran(status) => status,
+    };
+    history::mark(input, Action::Executed, exit_status);
+    Ok(true)
+}
+
+/// Runs the commands of a suggestion, all at once or one at a time, stopping on the first
+/// failure.
+fn run_plan(
+    shared_state: &SharedState,
+    input: &str,
+    kept: &[String],
+) -> Result<Execution, CopilotError> {
+    if let Some(path) = shared_state.shell_output() {
+        if !confirm_execution(shared_state, input)? {
+            return Ok(Execution::NotRun);
         }
-        2 => {
-            println!(
-                "\n{}\n",
-                style("What would you like the GitHub command to do?")
-                    .white()
-                    .bold()
-                    .bright()
-                    .to_string(),
-            );
-            let github_command = Input::<String>::new()
-                .with_prompt(style("> ").blue().bold().to_string())
-                .interact()
-                .unwrap();
-            let suggest_chain = recomend_command_github_chain(shared_state.llm());
-            let suggestion = suggest_chain
-                .invoke(prompt_args! {
-                    "command"=>github_command
-                })
-                .await
-                .unwrap();
-            println!("Sugestion:\n");
-            println!("{}\n", style(suggestion.clone()).yellow().bold());
-            choose_options(shared_state, &suggestion).await
+        // The shell wrapper runs it, so `cd` and `export` apply to the user's shell.
+        fs::write(path, input)?;
+        return Ok(Execution::InShell);
+    }
+
+    let mut plan = Plan::parse(input);
+    plan.keep(kept);
+    if plan.is_empty() {
+        return Ok(Execution::NotRun);
+    }
+    if plan.len() == 1 {
+        if !confirm_execution(shared_state, input)? {
+            return Ok(Execution::NotRun);
         }
-        3 => std::process::exit(0),
+        plan.run(0)?;
+        plan.print_report();
+        return Ok(Execution::Ran(plan.exit_status()));
+    }
 
-        _ => eprintln!("Invalid option."),
+    let opciones = ["Run all", "Step through", "Edit a step", "Cancel"];
+    loop {
+        plan.print();
+        let seleccion = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Run the plan")
+            .default(0)
+            .items(&opciones[..])
+            .interact_opt()?
+            .ok_or(CopilotError::Cancelled)?;
+        match seleccion {
+            0 => {
+                if !confirm_execution(shared_state, &plan.commands())? {
+                    return Ok(Execution::NotRun);
+                }
+                for i in 0..plan.len() {
+                    if !plan.run(i)? {
+                        break;
+                    }
+                }
+                break;
+            }
+            1 => {
+                step_through(shared_state, &mut plan)?;
+                break;
+            }
+            2 => {
+                let commands = plan
+                    .steps
+                    .iter()
+                    .enumerate()
+                    .map(|(i, s)| format!("{}. {}", i + 1, s.command))
+                    .collect::<Vec<String>>();
+                let Some(index) = Select::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Step to edit")
+                    .default(0)
+                    .items(&commands)
+                    .interact_opt()?
+                else {
+                    continue;
+                };
+                edit_step(&mut plan, index)?;
+            }
+            _ => return Ok(Execution::NotRun),
+        }
+    }
+    plan.print_report();
+    Ok(Execution::Ran(plan.exit_status()))
+}
+
+/// Asks what to do with every step before running it.
+fn step_through(shared_state: &SharedState, plan: &mut Plan) -> Result<(), CopilotError> {
+    let opciones = ["Run", "Skip", "Edit", "Stop"];
+    let mut i = 0;
+    while i < plan.len() {
+        let seleccion = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!(
+                "Step {}/{}: {}",
+                i + 1,
+                plan.len(),
+                style(&plan.steps[i].command).yellow().bold()
+            ))
+            .default(0)
+            .items(&opciones[..])
+            .interact_opt()?
+            .ok_or(CopilotError::Cancelled)?;
+        match seleccion {
+            0 if !confirm_execution(shared_state, &plan.steps[i].command)? => plan.skip(i),
+            0 => {
+                if !plan.run(i)? {
+                    return Ok(());
+                }
+            }
+            1 => plan.skip(i),
+            2 => {
+                edit_step(plan, i)?;
+                continue;
+            }
+            _ => return Ok(()),
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+/// Edits a step, asking for the placeholders the new command has.
+fn edit_step(plan: &mut Plan, index: usize) -> Result<(), CopilotError> {
+    let command: String = Input::new()
+        .with_prompt(style("> ").blue().bold().to_string())
+        .with_initial_text(plan.steps[index].command.clone())
+        .interact_text()?;
+    let (command, kept) = fill_placeholders(&command)?;
+    plan.keep(&kept);
+    plan.edit(index, &command);
+    Ok(())
+}
+
+/// Asks for the value of every placeholder of `input`, suggesting one from the
+/// environment, and returns the command with the values filled in along with the tokens
+/// left empty, which are kept as written since they are not placeholders.
+fn fill_placeholders(input: &str) -> Result<(String, Vec<String>), CopilotError> {
+    let placeholders = detect_placeholders(input);
+    if placeholders.is_empty() {
+        return Ok((input.to_string(), Vec::new()));
+    }
+
+    println!(
+        "\n{}\n{}\n",
+        style("Fill in the placeholders:").white().bold(),
+        style("Leave one empty to keep it as written.").dim()
+    );
+    let mut values = Vec::new();
+    let mut kept = Vec::new();
+    for placeholder in placeholders {
+        let mut prompt = Input::<String>::new();
+        prompt
+            .with_prompt(style(&placeholder.token).yellow().to_string())
+            .allow_empty(true);
+        if let Some(default) = placeholder.default() {
+            prompt.with_initial_text(default);
+        }
+        let value = prompt.interact_text()?;
+        match value.trim().is_empty() {
+            true => kept.push(placeholder.token),
+            false => values.push((placeholder.token, value)),
+        }
     }
+    let filled = substitute(input, &values);
+    println!();
+    Answer::Suggestion.print(&filled);
+    Ok((filled, kept))
+}
+
+/// Shows the risk of running `input` and asks before running anything but low risk
+/// commands. High risk commands must be confirmed by typing `yes`.
+fn confirm_execution(shared_state: &SharedState, input: &str) -> Result<bool, CopilotError> {
+    let risk = analyze(input, shared_state.safety());
+    if risk.level == RiskLevel::Low {
+        return Ok(true);
+    }
+    risk.print();
+    if risk.level == RiskLevel::Medium {
+        return Ok(Confirm::new()
+            .with_prompt("Run it anyway?")
+            .default(false)
+            .interact()?);
+    }
+    let typed: String = Input::new()
+        .with_prompt(format!("Type {} to run it", style("yes").red().bold()))
+        .allow_empty(true)
+        .interact_text()?;
+    Ok(typed.trim() == "yes")
+}
+
+fn copy_to_clipboard(input: &str) -> Result<(), CopilotError> {
+    let unavailable = |e: Box<dyn std::error::Error>| {
+        CopilotError::Other(format!("Clipboard unavailable: {}", e))
+    };
+    let mut ctx: ClipboardContext = ClipboardProvider::new().map_err(unavailable)?;
+    ctx.set_contents(input.into()).map_err(unavailable)
 }
 
 #[async_recursion]
-pub async fn choose_options(shared_state: &SharedState, input: &str) {
-    let opciones = vec![
+pub async fn choose_options(shared_state: &SharedState, input: &str) -> Result<(), CopilotError> {
+    let opciones = [
         "Copy to clipboard",
         "Execute Command",
         "Explain Command",
@@ -178,57 +583,71 @@ pub async fn choose_options(shared_state: &SharedState, input: &str) {
         .with_prompt(&prompt)
         .default(0)
         .items(&opciones[..])
-        .interact()
-        .unwrap();
+        .interact_opt()?
+        .ok_or(CopilotError::Cancelled)?;
 
     match seleccion {
         0 => {
-            let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-            ctx.set_contents(input.into()).unwrap();
+            copy_to_clipboard(&fill_placeholders(input)?.0)?;
+            history::mark(input, Action::Copied, None);
+            Ok(())
         }
-        1 => match Command::new("sh").arg("-c").arg(input).status() {
-            Ok(status) if status.success() => println!("Command executed successfully."),
-            _ => eprintln!("Error executing command."),
-        },
+        1 if !execute_plan(shared_state, input)? => {
+            println!("Not executed.");
+            choose_options(shared_state, input).await
+        }
+        1 => Ok(()),
         2 => {
-            let explain_chian = explain_command_chain(shared_state.llm());
-            let explanation = explain_chian
-                .invoke(prompt_args! {
-                    "os"=>shared_state.os(),
-                    "command"=>input
-                })
-                .await
-                .unwrap();
-            println!("Explanation:\n");
-            println!("{}\n", apply_styles_to_backticks(&explanation));
-            choose_options(shared_state, &input).await
+            explain_command(shared_state, input).await?;
+            choose_options(shared_state, input).await
         }
         3 => {
             let revised = Input::<String>::new()
                 .with_prompt(style("> ").blue().bold().to_string())
-                .interact()
-                .unwrap();
-            let revise_chain = revise_command_chain(shared_state.llm());
-            let revised_command = revise_chain
-                .invoke(prompt_args! {
+                .interact()?;
+            let revise_chain = revise_command_chain(
+                shared_state.llm(),
+                shared_state.prompt(REVISE_COMMAND_PROMPT.name),
+            );
+            let revised_command = show_chain(
+                shared_state,
+                &revise_chain,
+                prompt_args! {
                     "to"=>revised,
                     "command"=>input
-                })
-                .await
-                .unwrap();
-            println!("Sugestion:\n");
-            println!("{}\n", style(revised_command.clone()).yellow().bold());
+                },
+                Answer::Suggestion,
+            )
+            .await?;
+            let revised_command = verify_suggestion(shared_state, &revised_command, false).await?;
+            history::record(
+                shared_state,
+                "revise",
+                REVISE_COMMAND_PROMPT.name,
+                Some(&revised),
+                &revised_command,
+            );
             choose_options(shared_state, &revised_command).await
         }
-        4 => std::process::exit(0),
+        4 => Ok(()),
 
-        _ => eprintln!("Invalid option."),
+        _ => {
+            eprintln!("Invalid option.");
+            Ok(())
+        }
     }
 }
 
 #[async_recursion]
-pub async fn choose_options_for_commit(shared_state: &SharedState, input: &str) {
-    let opciones = vec![
+/// The menu for a generated commit message. Revised messages are held to the `commit_style`
+/// of the repository and `footers` are added back to them.
+pub async fn choose_options_for_commit(
+    shared_state: &SharedState,
+    input: &str,
+    commit_style: &CommitStyle,
+    footers: &[String],
+) -> Result<(), CopilotError> {
+    let opciones = [
         "Copy to clipboard",
         "Commit message",
         "Revise  Command",
@@ -244,41 +663,61 @@ pub async fn choose_options_for_commit(shared_state: &SharedState, input: &str)
         .with_prompt(&prompt)
         .default(0)
         .items(&opciones[..])
-        .interact()
-        .unwrap();
+        .interact_opt()?
+        .ok_or(CopilotError::Cancelled)?;
 
     match seleccion {
         0 => {
-            let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-            ctx.set_contents(input.into()).unwrap();
+            copy_to_clipboard(input)?;
+            history::mark(input, Action::Copied, None);
+            Ok(())
+        }
+        1 => {
+            let status = git::commit(input)?;
+            history::mark(input, Action::Executed, status.code());
+            if !status.success() {
+                return Err(CopilotError::Git(format!("git commit failed ({})", status)));
+            }
+            println!("Command executed successfully.");
+            Ok(())
         }
-        1 => match Command::new("sh")
-            .arg("-c")
-            .arg(format!(r#"git commit -m "{}""#, input))
-            .status()
-        {
-            Ok(status) if status.success() => println!("Command executed successfully."),
-            _ => eprintln!("Error executing command."),
-        },
         2 => {
             let revised = Input::<String>::new()
                 .with_prompt(style("> ").blue().bold().to_string())
-                .interact()
-                .unwrap();
-            let revise_chain = revise_commit_chain(shared_state.llm());
-            let revised_command = revise_chain
-                .invoke(prompt_args! {
-                    "to"=>revised,
-                    "commit"=>input
-                })
-                .await
-                .unwrap();
-            println!("Sugestion:\n");
-            println!("{}\n", style(revised_command.clone()).yellow().bold());
-            choose_options_for_commit(shared_state, &revised_command).await
+                .interact()?;
+            let revise_chain = revise_commit_chain(
+                shared_state.llm(),
+                shared_state.prompt(REVISE_COMMIT_PROMPT.name),
+            );
+            let args = prompt_args! {
+                "to"=>revised,
+                "commit"=>input
+            };
+            // Full messages are reformatted, so they are shown once they are complete.
+            let streamed = footers.is_empty() && !input.trim().contains('\n');
+            let answer = match streamed {
+                true => run_chain(shared_state, &revise_chain, args, Answer::Suggestion).await?,
+                false => invoke_chain(shared_state, &revise_chain, args).await?,
+            };
+            let revised_command =
+                finish_commit_message(shared_state, &answer, commit_style, footers).await?;
+            if !(streamed && shared_state.streaming()) || revised_command != answer.trim() {
+                Answer::Suggestion.print(&revised_command);
+            }
+            history::record(
+                shared_state,
+                "commit",
+                REVISE_COMMIT_PROMPT.name,
+                Some(&revised),
+                &revised_command,
+            );
+            choose_options_for_commit(shared_state, &revised_command, commit_style, footers).await
         }
-        3 => std::process::exit(0),
+        3 => Ok(()),
 
-        _ => eprintln!("Invalid option."),
+        _ => {
+            eprintln!("Invalid option.");
+            Ok(())
+        }
     }
 }
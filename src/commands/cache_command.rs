@@ -0,0 +1,62 @@
+use dialoguer::console::style;
+
+use crate::{
+    error::CopilotError,
+    util::{
+        cache::{self, CacheStats},
+        output::OutputFormat,
+        shared::SharedState,
+    },
+};
+
+/// Shows what the cache of answers holds, or empties it.
+pub fn cache_command(shared_state: &SharedState, action: &str) -> Result<(), CopilotError> {
+    match action {
+        "stats" => print_stats(shared_state, &cache::stats(shared_state.cache())),
+        "clear" => {
+            let count = cache::clear()?;
+            if shared_state.format() == OutputFormat::Text {
+                println!("Deleted {} cached answers.", count);
+            }
+        }
+        _ => {
+            return Err(CopilotError::Usage(format!(
+                "Unknown cache action '{}'",
+                action
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn print_stats(shared_state: &SharedState, stats: &CacheStats) {
+    match shared_state.format() {
+        OutputFormat::Text => {
+            let field = |name: &str, value: String| {
+                println!("{:<10} {}", style(name).dim(), value);
+            };
+            field(
+                "enabled",
+                match shared_state.cache().enabled() {
+                    true => "yes".to_string(),
+                    false => "no, disabled in [cache]".to_string(),
+                },
+            );
+            field("path", stats.path.display().to_string());
+            field(
+                "answers",
+                format!("{} ({} expired)", stats.entries, stats.expired),
+            );
+            field("size", format!("{:.1} KB", stats.bytes as f64 / 1024.0));
+            field("hits", stats.hits.to_string());
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(stats).unwrap() //safe to unwrap
+        ),
+        OutputFormat::Raw => println!(
+            "{}\t{}\t{}\t{}",
+            stats.entries, stats.expired, stats.bytes, stats.hits
+        ),
+    }
+}
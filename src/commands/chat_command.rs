@@ -0,0 +1,203 @@
+use std::io;
+
+use dialoguer::{console::style, Input};
+use langchain_rust::prompt_args;
+
+use crate::{
+    chains::{chat_chain, CHAT_PROMPT},
+    cli::{choose_options, execute_plan},
+    commands::{explain_command, git_commit_command, verify_suggestion},
+    error::CopilotError,
+    util::{
+        history,
+        output::Answer,
+        session::{session_file, Session},
+        shared::{split_code_block, SharedState},
+        stream::invoke_chain,
+    },
+};
+
+const CHAT_HELP: &str = "\
+/explain [command]   Explain a command, the last suggested one by default
+/suggest <request>   Suggest a command, knowing the conversation so far
+/run [command]       Run a command, the last suggested one by default
+/commit [context]    Write a commit message for the staged changes
+/save [name]         Save the session, to resume it with `chat --session <name>`
+/reset               Forget the conversation
+/help                Show this help
+/exit                Leave the chat";
+
+/// A multi-turn conversation with the model that remembers what was said, with slash
+/// commands to explain, suggest, run and commit. A named session is resumed when it was
+/// saved before, and saved again after every message.
+pub async fn chat_command(
+    shared_state: &SharedState,
+    session: Option<&str>,
+) -> Result<(), CopilotError> {
+    if !shared_state.interactive() {
+        return Err(CopilotError::Usage(
+            "The chat needs an interactive terminal".to_string(),
+        ));
+    }
+    let mut session = match session {
+        Some(name) => Session::load(name)?,
+        None => Session::default(),
+    };
+    match (&session.name, session.is_empty()) {
+        (Some(name), false) => println!(
+            "Resumed session {} with {} messages.\n",
+            style(name).bold(),
+            session.len()
+        ),
+        _ => println!("Type a message, or /help for the commands.\n"),
+    }
+
+    loop {
+        let line = match Input::<String>::new()
+            .with_prompt(style(">").blue().bold().to_string())
+            .allow_empty(true)
+            .interact_text()
+        {
+            Ok(line) => line,
+            // The end of the input leaves the chat.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        };
+        match chat_line(shared_state, &mut session, line.trim()).await {
+            Ok(true) => {}
+            Ok(false) => break,
+            // Leaving a menu goes back to the chat.
+            Err(CopilotError::Cancelled) => {}
+            Err(e) => eprintln!("{} {}\n", style("Error:").red().bold(), e),
+        }
+        if session.name.is_some() {
+            session.save()?;
+        }
+    }
+    Ok(())
+}
+
+/// Handles a line typed in the chat. Returns false when the user leaves.
+async fn chat_line(
+    shared_state: &SharedState,
+    session: &mut Session,
+    line: &str,
+) -> Result<bool, CopilotError> {
+    let Some(command) = line.strip_prefix('/') else {
+        if !line.is_empty() {
+            let answer = ask(shared_state, session, line).await?;
+            history::record(shared_state, "chat", CHAT_PROMPT.name, Some(line), &answer);
+        }
+        return Ok(true);
+    };
+    let (command, argument) = command
+        .split_once(char::is_whitespace)
+        .unwrap_or((command, ""));
+    let argument = Some(argument.trim()).filter(|a| !a.is_empty());
+    let last_command = session.last_command.clone();
+
+    match (command, argument.or(last_command.as_deref())) {
+        ("explain", Some(input)) => {
+            let explanation = explain_command(shared_state, input).await?;
+            session.add(&format!("Explain `{}`", input), &explanation);
+        }
+        ("suggest", _) => {
+            let Some(request) = argument else {
+                return Err(CopilotError::Usage("Usage: /suggest <request>".to_string()));
+            };
+            suggest_in_chat(shared_state, session, request).await?;
+        }
+        ("run", Some(input)) => {
+            if execute_plan(shared_state, input)? {
+                session.note(&format!("I ran `{}`.", input));
+            }
+        }
+        ("explain" | "run", None) => {
+            return Err(CopilotError::Usage(format!(
+                "Nothing was suggested yet, use /{} <command>",
+                command
+            )))
+        }
+        ("commit", _) => git_commit_command(shared_state, argument, None, false, &[]).await?,
+        ("save", _) => {
+            if let Some(name) = argument {
+                session_file(name)?;
+                session.name = Some(name.to_string());
+            }
+            let path = session.save()?;
+            println!("Saved to {}\n", path.display());
+        }
+        ("reset", _) => {
+            session.reset();
+            println!("Started a new conversation.\n");
+        }
+        ("help", _) => println!("{}\n", CHAT_HELP),
+        ("exit" | "quit", _) => return Ok(false),
+        _ => {
+            return Err(CopilotError::Usage(format!(
+                "Unknown command /{}, /help lists the commands",
+                command
+            )))
+        }
+    }
+    Ok(true)
+}
+
+/// Answers `input` with the conversation so far and remembers both. The command of the
+/// answer, if there is one, is shown as a suggestion and becomes the last command.
+async fn ask(
+    shared_state: &SharedState,
+    session: &mut Session,
+    input: &str,
+) -> Result<String, CopilotError> {
+    let chain = chat_chain(shared_state.llm(), shared_state.prompt(CHAT_PROMPT.name));
+    let answer = invoke_chain(
+        shared_state,
+        &chain,
+        prompt_args! {
+            "os" => shared_state.os(),
+            "environment" => shared_state.environment().describe(),
+            "history" => session.history(),
+            "input" => input
+        },
+    )
+    .await?;
+    session.add(input, &answer);
+    let Some((explanation, command)) = split_code_block(&answer).filter(|(_, c)| !c.is_empty())
+    else {
+        Answer::Explanation.print(answer.trim());
+        return Ok(answer);
+    };
+    if !explanation.is_empty() {
+        Answer::Explanation.print(&explanation);
+    }
+    Answer::Suggestion.print(&command);
+    session.last_command = Some(command);
+    Ok(answer)
+}
+
+/// Suggests a command for `request` with the conversation so far, and shows the same menu
+/// as `suggest` for it.
+async fn suggest_in_chat(
+    shared_state: &SharedState,
+    session: &mut Session,
+    request: &str,
+) -> Result<(), CopilotError> {
+    let input = format!("Suggest a command to {}", request);
+    let previous = session.last_command.take();
+    ask(shared_state, session, &input).await?;
+    let Some(suggestion) = session.last_command.clone() else {
+        session.last_command = previous;
+        return Ok(());
+    };
+    let suggestion = verify_suggestion(shared_state, &suggestion, false).await?;
+    history::record(
+        shared_state,
+        "suggest",
+        CHAT_PROMPT.name,
+        Some(request),
+        &suggestion,
+    );
+    session.last_command = Some(suggestion.clone());
+    choose_options(shared_state, &suggestion).await
+}
@@ -1,24 +1,198 @@
-use langchain_rust::{chain::Chain, prompt_args};
+use std::{
+    fs,
+    io::{self, Read},
+    time::Instant,
+};
+
+use langchain_rust::prompt_args;
 
 use crate::{
-    chains::explain_command_chain,
-    util::shared::{apply_styles_to_backticks, SharedState},
+    chains::{
+        explain_command_chain, explain_script_chain, explain_tree_chain, EXPLAIN_PROMPT,
+        EXPLAIN_SCRIPT_PROMPT, EXPLAIN_TREE_PROMPT,
+    },
+    error::CopilotError,
+    util::{
+        docs::Documentation,
+        history,
+        output::{Answer, Report},
+        script::{annotate, chunks, sections, side_effects, ScriptKind, Section, MAX_CHUNK_CHARS},
+        shared::SharedState,
+        shell::parse,
+        stream::{invoke_chain, run_chain},
+    },
 };
 
+/// Explains a command part by part along its syntax tree, or as a whole when it does not
+/// parse. An answer that does not follow the tree is kept as a plain explanation. Returns
+/// the explanation.
 pub async fn explain_command(
     shared_state: &SharedState,
     command: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let explain_chain = explain_command_chain(shared_state.llm());
-    let explanation = explain_chain
-        .invoke(prompt_args! {
+) -> Result<String, CopilotError> {
+    let start = Instant::now();
+    let documentation = Documentation::lookup(command);
+
+    if let Ok(node) = parse(command) {
+        let mut tree = node.parts();
+        let explain_chain = explain_tree_chain(
+            shared_state.llm(),
+            shared_state.prompt(EXPLAIN_TREE_PROMPT.name),
+        );
+        let answer = run_chain(
+            shared_state,
+            &explain_chain,
+            prompt_args! {
+                "os" => shared_state.os(),
+                "environment" => shared_state.environment().describe(),
+                "documentation" => documentation.describe(),
+                "command" => command,
+                "parts" => tree.outline()
+            },
+            Answer::Explanation,
+        )
+        .await?;
+        let report = match tree.annotate(&answer) {
+            0 => Report::new(
+                shared_state,
+                "explain",
+                Some(command),
+                &answer,
+                start.elapsed(),
+            )
+            .with_bullets(),
+            _ => Report::new(
+                shared_state,
+                "explain",
+                Some(command),
+                &tree.render(false),
+                start.elapsed(),
+            )
+            .with_tree(tree),
+        };
+        let explanation = report.output.clone();
+        report
+            .with_undocumented(documentation.undocumented(&answer))
+            .print(shared_state);
+        history::record(
+            shared_state,
+            "explain",
+            EXPLAIN_TREE_PROMPT.name,
+            Some(command),
+            &explanation,
+        );
+        return Ok(explanation);
+    }
+
+    let explain_chain =
+        explain_command_chain(shared_state.llm(), shared_state.prompt(EXPLAIN_PROMPT.name));
+    let explanation = run_chain(
+        shared_state,
+        &explain_chain,
+        prompt_args! {
             "os" => shared_state.os(),
+            "environment" => shared_state.environment().describe(),
+            "documentation" => documentation.describe(),
             "command" => command
-        })
+        },
+        Answer::Explanation,
+    )
+    .await?;
+
+    Report::new(
+        shared_state,
+        "explain",
+        Some(command),
+        &explanation,
+        start.elapsed(),
+    )
+    .with_bullets()
+    .with_undocumented(documentation.undocumented(&explanation))
+    .print(shared_state);
+    history::record(
+        shared_state,
+        "explain",
+        EXPLAIN_PROMPT.name,
+        Some(command),
+        &explanation,
+    );
+
+    Ok(explanation)
+}
+
+/// Explains a script, Makefile, Dockerfile or CI configuration read from `path`, or from
+/// stdin when it is `-`, section by section and with a summary of its side effects. Long
+/// files are sent to the model in chunks.
+pub async fn explain_file(shared_state: &SharedState, path: &str) -> Result<(), CopilotError> {
+    let start = Instant::now();
+    let content = match path {
+        "-" => {
+            let mut content = String::new();
+            io::stdin().read_to_string(&mut content)?;
+            content
+        }
+        _ => fs::read_to_string(path)
+            .map_err(|e| CopilotError::Usage(format!("Could not read {}: {}", path, e)))?,
+    };
+    let kind = ScriptKind::detect((path != "-").then_some(path), &content);
+    let chunks = chunks(sections(kind, &content), MAX_CHUNK_CHARS);
+    if chunks.is_empty() {
+        return Err(CopilotError::Usage(format!(
+            "There is nothing to explain in {}",
+            path
+        )));
+    }
+
+    let explain_chain = explain_script_chain(
+        shared_state.llm(),
+        shared_state.prompt(EXPLAIN_SCRIPT_PROMPT.name),
+    );
+    let mut answers = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let part = match chunks.len() {
+            1 => String::new(),
+            n => format!("This is part {} of {} of the {}.", i + 1, n, kind),
+        };
+        let script = chunk
+            .iter()
+            .map(Section::numbered)
+            .collect::<Vec<String>>()
+            .join("\n");
+        let answer = invoke_chain(
+            shared_state,
+            &explain_chain,
+            prompt_args! {
+                "os" => shared_state.os(),
+                "environment" => shared_state.environment().describe(),
+                "kind" => kind.to_string(),
+                "part" => part,
+                "script" => script
+            },
+        )
         .await?;
+        answers.push(answer.trim().to_string());
+    }
+    let explanation = answers.join("\n\n");
 
-    println!("Explanation:\n");
-    println!("{}\n", apply_styles_to_backticks(&explanation));
+    let mut sections = chunks.into_iter().flatten().collect::<Vec<Section>>();
+    annotate(&mut sections, &explanation);
+    let side_effects = side_effects(&sections);
+    Report::new(
+        shared_state,
+        "explain",
+        Some(path),
+        &explanation,
+        start.elapsed(),
+    )
+    .with_script(sections, side_effects)
+    .print(shared_state);
+    history::record(
+        shared_state,
+        "explain",
+        EXPLAIN_SCRIPT_PROMPT.name,
+        Some(path),
+        &explanation,
+    );
 
     Ok(())
 }
@@ -0,0 +1,188 @@
+use std::{
+    env, fs,
+    io::{self, Read},
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use dialoguer::{
+    console::{style, Term},
+    Confirm,
+};
+use langchain_rust::prompt_args;
+
+use crate::{
+    chains::{fix_command_chain, FIX_COMMAND_PROMPT},
+    cli::choose_options,
+    commands::verify_suggestion,
+    error::CopilotError,
+    util::{
+        history,
+        output::{OutputFormat, Report},
+        process::capture,
+        safety::{analyze, RiskLevel},
+        shared::{split_code_block, SharedState},
+        stream::invoke_chain,
+    },
+};
+
+/// The environment variables the `ghcf` shell function passes the last command of the
+/// shell and its exit status in.
+pub const LAST_COMMAND_ENV: &str = "COPILOT_LAST_COMMAND";
+pub const LAST_STATUS_ENV: &str = "COPILOT_LAST_STATUS";
+
+/// How long a failed command may take when it is run again to capture its error output.
+const RERUN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many lines at the end of the error output are sent to the model.
+const MAX_ERROR_LINES: usize = 40;
+
+/// Diagnoses why `command` failed and suggests a corrected one. Without a command, the last
+/// command of the shell is taken from the `ghcf` shell function. The error output is read
+/// from `stderr`, a file or `-` for stdin, or captured by running the command again when it
+/// is low risk and the user agrees or `rerun` is set.
+pub async fn fix_command(
+    shared_state: &SharedState,
+    command: Option<&str>,
+    status: Option<i32>,
+    stderr: Option<&str>,
+    rerun: bool,
+) -> Result<(), CopilotError> {
+    let last_command = env::var(LAST_COMMAND_ENV).ok();
+    let command = command
+        .or(last_command.as_deref())
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .ok_or_else(|| {
+            CopilotError::Usage(
+                "A failed command is required, pass it or use the ghcf shell function".to_string(),
+            )
+        })?;
+    let mut status = status.or_else(|| env::var(LAST_STATUS_ENV).ok()?.parse().ok());
+    let error = match stderr {
+        Some("-") => {
+            let mut error = String::new();
+            io::stdin().read_to_string(&mut error)?;
+            Some(error)
+        }
+        Some(path) => Some(
+            fs::read_to_string(path)
+                .map_err(|e| CopilotError::Usage(format!("Could not read {}: {}", path, e)))?,
+        ),
+        None if rerun_allowed(shared_state, command, rerun)? => {
+            match capture(Command::new("sh").arg("-c").arg(command), RERUN_TIMEOUT) {
+                Some(output) => {
+                    status = status.or(output.status);
+                    match output.stderr.trim() {
+                        "" => Some(output.stdout),
+                        _ => Some(output.stderr),
+                    }
+                }
+                None => None,
+            }
+        }
+        None => None,
+    };
+
+    let start = Instant::now();
+    let fix_chain = fix_command_chain(
+        shared_state.llm(),
+        shared_state.prompt(FIX_COMMAND_PROMPT.name),
+    );
+    let answer = invoke_chain(
+        shared_state,
+        &fix_chain,
+        prompt_args! {
+            "os" => shared_state.os(),
+            "environment" => shared_state.environment().describe(),
+            "command" => command,
+            "status" => status.map(|s| s.to_string()).unwrap_or("unknown".to_string()),
+            "error" => error
+                .as_deref()
+                .map(last_lines)
+                .filter(|e| !e.is_empty())
+                .unwrap_or("No error output was captured.".to_string())
+        },
+    )
+    .await?;
+    let (diagnosis, fix) = split_fix(&answer);
+
+    let report = |fix: &str| {
+        Report::new(shared_state, "fix", Some(command), fix, start.elapsed())
+            .with_diagnosis(&diagnosis)
+            .with_risk(analyze(fix, shared_state.safety()))
+            .with_placeholders()
+            .with_missing_programs()
+    };
+    // Text output shows the fix before any warning about it.
+    let fix = match shared_state.format() {
+        OutputFormat::Text => {
+            report(&fix).print(shared_state);
+            verify_suggestion(shared_state, &fix, false).await?
+        }
+        _ => {
+            let fix = verify_suggestion(shared_state, &fix, false).await?;
+            report(&fix).print(shared_state);
+            fix
+        }
+    };
+    history::record(
+        shared_state,
+        "fix",
+        FIX_COMMAND_PROMPT.name,
+        Some(command),
+        &fix,
+    );
+
+    if shared_state.interactive() {
+        choose_options(shared_state, &fix).await?;
+    }
+    Ok(())
+}
+
+/// Whether the failed command may be run again to capture its error output: only low risk
+/// commands, when `rerun` is set or the user agrees to it at a terminal. Without `rerun`,
+/// non-interactive runs never run it again.
+fn rerun_allowed(
+    shared_state: &SharedState,
+    command: &str,
+    rerun: bool,
+) -> Result<bool, CopilotError> {
+    if analyze(command, shared_state.safety()).level > RiskLevel::Low {
+        if rerun && shared_state.format() == OutputFormat::Text {
+            println!(
+                "{} not running it again, the command is risky\n",
+                style("Warning:").yellow().bold()
+            );
+        }
+        return Ok(false);
+    }
+    if rerun {
+        return Ok(true);
+    }
+    if !shared_state.interactive() || !Term::stderr().is_term() {
+        return Ok(false);
+    }
+    Ok(Confirm::new()
+        .with_prompt("Run it again to capture its error output?")
+        .default(false)
+        .interact()?)
+}
+
+/// The last `MAX_ERROR_LINES` lines of an error output.
+fn last_lines(error: &str) -> String {
+    let lines = error.trim_end().lines().collect::<Vec<&str>>();
+    lines[lines.len().saturating_sub(MAX_ERROR_LINES)..].join("\n")
+}
+
+/// Splits an answer into the diagnosis and the corrected command, which is in the last code
+/// block, or the last line without one.
+fn split_fix(answer: &str) -> (String, String) {
+    if let Some(split) = split_code_block(answer) {
+        return split;
+    }
+    match answer.trim().rsplit_once('\n') {
+        Some((diagnosis, fix)) => (diagnosis.trim().to_string(), fix.trim().to_string()),
+        None => (String::new(), answer.trim().to_string()),
+    }
+}
@@ -1,83 +1,132 @@
-use std::{
-    io::{self, BufRead},
-    process::{Command, Stdio},
-};
+use std::time::Instant;
 
-use dialoguer::console::style;
-use langchain_rust::{chain::Chain, prompt_args};
+use langchain_rust::prompt_args;
 
 use crate::{
-    chains::{git_commit_chain, git_commit_chain_with_context},
+    chains::{
+        git_commit_chain, git_commit_chain_with_context, git_commit_full_chain,
+        GIT_COMMIT_FULL_PROMPT, GIT_COMMIT_PROMPT, GIT_COMMIT_WITH_CONTEXT_PROMPT,
+    },
     cli::choose_options_for_commit,
-    util::shared::SharedState,
+    error::CopilotError,
+    util::{
+        commit::finish_commit_message,
+        condense::{condense_diff, print_condensed},
+        git::{Exclude, StagedDiff},
+        history,
+        output::{Answer, OutputFormat, Report},
+        shared::SharedState,
+        stream::{invoke_chain, run_chain},
+        style::commit_style,
+    },
 };
 
+/// Writes a commit message for the staged changes and offers to commit with it. A `full`
+/// message, or one for a repository with `full = true` in `[commit]`, has a body and
+/// footers. `footers` are added to the message as they are.
 pub async fn git_commit_command(
     shared_state: &SharedState,
     context: Option<&str>,
     exclude: Option<&[&str]>,
-) {
-    let input = execute_git_diff_command(exclude.unwrap_or(&[]))
-        .map_err(|e| {
-            eprintln!("Error: {}", e);
-        })
-        .unwrap();
-    let suggestion = match context {
-        Some(context) => git_commit_chain_with_context(shared_state.llm())
-            .invoke(prompt_args! {
-                "input"=>input,
-                "context"=>context
-            })
-            .await
-            .unwrap(),
-        None => git_commit_chain(shared_state.llm())
-            .invoke(prompt_args! {
-                "input"=>input
-            })
-            .await
-            .unwrap(),
-    };
-
-    println!("Sugestion:\n");
-    println!("{}\n", style(suggestion.clone()).yellow().bold());
-    choose_options_for_commit(shared_state, &suggestion).await;
-}
+    full: bool,
+    footers: &[String],
+) -> Result<(), CopilotError> {
+    let project = shared_state.project();
+    let mut excludes = project
+        .commit
+        .excluded
+        .iter()
+        .map(|e| Exclude::from_root(e))
+        .collect::<Result<Vec<Exclude>, CopilotError>>()?;
+    for e in exclude.unwrap_or(&[]) {
+        excludes.push(Exclude::from_cwd(e)?);
+    }
+    let diff = StagedDiff::load(&excludes)?;
+    if diff.is_empty() {
+        return Err(CopilotError::Git(
+            "No staged changes found, use `git add` first".to_string(),
+        ));
+    }
+    let diff = condense_diff(shared_state, &diff).await?;
+    if shared_state.format() == OutputFormat::Text && !diff.condensed.is_empty() {
+        print_condensed(&diff.condensed);
+    }
+    let style = commit_style(shared_state);
+    let rules = project
+        .commit
+        .rules
+        .iter()
+        .map(|rule| format!("- {}\n", rule))
+        .collect::<String>();
 
-fn execute_git_diff_command(excludes: &[&str]) -> io::Result<String> {
-    let exclude_pattern = excludes.iter().fold(String::new(), |acc, &file| {
-        if acc.is_empty() {
-            format!("grep -vE '^{}$'", file)
-        } else {
-            format!("{} | grep -vE '^{}$'", acc, file)
-        }
-    });
-
-    let git_root_command_output = Command::new("git")
-        .args(["rev-parse", "--show-toplevel"])
-        .output()?;
-    let git_root_path = String::from_utf8_lossy(&git_root_command_output.stdout)
-        .trim()
-        .to_string();
-
-    let shell_command = if exclude_pattern.is_empty() {
-        format!("cd \"{}\" && git diff --cached --name-only --diff-filter=ACM | while read -r file; do echo \"\\n---------------------------\\n name:$file\"; git diff --cached \"$file\" | sed 's/^/+/'; done", git_root_path)
-    } else {
-        format!(
-            "cd \"{}\" && git diff --cached --name-only --diff-filter=ACM | {} | while read -r file; do echo \"\\n---------------------------\\n name:$file\"; git diff --cached \"$file\" | sed 's/^/+/'; done",
-            git_root_path, exclude_pattern
-        )
+    let start = Instant::now();
+    let full = full || project.commit.full;
+    let (chain, llm_chain, args) = match (full, context) {
+        (true, _) => (
+            GIT_COMMIT_FULL_PROMPT.name,
+            git_commit_full_chain(
+                shared_state.llm(),
+                shared_state.prompt(GIT_COMMIT_FULL_PROMPT.name),
+            ),
+            prompt_args! {
+                "input"=>diff.text.as_str(),
+                "context"=>context.unwrap_or("none"),
+                "style"=>style.instructions(),
+                "examples"=>style.examples(true),
+                "rules"=>rules
+            },
+        ),
+        (false, Some(context)) => (
+            GIT_COMMIT_WITH_CONTEXT_PROMPT.name,
+            git_commit_chain_with_context(
+                shared_state.llm(),
+                shared_state.prompt(GIT_COMMIT_WITH_CONTEXT_PROMPT.name),
+            ),
+            prompt_args! {
+                "input"=>diff.text.as_str(),
+                "context"=>context,
+                "style"=>style.instructions(),
+                "examples"=>style.examples(false),
+                "rules"=>rules
+            },
+        ),
+        (false, None) => (
+            GIT_COMMIT_PROMPT.name,
+            git_commit_chain(
+                shared_state.llm(),
+                shared_state.prompt(GIT_COMMIT_PROMPT.name),
+            ),
+            prompt_args! {
+                "input"=>diff.text.as_str(),
+                "style"=>style.instructions(),
+                "examples"=>style.examples(false),
+                "rules"=>rules
+            },
+        ),
     };
+    // Messages with a body or footers are reformatted, so they are shown once complete.
+    let streamed = !full && footers.is_empty();
+    let answer = match streamed {
+        true => run_chain(shared_state, &llm_chain, args, Answer::Suggestion).await?,
+        false => invoke_chain(shared_state, &llm_chain, args).await?,
+    };
+    let suggestion = finish_commit_message(shared_state, &answer, &style, footers).await?;
 
-    let output = Command::new("sh")
-        .arg("-c")
-        .arg(&shell_command)
-        .stdout(Stdio::piped())
-        .spawn()?
-        .stdout
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Could not capture stdout."))?;
-
-    let reader = io::BufReader::new(output);
-    let lines = reader.lines().collect::<Result<Vec<String>, _>>()?;
+    Report::new(
+        shared_state,
+        "commit",
+        context,
+        &suggestion,
+        start.elapsed(),
+    )
+    // A streamed answer that had to be cleaned or repaired is shown again as it is now.
+    .with_streamed(streamed && suggestion == answer.trim())
+    .with_condensed(diff.condensed)
+    .print(shared_state);
+    history::record(shared_state, "commit", chain, context, &suggestion);
 
-    Ok(lines.join("\n"))
+    if shared_state.interactive() {
+        choose_options_for_commit(shared_state, &suggestion, &style, footers).await?;
+    }
+    Ok(())
 }
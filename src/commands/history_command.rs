@@ -0,0 +1,131 @@
+use dialoguer::{console::style, Confirm};
+
+use crate::{
+    cli::execute_plan,
+    error::CopilotError,
+    util::{
+        history::{self, Entry},
+        output::{Answer, OutputFormat},
+        shared::SharedState,
+    },
+};
+
+/// Lists, searches, shows, reruns or clears the answers generated before.
+pub fn history_command(
+    shared_state: &SharedState,
+    action: &str,
+    argument: Option<&str>,
+    limit: usize,
+) -> Result<(), CopilotError> {
+    match (action, argument) {
+        ("list", _) => print_entries(
+            shared_state,
+            &history::load()
+                .into_iter()
+                .rev()
+                .take(limit)
+                .collect::<Vec<Entry>>(),
+        ),
+        ("search", Some(query)) => print_entries(
+            shared_state,
+            &history::search(query)
+                .into_iter()
+                .take(limit)
+                .collect::<Vec<Entry>>(),
+        ),
+        ("show", Some(id)) => print_entry(shared_state, &find_entry(id)?),
+        ("rerun", Some(id)) => {
+            let entry = find_entry(id)?;
+            if !entry.is_command() {
+                return Err(CopilotError::Usage(format!(
+                    "Entry {} is an answer of `{}`, only commands can be run again",
+                    entry.id, entry.command
+                )));
+            }
+            if !shared_state.interactive() {
+                return Err(CopilotError::Usage(
+                    "Running a command again needs an interactive terminal".to_string(),
+                ));
+            }
+            Answer::Suggestion.print(&entry.output);
+            if !execute_plan(shared_state, &entry.output)? {
+                println!("Not executed.");
+            }
+        }
+        ("clear", _) => {
+            let confirmed = !shared_state.interactive()
+                || Confirm::new()
+                    .with_prompt("Delete the whole history?")
+                    .default(false)
+                    .interact()?;
+            if confirmed {
+                let count = history::clear()?;
+                if shared_state.format() == OutputFormat::Text {
+                    println!("Deleted {} entries.", count);
+                }
+            }
+        }
+        _ => {
+            return Err(CopilotError::Usage(format!(
+                "Unknown history action '{}'",
+                action
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn find_entry(id: &str) -> Result<Entry, CopilotError> {
+    id.parse()
+        .ok()
+        .and_then(history::find)
+        .ok_or_else(|| CopilotError::Usage(format!("No history entry {}", id)))
+}
+
+fn print_entries(shared_state: &SharedState, entries: &[Entry]) {
+    match shared_state.format() {
+        OutputFormat::Text if entries.is_empty() => println!("The history is empty."),
+        OutputFormat::Text => entries.iter().for_each(|e| println!("{}", e.summary(true))),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(entries).unwrap() //safe to unwrap
+        ),
+        OutputFormat::Raw => entries
+            .iter()
+            .for_each(|e| println!("{}", e.summary(false))),
+    }
+}
+
+fn print_entry(shared_state: &SharedState, entry: &Entry) {
+    match shared_state.format() {
+        OutputFormat::Text => {
+            let field = |name: &str, value: &str| {
+                println!("{:<10} {}", style(name).dim(), value);
+            };
+            field("id", &entry.id.to_string());
+            field("when", &entry.age());
+            field("command", &entry.command);
+            field("chain", &entry.chain);
+            field(
+                "model",
+                &format!("{} {}", entry.provider, style(&entry.model).dim()),
+            );
+            if let Some(outcome) = entry.outcome() {
+                field("outcome", &outcome);
+            }
+            if let Some(input) = &entry.input {
+                field("input", input);
+            }
+            println!();
+            match entry.is_command() || entry.command == "commit" {
+                true => Answer::Suggestion.print(&entry.output),
+                false => Answer::Explanation.print(&entry.output),
+            }
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(entry).unwrap() //safe to unwrap
+        ),
+        OutputFormat::Raw => println!("{}", entry.output),
+    }
+}
@@ -4,5 +4,23 @@ pub use explain_command::*;
 mod suggest_command;
 pub use suggest_command::*;
 
+mod cache_command;
+pub use cache_command::*;
+
+mod chat_command;
+pub use chat_command::*;
+
+mod fix_command;
+pub use fix_command::*;
+
 mod git_commit_command;
 pub use git_commit_command::*;
+
+mod history_command;
+pub use history_command::*;
+
+mod prompts_command;
+pub use prompts_command::*;
+
+mod shell_init_command;
+pub use shell_init_command::*;
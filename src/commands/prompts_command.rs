@@ -0,0 +1,150 @@
+use std::{env, fs, process::Command};
+
+use dialoguer::{
+    console::{style, Term},
+    Confirm,
+};
+use tempfile::Builder;
+
+use crate::{
+    chains::{find_chain_prompt, ChainPrompt, CHAIN_PROMPTS},
+    error::CopilotError,
+    util::{
+        config::ConfigFile,
+        project::ProjectConfig,
+        templates::{prompt_file, raw_prompts, PromptSource},
+    },
+};
+
+/// Lists, shows, edits or resets the prompt templates of the chains.
+pub fn prompts_command(action: &str, name: Option<&str>) -> Result<(), CopilotError> {
+    let user = ConfigFile::load()?;
+    let project = ProjectConfig::load()?;
+    let overrides = raw_prompts(user.as_ref(), &project);
+    let effective = |chain_prompt: &ChainPrompt| {
+        overrides
+            .get(chain_prompt.name)
+            .cloned()
+            .unwrap_or((chain_prompt.template.to_string(), PromptSource::BuiltIn))
+    };
+
+    let chain_prompt = match name {
+        Some(name) => Some(
+            find_chain_prompt(name)
+                .ok_or_else(|| CopilotError::Usage(format!("Unknown prompt '{}'", name)))?,
+        ),
+        None => None,
+    };
+
+    match (action, chain_prompt) {
+        ("list", _) => {
+            for chain_prompt in CHAIN_PROMPTS {
+                let (template, source) = effective(chain_prompt);
+                let status = match chain_prompt.validate(&template) {
+                    Ok(_) => style(source.to_string()).green(),
+                    Err(_) => style(format!("{}, invalid", source)).red(),
+                };
+                println!(
+                    "{:<20} {:<30} {}",
+                    style(chain_prompt.name).yellow().bold(),
+                    status,
+                    chain_prompt.description
+                );
+                println!(
+                    "{:<20} variables: {}",
+                    "",
+                    style(chain_prompt.variables.join(", ")).dim()
+                );
+            }
+        }
+        ("show", Some(chain_prompt)) => {
+            let (template, source) = effective(chain_prompt);
+            println!(
+                "{} ({})\n",
+                style(chain_prompt.name).yellow().bold(),
+                source
+            );
+            println!("{}", template.trim());
+            if let Err(e) = chain_prompt.validate(&template) {
+                eprintln!("\n{} {}", style("Invalid:").red().bold(), e);
+            }
+        }
+        ("edit", Some(chain_prompt)) => {
+            let path = prompt_file(chain_prompt.name);
+            let mut template = fs::read_to_string(&path).unwrap_or(effective(chain_prompt).0);
+            let draft = Builder::new()
+                .prefix(&format!("copilot-prompt-{}-", chain_prompt.name))
+                .suffix(".txt")
+                .tempfile()?;
+            loop {
+                fs::write(draft.path(), &template)?;
+                let editor = env::var("VISUAL")
+                    .or(env::var("EDITOR"))
+                    .unwrap_or("vi".to_string());
+                Command::new("sh")
+                    .arg("-c")
+                    .arg(format!("{} \"$1\"", editor))
+                    .arg("sh")
+                    .arg(draft.path())
+                    .status()?;
+                template = fs::read_to_string(draft.path())?;
+
+                match chain_prompt.validate(&template) {
+                    Ok(_) => break,
+                    Err(e) => {
+                        eprintln!("{} {}", style("Invalid:").red().bold(), e);
+                        let retry = Term::stderr().is_term()
+                            && Confirm::new()
+                                .with_prompt("Edit it again?")
+                                .default(true)
+                                .interact()?;
+                        if !retry {
+                            return Err(CopilotError::Config(
+                                "The prompt was not saved".to_string(),
+                            ));
+                        }
+                    }
+                }
+            }
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, template)?;
+            println!("Saved {}", path.display());
+            warn_if_shadowed(&overrides, chain_prompt);
+        }
+        ("reset", Some(chain_prompt)) => {
+            let path = prompt_file(chain_prompt.name);
+            if path.exists() {
+                fs::remove_file(&path)?;
+                println!("Removed {}", path.display());
+            } else {
+                println!("'{}' has no prompt file to reset", chain_prompt.name);
+            }
+            warn_if_shadowed(&overrides, chain_prompt);
+        }
+        _ => {
+            return Err(CopilotError::Usage(format!(
+                "Unsupported prompts action: {}",
+                action
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn warn_if_shadowed(
+    overrides: &std::collections::BTreeMap<String, (String, PromptSource)>,
+    chain_prompt: &ChainPrompt,
+) {
+    if let Some((_, source)) = overrides.get(chain_prompt.name) {
+        if *source != PromptSource::File {
+            eprintln!(
+                "{} the {} also sets '{}' and takes precedence over the prompt file",
+                style("Warning:").yellow().bold(),
+                source,
+                chain_prompt.name
+            );
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::{env, str::FromStr};
+
+use crate::error::CopilotError;
+
+/// The environment variable naming the file an accepted command is written to instead of
+/// being executed, so the shell wrapper can run it in the user's shell.
+pub const SHELL_OUTPUT_ENV: &str = "COPILOT_SHELL_OUTPUT";
+
+/// The shells `shell-init` can integrate with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(format!("Unsupported shell: {}", s)),
+        }
+    }
+}
+
+const BASH_INIT: &str = r#"# Free GitHub Copilot shell integration, add to ~/.bashrc:
+#   eval "$({{exe}} shell-init bash)"
+
+# Suggest a command and run the accepted one in this shell.
+ghcs() {
+    local out cmd
+    out="$(mktemp)"
+    COPILOT_SHELL_OUTPUT="$out" "{{exe}}" suggest "$@"
+    cmd="$(cat "$out")"
+    rm -f "$out"
+    if [ -n "$cmd" ]; then
+        history -s -- "$cmd"
+        eval -- "$cmd"
+    fi
+}
+
+# Explain a command.
+ghce() {
+    "{{exe}}" explain "$@"
+}
+
+# Diagnose the last command and run the accepted fix in this shell.
+ghcf() {
+    local status=$? last out cmd
+    read -r last <<< "$(fc -ln -1)"
+    out="$(mktemp)"
+    COPILOT_SHELL_OUTPUT="$out" COPILOT_LAST_COMMAND="$last" COPILOT_LAST_STATUS="$status" \
+        "{{exe}}" fix "$@"
+    cmd="$(cat "$out")"
+    rm -f "$out"
+    if [ -n "$cmd" ]; then
+        history -s -- "$cmd"
+        eval -- "$cmd"
+    fi
+}
+
+# Ctrl-G: replace the command line with a suggestion for it.
+_copilot_suggest_line() {
+    local out cmd
+    out="$(mktemp)"
+    COPILOT_SHELL_OUTPUT="$out" "{{exe}}" suggest ${READLINE_LINE:+"$READLINE_LINE"} </dev/tty >/dev/tty
+    cmd="$(cat "$out")"
+    rm -f "$out"
+    if [ -n "$cmd" ]; then
+        READLINE_LINE="$cmd"
+        READLINE_POINT=${#cmd}
+    fi
+}
+bind -x '"\C-g": _copilot_suggest_line'
+"#;
+
+const ZSH_INIT: &str = r#"# Free GitHub Copilot shell integration, add to ~/.zshrc:
+#   eval "$({{exe}} shell-init zsh)"
+
+# Suggest a command and run the accepted one in this shell.
+ghcs() {
+    local out cmd
+    out="$(mktemp)"
+    COPILOT_SHELL_OUTPUT="$out" "{{exe}}" suggest "$@"
+    cmd="$(<"$out")"
+    rm -f "$out"
+    if [[ -n "$cmd" ]]; then
+        print -s -- "$cmd"
+        eval -- "$cmd"
+    fi
+}
+
+# Explain a command.
+ghce() {
+    "{{exe}}" explain "$@"
+}
+
+# Diagnose the last command and run the accepted fix in this shell.
+ghcf() {
+    local status=$? last out cmd
+    last="$(fc -ln -1)"
+    out="$(mktemp)"
+    COPILOT_SHELL_OUTPUT="$out" COPILOT_LAST_COMMAND="$last" COPILOT_LAST_STATUS="$status" \
+        "{{exe}}" fix "$@"
+    cmd="$(<"$out")"
+    rm -f "$out"
+    if [[ -n "$cmd" ]]; then
+        print -s -- "$cmd"
+        eval -- "$cmd"
+    fi
+}
+
+# Ctrl-G: replace the command line with a suggestion for it.
+_copilot_suggest_widget() {
+    local out cmd
+    out="$(mktemp)"
+    COPILOT_SHELL_OUTPUT="$out" "{{exe}}" suggest ${BUFFER:+"$BUFFER"} </dev/tty >/dev/tty
+    cmd="$(<"$out")"
+    rm -f "$out"
+    if [[ -n "$cmd" ]]; then
+        BUFFER="$cmd"
+        CURSOR=${#BUFFER}
+    fi
+    zle reset-prompt
+}
+zle -N _copilot_suggest_widget
+bindkey '^G' _copilot_suggest_widget
+"#;
+
+const FISH_INIT: &str = r#"# Free GitHub Copilot shell integration, add to ~/.config/fish/config.fish:
+#   {{exe}} shell-init fish | source
+
+# Suggest a command and run the accepted one in this shell.
+function ghcs
+    set -l out (mktemp)
+    env COPILOT_SHELL_OUTPUT=$out "{{exe}}" suggest $argv
+    set -l cmd (cat $out | string collect)
+    rm -f $out
+    if test -n "$cmd"
+        eval $cmd
+    end
+end
+
+# Explain a command.
+function ghce
+    "{{exe}}" explain $argv
+end
+
+# Diagnose the last command and run the accepted fix in this shell.
+function ghcf
+    set -l last_status $status
+    set -l out (mktemp)
+    env COPILOT_SHELL_OUTPUT=$out COPILOT_LAST_COMMAND=$history[1] COPILOT_LAST_STATUS=$last_status \
+        "{{exe}}" fix $argv
+    set -l cmd (cat $out | string collect)
+    rm -f $out
+    if test -n "$cmd"
+        eval $cmd
+    end
+end
+
+# Ctrl-G: replace the command line with a suggestion for it.
+function _copilot_suggest_line
+    set -l out (mktemp)
+    set -l line (commandline | string collect)
+    if test -n "$line"
+        env COPILOT_SHELL_OUTPUT=$out "{{exe}}" suggest "$line" </dev/tty >/dev/tty
+    else
+        env COPILOT_SHELL_OUTPUT=$out "{{exe}}" suggest </dev/tty >/dev/tty
+    end
+    set -l cmd (cat $out | string collect)
+    rm -f $out
+    if test -n "$cmd"
+        commandline -r -- $cmd
+    end
+    commandline -f repaint
+end
+bind \cg _copilot_suggest_line
+"#;
+
+/// Prints the wrapper functions and key binding for `shell`, meant to be evaluated by it.
+pub fn shell_init_command(shell: Shell) -> Result<(), CopilotError> {
+    let exe = env::current_exe()?;
+    let script = match shell {
+        Shell::Bash => BASH_INIT,
+        Shell::Zsh => ZSH_INIT,
+        Shell::Fish => FISH_INIT,
+    };
+    print!("{}", script.replace("{{exe}}", &exe.display().to_string()));
+    Ok(())
+}
@@ -1,26 +1,219 @@
-use dialoguer::console::style;
-use langchain_rust::{chain::Chain, prompt_args};
+use std::{str::FromStr, time::Instant};
+
+use dialoguer::{console::style, Confirm};
+use langchain_rust::prompt_args;
 
 use crate::{
-    chains::recomend_command_chain,
+    chains::{
+        recomend_command_chain, recomend_command_git_chain, recomend_command_github_chain,
+        revise_command_chain, ChainPrompt, RECOMMEND_GITHUB_PROMPT, RECOMMEND_GIT_PROMPT,
+        RECOMMEND_PROMPT, REVISE_COMMAND_PROMPT,
+    },
     cli::{choose_options, choose_sugestion_options},
-    util::shared::SharedState,
+    error::CopilotError,
+    util::{
+        availability::missing_programs,
+        history,
+        output::{Answer, OutputFormat, Report},
+        safety::analyze,
+        shared::SharedState,
+        stream::{run_chain, show_chain},
+    },
 };
 
-pub async fn suggest_command(shared_state: &SharedState, command: Option<&str>) {
-    let recommend_chian = recomend_command_chain(shared_state.llm());
-    if let Some(input) = command {
-        let suggestion = recommend_chian
-            .invoke(prompt_args! {
-                "os"=>shared_state.os(),
-                "command"=>input
-            })
-            .await
-            .unwrap();
-        println!("Sugestion:\n");
-        println!("{}\n", style(suggestion.clone()).yellow().bold());
-        choose_options(shared_state, &suggestion).await;
-    } else {
-        choose_sugestion_options(shared_state).await
+/// The kind of command the user wants a suggestion for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuggestionKind {
+    Shell,
+    Git,
+    GitHub,
+}
+
+impl FromStr for SuggestionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shell" => Ok(SuggestionKind::Shell),
+            "git" => Ok(SuggestionKind::Git),
+            "gh" | "github" => Ok(SuggestionKind::GitHub),
+            _ => Err(format!("Unsupported suggestion type: {}", s)),
+        }
+    }
+}
+
+impl SuggestionKind {
+    /// The chain that suggests commands of this kind.
+    pub fn chain(&self) -> &'static ChainPrompt {
+        match self {
+            SuggestionKind::Shell => &RECOMMEND_PROMPT,
+            SuggestionKind::Git => &RECOMMEND_GIT_PROMPT,
+            SuggestionKind::GitHub => &RECOMMEND_GITHUB_PROMPT,
+        }
+    }
+}
+
+/// Offers the suggestion made for a similar request before, so the model is not asked
+/// again. Returns it when the user takes it.
+pub fn past_suggestion(
+    shared_state: &SharedState,
+    kind: SuggestionKind,
+    input: &str,
+) -> Result<Option<String>, CopilotError> {
+    if !shared_state.interactive() {
+        return Ok(None);
+    }
+    let Some(entry) = history::similar(kind.chain().name, input) else {
+        return Ok(None);
+    };
+    let outcome = entry
+        .outcome()
+        .map(|o| format!(", {}", o))
+        .unwrap_or_default();
+    println!(
+        "{} {}\n",
+        style(format!("Asked before ({}{}):", entry.age(), outcome)).dim(),
+        entry.input.as_deref().unwrap_or_default()
+    );
+    println!("{}\n", style(&entry.output).yellow().bold());
+    let reuse = Confirm::new()
+        .with_prompt("Use this suggestion instead of asking for a new one?")
+        .default(true)
+        .interact()?;
+    Ok(reuse.then_some(entry.output))
+}
+
+/// Asks the chain matching `kind` for a command that does what `input` describes,
+/// streaming the answer when enabled.
+pub async fn generate_suggestion(
+    shared_state: &SharedState,
+    kind: SuggestionKind,
+    input: &str,
+) -> Result<String, CopilotError> {
+    let prompt = shared_state.prompt(kind.chain().name);
+    let suggest_chain = match kind {
+        SuggestionKind::Shell => recomend_command_chain(shared_state.llm(), prompt),
+        SuggestionKind::Git => recomend_command_git_chain(shared_state.llm(), prompt),
+        SuggestionKind::GitHub => recomend_command_github_chain(shared_state.llm(), prompt),
+    };
+    let suggestion = run_chain(
+        shared_state,
+        &suggest_chain,
+        prompt_args! {
+            "os"=>shared_state.os(),
+            "environment"=>shared_state.environment().describe(),
+            "command"=>input
+        },
+        Answer::Suggestion,
+    )
+    .await?;
+    Ok(suggestion)
+}
+
+/// How many times a suggestion is regenerated to get rid of programs that are missing.
+const MAX_REGENERATIONS: usize = 2;
+
+/// Checks that every program of a suggestion is installed. When some are missing they are
+/// reported and, if the user agrees or `regenerate` is set, the suggestion is revised to use
+/// installed tools only. Returns the suggestion to keep.
+pub async fn verify_suggestion(
+    shared_state: &SharedState,
+    suggestion: &str,
+    regenerate: bool,
+) -> Result<String, CopilotError> {
+    let text = shared_state.format() == OutputFormat::Text;
+    let mut suggestion = suggestion.to_string();
+    for _ in 0..MAX_REGENERATIONS {
+        let missing = missing_programs(&suggestion);
+        if missing.is_empty() {
+            break;
+        }
+        if text {
+            println!(
+                "{} not installed: {}\n",
+                style("Warning:").yellow().bold(),
+                missing.join(", ")
+            );
+        }
+        let regenerate = regenerate
+            || (shared_state.interactive()
+                && Confirm::new()
+                    .with_prompt("Regenerate it with installed tools only?")
+                    .default(true)
+                    .interact()?);
+        if !regenerate {
+            break;
+        }
+
+        let revise_chain = revise_command_chain(
+            shared_state.llm(),
+            shared_state.prompt(REVISE_COMMAND_PROMPT.name),
+        );
+        let args = prompt_args! {
+            "to"=>format!("use only installed tools, these are not available: {}", missing.join(", ")),
+            "command"=>suggestion
+        };
+        suggestion = match text {
+            true => show_chain(shared_state, &revise_chain, args, Answer::Suggestion).await?,
+            false => run_chain(shared_state, &revise_chain, args, Answer::Suggestion).await?,
+        };
+    }
+    Ok(suggestion)
+}
+
+pub async fn suggest_command(
+    shared_state: &SharedState,
+    command: Option<&str>,
+    kind: SuggestionKind,
+    installed_only: bool,
+) -> Result<(), CopilotError> {
+    let Some(input) = command else {
+        return choose_sugestion_options(shared_state).await;
+    };
+
+    let start = Instant::now();
+    let past = past_suggestion(shared_state, kind, input)?;
+    let suggestion = match &past {
+        Some(past) => past.clone(),
+        None => generate_suggestion(shared_state, kind, input).await?,
+    };
+    let report = |suggestion: &str| {
+        Report::new(
+            shared_state,
+            "suggest",
+            Some(input),
+            suggestion,
+            start.elapsed(),
+        )
+        .with_shown(past.is_some())
+        .with_risk(analyze(suggestion, shared_state.safety()))
+        .with_placeholders()
+        .with_missing_programs()
+    };
+    // Text output shows the suggestion before any warning about it.
+    let suggestion = match shared_state.format() {
+        OutputFormat::Text => {
+            report(&suggestion).print(shared_state);
+            verify_suggestion(shared_state, &suggestion, installed_only).await?
+        }
+        _ => {
+            let suggestion = verify_suggestion(shared_state, &suggestion, installed_only).await?;
+            report(&suggestion).print(shared_state);
+            suggestion
+        }
+    };
+    if past.as_ref() != Some(&suggestion) {
+        history::record(
+            shared_state,
+            "suggest",
+            kind.chain().name,
+            Some(input),
+            &suggestion,
+        );
+    }
+
+    if shared_state.interactive() {
+        choose_options(shared_state, &suggestion).await?;
     }
+    Ok(())
 }
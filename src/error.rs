@@ -0,0 +1,176 @@
+use std::{fmt, io};
+
+use langchain_rust::{chain::ChainError, language_models::LLMError, llm::AnthropicError};
+
+use crate::util::shared::SharedState;
+
+pub const EXIT_FAILURE: i32 = 1;
+/// The same exit code clap uses for argument errors.
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_CONFIG: i32 = 3;
+pub const EXIT_AUTH: i32 = 4;
+pub const EXIT_NETWORK: i32 = 5;
+pub const EXIT_GIT: i32 = 6;
+/// The exit code of a process interrupted with Ctrl-C.
+pub const EXIT_CANCELLED: i32 = 130;
+
+/// Every way a run can fail, each with its own exit code.
+#[derive(Debug)]
+pub enum CopilotError {
+    /// The command line was used wrongly.
+    Usage(String),
+    /// The user config, the repository config or a prompt template is missing or invalid.
+    Config(String),
+    /// The provider rejected the credentials.
+    Auth(String),
+    /// The provider could not be reached.
+    Network(String),
+    /// The provider answered with an error.
+    Provider(String),
+    Git(String),
+    /// The user left a prompt or a menu.
+    Cancelled,
+    Io(io::Error),
+    Other(String),
+}
+
+impl CopilotError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CopilotError::Usage(_) => EXIT_USAGE,
+            CopilotError::Config(_) => EXIT_CONFIG,
+            CopilotError::Auth(_) => EXIT_AUTH,
+            CopilotError::Network(_) => EXIT_NETWORK,
+            CopilotError::Git(_) => EXIT_GIT,
+            CopilotError::Cancelled => EXIT_CANCELLED,
+            CopilotError::Provider(_) | CopilotError::Io(_) | CopilotError::Other(_) => {
+                EXIT_FAILURE
+            }
+        }
+    }
+
+    /// How to fix the error, using the provider of the run when there is one.
+    pub fn hint(&self, shared_state: Option<&SharedState>) -> Option<String> {
+        let provider = shared_state.map(|s| s.provider()).unwrap_or("openai");
+        match self {
+            CopilotError::Auth(_) => Some(match provider {
+                "openai" => {
+                    "Set OPENAI_API_KEY or store an api_key in the profile with `config`".into()
+                }
+                "anthropic" => {
+                    "Set CLAUDE_API_KEY or ANTHROPIC_API_KEY, or store an api_key in the profile with `config`".into()
+                }
+                _ => "Check the api_key of the profile, or run `config` to set it again".into(),
+            }),
+            CopilotError::Network(_) => {
+                let api_base = shared_state.and_then(|s| s.api_base());
+                Some(match (provider, api_base) {
+                    ("ollama", api_base) => format!(
+                        "Is Ollama running at {}? Start it with `ollama serve`",
+                        api_base.unwrap_or("http://localhost:11434/v1")
+                    ),
+                    (_, Some(api_base)) => format!(
+                        "Check your connection and that {} is reachable, or fix the api_base with `config`",
+                        api_base
+                    ),
+                    _ => "Check your connection and try again".into(),
+                })
+            }
+            CopilotError::Provider(message) if message.contains("model") => {
+                let model = shared_state.map(|s| s.model()).unwrap_or_default();
+                Some(format!(
+                    "Check that the model '{}' is available for {}, or pick another one with `config`",
+                    model, provider
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CopilotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CopilotError::Usage(message)
+            | CopilotError::Config(message)
+            | CopilotError::Git(message)
+            | CopilotError::Other(message) => write!(f, "{}", message),
+            CopilotError::Auth(message) => write!(f, "Authentication failed: {}", message),
+            CopilotError::Network(message) => {
+                write!(f, "Could not reach the provider: {}", message)
+            }
+            CopilotError::Provider(message) => write!(f, "The provider failed: {}", message),
+            CopilotError::Cancelled => write!(f, "Cancelled"),
+            CopilotError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CopilotError {}
+
+impl From<io::Error> for CopilotError {
+    /// Ctrl-C inside a dialoguer prompt surfaces as an interrupted read.
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::Interrupted => CopilotError::Cancelled,
+            _ => CopilotError::Io(e),
+        }
+    }
+}
+
+impl From<git2::Error> for CopilotError {
+    fn from(e: git2::Error) -> Self {
+        CopilotError::Git(e.message().to_string())
+    }
+}
+
+impl From<LLMError> for CopilotError {
+    fn from(e: LLMError) -> Self {
+        match e {
+            LLMError::AnthropicError(
+                AnthropicError::AuthenticationError(message)
+                | AnthropicError::PermissionError(message),
+            ) => CopilotError::Auth(message),
+            LLMError::RequestError(e) => match e.status().map(|s| s.as_u16()) {
+                Some(401 | 403) => CopilotError::Auth(e.to_string()),
+                _ => CopilotError::Network(e.to_string()),
+            },
+            LLMError::Timeout(_) => CopilotError::Network("the request timed out".into()),
+            // async-openai errors are not re-exported, so they are told apart by their message.
+            LLMError::OpenAIError(e) => classify(e.to_string()),
+            e => CopilotError::Provider(e.to_string()),
+        }
+    }
+}
+
+impl From<ChainError> for CopilotError {
+    fn from(e: ChainError) -> Self {
+        match e {
+            ChainError::LLMError(e) => e.into(),
+            e => CopilotError::Provider(e.to_string()),
+        }
+    }
+}
+
+fn classify(message: String) -> CopilotError {
+    let lower = message.to_lowercase();
+    if ["api key", "invalid_api_key", "401", "403", "unauthorized"]
+        .iter()
+        .any(|m| lower.contains(m))
+    {
+        CopilotError::Auth(message)
+    } else if [
+        "error sending request",
+        "connection refused",
+        "dns error",
+        "timed out",
+        "transport error",
+    ]
+    .iter()
+    .any(|m| lower.contains(m))
+    {
+        CopilotError::Network(message)
+    } else {
+        CopilotError::Provider(message)
+    }
+}
@@ -1,46 +1,182 @@
 #![allow(dead_code)]
 
+use std::path::PathBuf;
+
 use cli::init_clap;
+use dialoguer::console::style;
+use error::CopilotError;
 use util::{
+    commit::passthrough_footers,
     config::LLMConfig,
+    output::OutputFormat,
     shared::{intro, SharedState},
 };
 
 mod chains;
 mod cli;
 mod commands;
+mod error;
 mod util;
 pub const COPILOT_PATH: &str = ".free_copilot_cli";
 
 #[tokio::main]
 async fn main() {
     let matches = init_clap();
-    let shared_state = SharedState::default();
+    let format: OutputFormat = matches.value_of_t("format").unwrap_or_else(|e| e.exit());
 
-    intro();
-    match matches.subcommand() {
-        Some(("explain", args)) => {
-            let command = args.value_of("command").unwrap();
-            commands::explain_command(&shared_state, command)
-                .await
-                .unwrap();
+    // The wizard may create the requested profile, so it runs before any profile is resolved.
+    if let Some(("config", args)) = matches.subcommand() {
+        intro();
+        let commands = args
+            .values_of("for")
+            .map(|v| v.collect::<Vec<&str>>())
+            .unwrap_or_default();
+        if let Err(e) = LLMConfig::new_config(matches.value_of("profile"), &commands).await {
+            exit_with(e, None);
+        }
+        return;
+    }
+
+    if let Some(("shell-init", args)) = matches.subcommand() {
+        let shell = args.value_of_t("shell").unwrap_or_else(|e| e.exit());
+        if let Err(e) = commands::shell_init_command(shell) {
+            exit_with(e, None);
+        }
+        return;
+    }
+
+    // Prompts are managed without resolving them, so a broken template can still be fixed.
+    if let Some(("prompts", args)) = matches.subcommand() {
+        let (action, args) = args.subcommand().unwrap(); //safe to unwrap, a subcommand is required
+        if let Err(e) = commands::prompts_command(
+            action,
+            args.try_get_one::<String>("name")
+                .unwrap_or(None)
+                .map(|n| n.as_str()),
+        ) {
+            exit_with(e, None);
         }
+        return;
+    }
+
+    let shared_state = SharedState::resolve(matches.value_of("profile"), matches.subcommand_name())
+        .unwrap_or_else(|e| exit_with(e, None))
+        .with_format(format)
+        .with_interactive(!matches.is_present("no-interactive"))
+        .with_stream(!matches.is_present("no-stream"))
+        .with_caching(!matches.is_present("no-cache"))
+        .with_shell_output(
+            std::env::var_os(commands::SHELL_OUTPUT_ENV)
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from),
+        );
+
+    if shared_state.format() == OutputFormat::Text {
+        intro();
+    }
+    let result = match matches.subcommand() {
+        Some(("explain", args)) => match (args.value_of("file"), args.value_of("command")) {
+            (Some(path), _) | (None, Some(path @ "-")) => {
+                commands::explain_file(&shared_state, path).await
+            }
+            //safe to unwrap, the command is required without a file
+            _ => commands::explain_command(&shared_state, args.value_of("command").unwrap())
+                .await
+                .map(|_| ()),
+        },
         Some(("suggest", args)) => {
             let input = args.value_of("input");
-            commands::suggest_command(&shared_state, input).await;
+            let kind = args.value_of_t("type").unwrap_or_else(|e| e.exit());
+            match input {
+                None if !shared_state.interactive() => Err(CopilotError::Usage(
+                    "An input is required when running non-interactively".to_string(),
+                )),
+                _ => {
+                    let installed_only = args.is_present("installed-only");
+                    commands::suggest_command(&shared_state, input, kind, installed_only).await
+                }
+            }
+        }
+
+        Some(("fix", args)) => {
+            let status = args
+                .is_present("exit-code")
+                .then(|| args.value_of_t("exit-code").unwrap_or_else(|e| e.exit()));
+            commands::fix_command(
+                &shared_state,
+                args.value_of("command"),
+                status,
+                args.value_of("stderr"),
+                args.is_present("rerun"),
+            )
+            .await
+        }
+
+        Some(("chat", args)) => {
+            commands::chat_command(&shared_state, args.value_of("session")).await
+        }
+
+        Some(("history", args)) => {
+            let (action, args) = args.subcommand().unwrap(); //safe to unwrap, a subcommand is required
+            let value = |name: &str| {
+                args.try_get_one::<String>(name)
+                    .unwrap_or(None)
+                    .map(|v| v.as_str())
+            };
+            match value("limit").map(str::parse).unwrap_or(Ok(0)) {
+                Ok(limit) => commands::history_command(
+                    &shared_state,
+                    action,
+                    value("query").or(value("id")),
+                    limit,
+                ),
+                Err(_) => Err(CopilotError::Usage(
+                    "The limit must be a number".to_string(),
+                )),
+            }
         }
 
-        Some(("config", _)) => LLMConfig::new_config().await,
+        Some(("cache", args)) => {
+            //safe to unwrap, a subcommand is required
+            commands::cache_command(&shared_state, args.subcommand_name().unwrap())
+        }
 
         Some(("commit", args)) => {
             let context = args.value_of("context");
-            let exclude = args.values_of("exclude").map(|v| v.collect::<Vec<&str>>());
+            let exclude = args.values_of("excluded").map(|v| v.collect::<Vec<&str>>());
+            let values = |name: &str| {
+                args.values_of(name)
+                    .map(|v| v.collect::<Vec<&str>>())
+                    .unwrap_or_default()
+            };
+            let footers = passthrough_footers(&values("refs"), &values("co-author"));
 
-            commands::git_commit_command(&shared_state, context, exclude.as_deref()).await;
+            commands::git_commit_command(
+                &shared_state,
+                context,
+                exclude.as_deref(),
+                args.is_present("full"),
+                &footers,
+            )
+            .await
         }
 
         _ => {
             println!("No subcommand was used");
+            Ok(())
         }
+    };
+
+    if let Err(e) = result {
+        exit_with(e, Some(&shared_state));
+    }
+}
+
+/// Prints the error with a hint on how to fix it and exits with its exit code.
+fn exit_with(e: CopilotError, shared_state: Option<&SharedState>) -> ! {
+    eprintln!("{} {}", style("Error:").red().bold(), e);
+    if let Some(hint) = e.hint(shared_state) {
+        eprintln!("{} {}", style("Hint:").yellow().bold(), hint);
     }
+    std::process::exit(e.exit_code())
 }
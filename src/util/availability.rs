@@ -0,0 +1,308 @@
+use super::{
+    environment::find_program,
+    shell::{parse_suggestion, unquote, NodeKind},
+};
+
+/// Builtins and keywords of POSIX shells, bash, zsh and fish, which are not on `PATH`.
+const BUILTINS: &[&str] = &[
+    ".",
+    ":",
+    "[",
+    "[[",
+    "abbr",
+    "alias",
+    "and",
+    "autoload",
+    "begin",
+    "bg",
+    "bind",
+    "bindkey",
+    "break",
+    "builtin",
+    "caller",
+    "case",
+    "cd",
+    "compgen",
+    "complete",
+    "compopt",
+    "contains",
+    "continue",
+    "coproc",
+    "declare",
+    "dirs",
+    "disown",
+    "do",
+    "done",
+    "echo",
+    "elif",
+    "else",
+    "emulate",
+    "enable",
+    "end",
+    "esac",
+    "eval",
+    "exit",
+    "export",
+    "false",
+    "fc",
+    "fg",
+    "fi",
+    "for",
+    "funced",
+    "funcsave",
+    "function",
+    "functions",
+    "getopts",
+    "hash",
+    "help",
+    "history",
+    "if",
+    "in",
+    "jobs",
+    "kill",
+    "let",
+    "local",
+    "logout",
+    "mapfile",
+    "math",
+    "not",
+    "or",
+    "popd",
+    "print",
+    "printf",
+    "pushd",
+    "pwd",
+    "read",
+    "readarray",
+    "readonly",
+    "rehash",
+    "return",
+    "select",
+    "set",
+    "set_color",
+    "setopt",
+    "shift",
+    "shopt",
+    "source",
+    "status",
+    "string",
+    "suspend",
+    "switch",
+    "test",
+    "then",
+    "times",
+    "trap",
+    "true",
+    "type",
+    "typeset",
+    "ulimit",
+    "umask",
+    "unalias",
+    "unset",
+    "unsetopt",
+    "until",
+    "wait",
+    "whence",
+    "where",
+    "while",
+    "zle",
+    "zmodload",
+];
+
+/// Commands that run the program given after them, with their options that take a value
+/// and the number of arguments they take before the program.
+const WRAPPERS: [(&str, &[&str], usize); 12] = [
+    (
+        "sudo",
+        &[
+            "-u",
+            "--user",
+            "-g",
+            "--group",
+            "-p",
+            "--prompt",
+            "-C",
+            "--close-from",
+            "-D",
+            "--chdir",
+            "-h",
+            "--host",
+            "-r",
+            "--role",
+            "-t",
+            "--type",
+            "-U",
+            "--other-user",
+            "-T",
+            "--command-timeout",
+        ],
+        0,
+    ),
+    ("doas", &["-u", "-C"], 0),
+    ("env", &["-u", "--unset", "-C", "--chdir"], 0),
+    ("time", &["-f", "--format", "-o", "--output"], 0),
+    ("nohup", &[], 0),
+    ("exec", &["-a"], 0),
+    ("command", &[], 0),
+    ("nice", &["-n", "--adjustment"], 0),
+    ("ionice", &["-c", "--class", "-n", "--classdata"], 0),
+    ("stdbuf", &["-i", "-o", "-e"], 0),
+    ("timeout", &["-s", "--signal", "-k", "--kill-after"], 1),
+    (
+        "xargs",
+        &[
+            "-n",
+            "--max-args",
+            "-L",
+            "--max-lines",
+            "-P",
+            "--max-procs",
+            "-s",
+            "--max-chars",
+            "-I",
+            "-d",
+            "--delimiter",
+            "-E",
+            "-a",
+            "--arg-file",
+        ],
+        0,
+    ),
+];
+
+/// Every simple command of `input` as its unquoted words, starting with the program it
+/// runs. Commands of pipes, lists, compound commands and substitutions come one after the
+/// other.
+pub fn invocations(input: &str) -> Vec<Vec<String>> {
+    let (nodes, _) = parse_suggestion(input);
+    nodes
+        .iter()
+        .flat_map(|node| node.nodes())
+        .filter_map(|node| match &node.kind {
+            NodeKind::Command { words, .. } => {
+                let words = words.iter().map(|w| unquote(&w.text)).collect::<Vec<_>>();
+                let program = without_wrappers(&words);
+                (!program.is_empty()).then(|| program.to_vec())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The programs every command of a suggestion runs, in order and without duplicates.
+/// Builtins, the functions the suggestion defines and programs named by a variable or a
+/// substitution are left out.
+pub fn programs(input: &str) -> Vec<String> {
+    let (nodes, _) = parse_suggestion(input);
+    let functions = nodes
+        .iter()
+        .flat_map(|node| node.nodes())
+        .filter_map(|node| match &node.kind {
+            NodeKind::Function { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<&str>>();
+    let mut programs: Vec<String> = Vec::new();
+    for words in invocations(input) {
+        let program = &words[0];
+        if BUILTINS.contains(&program.as_str())
+            || functions.contains(&program.as_str())
+            || program.contains(['$', '`'])
+            || programs.contains(program)
+        {
+            continue;
+        }
+        programs.push(program.clone());
+    }
+    programs
+}
+
+/// The programs of a suggestion that cannot be found on this machine.
+pub fn missing_programs(input: &str) -> Vec<String> {
+    programs(input)
+        .into_iter()
+        .filter(|program| find_program(program).is_none())
+        .collect()
+}
+
+/// The words of a command from the program it runs, past the wrappers like `sudo -u
+/// postgres` or `xargs -n 1` in front of it.
+fn without_wrappers(words: &[String]) -> &[String] {
+    let mut i = 0;
+    while let Some((_, options, arguments)) = words
+        .get(i)
+        .and_then(|word| WRAPPERS.iter().find(|(name, ..)| name == word))
+    {
+        i += 1;
+        let mut arguments = *arguments;
+        while let Some(word) = words.get(i) {
+            if word == "--" {
+                i += 1;
+                break;
+            }
+            let assignment = word
+                .split_once('=')
+                .is_some_and(|(name, _)| !name.is_empty() && !name.contains('/'));
+            if word.starts_with('-') && word.len() > 1 {
+                i += if options.contains(&word.as_str()) {
+                    2
+                } else {
+                    1
+                };
+            } else if assignment {
+                i += 1;
+            } else if arguments > 0 {
+                arguments -= 1;
+                i += 1;
+            } else {
+                break;
+            }
+        }
+    }
+    &words[i.min(words.len())..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrappers_and_their_options_are_skipped() {
+        assert_eq!(programs("sudo -u postgres psql -c 'SELECT 1'"), ["psql"]);
+        assert_eq!(
+            programs("find . -name '*.log' | xargs -n 1 gzip"),
+            ["find", "gzip"]
+        );
+        assert_eq!(programs("env -C dir FOO=1 make"), ["make"]);
+        assert_eq!(
+            programs("timeout -s KILL 10s nice -n 5 cargo build"),
+            ["cargo"]
+        );
+        assert_eq!(programs("sudo -- rm -r build"), ["rm"]);
+    }
+
+    #[test]
+    fn builtins_and_functions_are_not_programs() {
+        assert!(programs("[[ -f .env ]] && . .env; pushd src; shopt -s globstar; popd").is_empty());
+        assert_eq!(programs("greet() { echo hi; }\ngreet && tree"), ["tree"]);
+        assert!(programs("$EDITOR notes.txt").is_empty());
+    }
+
+    #[test]
+    fn every_command_of_the_tree_is_found() {
+        assert_eq!(
+            programs("if grep -q x f; then jq . \"$(curl -s url)\"; fi | less"),
+            ["grep", "jq", "curl", "less"]
+        );
+        assert_eq!(programs("cat <<EOF > notes.txt\nfirst line\nEOF"), ["cat"]);
+        assert_eq!(programs("```sh\nls -la\n```"), ["ls"]);
+    }
+
+    #[test]
+    fn invocations_keep_their_unquoted_words() {
+        assert_eq!(
+            invocations("sudo -u web git log --format='%h %s'"),
+            [["git", "log", "--format=%h %s"]]
+        );
+    }
+}
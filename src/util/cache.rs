@@ -0,0 +1,310 @@
+use std::{
+    fs, io,
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use futures::{future, stream, Stream, StreamExt};
+use langchain_rust::{
+    language_models::{llm::LLM, GenerateResult, LLMError},
+    schemas::{Message, StreamData},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{llm::LLMVariant, shared::expand_home};
+
+/// Directory holding one `<key>.json` file per cached answer.
+pub const CACHE_PATH: &str = "~/.free_copilot_cache";
+
+const DEFAULT_TTL_HOURS: u64 = 24 * 7;
+const DEFAULT_MAX_SIZE_MB: u64 = 20;
+
+/// The `[cache]` section of the config file. Answers are cached unless `enabled` is false.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CacheConfig {
+    pub enabled: Option<bool>,
+    /// How long an answer is reused, a week by default.
+    pub ttl_hours: Option<u64>,
+    /// How large the cache may grow before the oldest answers are dropped, 20 MB by default.
+    pub max_size_mb: Option<u64>,
+}
+
+impl CacheConfig {
+    pub fn enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
+    fn ttl(&self) -> u64 {
+        self.ttl_hours.unwrap_or(DEFAULT_TTL_HOURS) * 3600
+    }
+
+    fn max_size(&self) -> u64 {
+        self.max_size_mb.unwrap_or(DEFAULT_MAX_SIZE_MB) * 1024 * 1024
+    }
+}
+
+/// A cached answer, with the prompt it answers to tell hash collisions apart.
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    /// Seconds since the Unix epoch.
+    time: u64,
+    provider: String,
+    model: String,
+    prompt: String,
+    response: String,
+    #[serde(default)]
+    hits: u64,
+}
+
+/// What the cache holds, for `cache stats`.
+#[derive(Debug, Default, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub expired: usize,
+    pub bytes: u64,
+    pub hits: u64,
+    pub path: PathBuf,
+}
+
+/// A provider whose answers are kept on disk and replayed for the same prompt, provider,
+/// endpoint and model until they expire.
+#[derive(Clone)]
+pub struct CachedLLM {
+    llm: Box<LLMVariant>,
+    provider: String,
+    api_base: Option<String>,
+    model: String,
+    config: CacheConfig,
+}
+
+impl CachedLLM {
+    pub fn new(
+        llm: LLMVariant,
+        provider: &str,
+        api_base: Option<&str>,
+        model: &str,
+        config: CacheConfig,
+    ) -> Self {
+        CachedLLM {
+            llm: Box::new(llm),
+            provider: provider.to_string(),
+            api_base: api_base.map(|a| a.to_string()),
+            model: model.to_string(),
+            config,
+        }
+    }
+
+    fn prompt(messages: &[Message]) -> String {
+        //safe to unwrap, messages are always serializable
+        serde_json::to_string(messages).unwrap()
+    }
+
+    fn key(&self, prompt: &str) -> String {
+        format!(
+            "{:016x}",
+            fnv1a(
+                &[
+                    &self.provider,
+                    self.api_base.as_deref().unwrap_or_default(),
+                    &self.model,
+                    prompt
+                ]
+                .join("\0")
+            )
+        )
+    }
+
+    /// The cached answer to `prompt`, counting the hit.
+    fn lookup(&self, key: &str, prompt: &str) -> Option<String> {
+        let path = cache_dir().join(format!("{}.json", key));
+        let mut entry: CacheEntry = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+        if entry.prompt != prompt
+            || entry.provider != self.provider
+            || entry.model != self.model
+            || now().saturating_sub(entry.time) > self.config.ttl()
+        {
+            return None;
+        }
+        entry.hits += 1;
+        let _ = write_entry(key, &entry);
+        Some(entry.response)
+    }
+
+    /// Caches an answer. The cache is best effort, failing to write it does not fail the run.
+    fn store(&self, key: &str, prompt: &str, response: &str) {
+        if response.trim().is_empty() {
+            return;
+        }
+        let entry = CacheEntry {
+            time: now(),
+            provider: self.provider.clone(),
+            model: self.model.clone(),
+            prompt: prompt.to_string(),
+            response: response.to_string(),
+            hits: 0,
+        };
+        if write_entry(key, &entry).is_ok() {
+            let _ = prune(&self.config);
+        }
+    }
+}
+
+#[async_trait]
+impl LLM for CachedLLM {
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        let prompt = CachedLLM::prompt(messages);
+        let key = self.key(&prompt);
+        if let Some(generation) = self.lookup(&key, &prompt) {
+            return Ok(GenerateResult {
+                tokens: None,
+                generation,
+            });
+        }
+        let result = self.llm.generate(messages).await?;
+        self.store(&key, &prompt, &result.generation);
+        Ok(result)
+    }
+
+    /// Replays a cached answer in chunks, or streams a new one and caches it once it is
+    /// complete.
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        let prompt = CachedLLM::prompt(messages);
+        let key = self.key(&prompt);
+        if let Some(generation) = self.lookup(&key, &prompt) {
+            let chunks = generation
+                .split_inclusive(char::is_whitespace)
+                .map(|chunk| Ok(StreamData::new(json!({ "content": chunk }), chunk)))
+                .collect::<Vec<_>>();
+            return Ok(Box::pin(stream::iter(chunks)));
+        }
+
+        let content = Arc::new(Mutex::new(Some(String::new())));
+        let received = content.clone();
+        let answer = self.llm.stream(messages).await?.inspect(move |data| {
+            //safe to unwrap, the lock is never held across a panic
+            let mut received = received.lock().unwrap();
+            match data {
+                Ok(data) => {
+                    if let Some(content) = received.as_mut() {
+                        content.push_str(&data.content);
+                    }
+                }
+                // A failed answer is not cached.
+                Err(_) => *received = None,
+            }
+        });
+        let cache = self.clone();
+        let store = stream::once(async move {
+            //safe to unwrap, the lock is never held across a panic
+            if let Some(content) = content.lock().unwrap().take() {
+                cache.store(&key, &prompt, &content);
+            }
+        })
+        .filter_map(|_| future::ready(None));
+        Ok(Box::pin(answer.chain(store)))
+    }
+}
+
+fn cache_dir() -> PathBuf {
+    expand_home(CACHE_PATH)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// A hash that stays the same across builds, unlike the one of the standard library.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn write_entry(key: &str, entry: &CacheEntry) -> io::Result<()> {
+    let dir = cache_dir();
+    fs::create_dir_all(&dir)?;
+    //safe to unwrap, entries are always serializable
+    fs::write(
+        dir.join(format!("{}.json", key)),
+        serde_json::to_string(entry).unwrap(),
+    )
+}
+
+/// Every cached answer with its file and size, oldest first.
+fn entries() -> Vec<(PathBuf, u64, CacheEntry)> {
+    let Ok(dir) = fs::read_dir(cache_dir()) else {
+        return Vec::new();
+    };
+    let mut entries = dir
+        .filter_map(|file| {
+            let path = file.ok()?.path();
+            let size = fs::metadata(&path).ok()?.len();
+            let entry = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+            Some((path, size, entry))
+        })
+        .collect::<Vec<(PathBuf, u64, CacheEntry)>>();
+    entries.sort_by_key(|(_, _, entry)| entry.time);
+    entries
+}
+
+/// Drops the expired answers, then the oldest ones until the cache fits its size limit.
+fn prune(config: &CacheConfig) -> io::Result<()> {
+    let now = now();
+    let mut size = 0;
+    let mut kept = Vec::new();
+    for (path, bytes, entry) in entries() {
+        match now.saturating_sub(entry.time) > config.ttl() {
+            true => fs::remove_file(path)?,
+            false => {
+                size += bytes;
+                kept.push((path, bytes));
+            }
+        }
+    }
+    for (path, bytes) in kept {
+        if size <= config.max_size() {
+            break;
+        }
+        fs::remove_file(path)?;
+        size -= bytes;
+    }
+    Ok(())
+}
+
+pub fn stats(config: &CacheConfig) -> CacheStats {
+    let now = now();
+    entries().into_iter().fold(
+        CacheStats {
+            path: cache_dir(),
+            ..CacheStats::default()
+        },
+        |mut stats, (_, bytes, entry)| {
+            stats.entries += 1;
+            stats.bytes += bytes;
+            stats.hits += entry.hits;
+            if now.saturating_sub(entry.time) > config.ttl() {
+                stats.expired += 1;
+            }
+            stats
+        },
+    )
+}
+
+/// Deletes every cached answer and returns how many there were.
+pub fn clear() -> io::Result<usize> {
+    let count = entries().len();
+    match fs::remove_dir_all(cache_dir()) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(count),
+    }
+}
@@ -0,0 +1,578 @@
+use std::{fmt, sync::OnceLock};
+
+use dialoguer::console::style;
+use langchain_rust::prompt_args;
+use regex::Regex;
+
+use crate::{
+    chains::{revise_commit_chain, REVISE_COMMIT_PROMPT},
+    error::CopilotError,
+};
+
+use super::{
+    output::OutputFormat,
+    shared::{split_code_block, SharedState},
+    stream::invoke_chain,
+    style::CommitStyle,
+};
+
+/// The width commit bodies are wrapped at, the one `git log` reads best at.
+pub const BODY_WIDTH: usize = 72;
+
+/// The longest subject line accepted when the history does not say.
+pub const MAX_SUBJECT_LENGTH: usize = 72;
+
+/// The commit types of Conventional Commits, the Angular ones.
+pub const COMMIT_TYPES: [&str; 11] = [
+    "feat", "fix", "docs", "style", "refactor", "perf", "test", "build", "ci", "chore", "revert",
+];
+
+/// Common misspellings of the commit types, repaired without asking the model again.
+const TYPE_ALIASES: [(&str, &str); 10] = [
+    ("feature", "feat"),
+    ("features", "feat"),
+    ("bugfix", "fix"),
+    ("hotfix", "fix"),
+    ("doc", "docs"),
+    ("tests", "test"),
+    ("refactoring", "refactor"),
+    ("performance", "perf"),
+    ("chores", "chore"),
+    ("styles", "style"),
+];
+
+/// How many times the model is asked to fix a message that cannot be repaired.
+const MAX_REPAIRS: usize = 2;
+
+/// A trailer line like `Refs: #123`, `Co-authored-by: A <a@b.c>` or `BREAKING CHANGE: ...`.
+fn is_footer(line: &str) -> bool {
+    static FOOTER: OnceLock<Regex> = OnceLock::new();
+    FOOTER
+        .get_or_init(|| Regex::new(r"^(BREAKING[ -]CHANGE|[A-Za-z][A-Za-z-]*)(: | #)").unwrap())
+        .is_match(line)
+}
+
+/// Formats a commit message: the subject, then the body wrapped at `BODY_WIDTH` columns,
+/// then the footers of the message followed by `footers`, leaving out the ones it already
+/// has.
+pub fn format_commit_message(message: &str, footers: &[String]) -> String {
+    let message = message.trim();
+    let (subject, rest) = message.split_once('\n').unwrap_or((message, ""));
+    let mut paragraphs = rest
+        .trim()
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| p.to_string())
+        .collect::<Vec<String>>();
+
+    let mut trailers = match paragraphs.last() {
+        Some(last) if last.lines().all(is_footer) => paragraphs
+            .pop()
+            .unwrap_or_default()
+            .lines()
+            .map(|l| l.to_string())
+            .collect(),
+        _ => Vec::new(),
+    };
+    for footer in footers {
+        if !trailers.contains(footer) {
+            trailers.push(footer.clone());
+        }
+    }
+
+    let mut formatted = vec![subject.trim().to_string()];
+    formatted.extend(paragraphs.iter().map(|p| wrap_paragraph(p)));
+    if !trailers.is_empty() {
+        formatted.push(trailers.join("\n"));
+    }
+    formatted.join("\n\n")
+}
+
+/// Wraps a paragraph of the body. Bullets keep their own lines and are indented under
+/// their marker when they wrap.
+fn wrap_paragraph(paragraph: &str) -> String {
+    let mut items: Vec<String> = Vec::new();
+    for line in paragraph.lines().map(str::trim) {
+        let bullet = ["- ", "* ", "• "].iter().any(|m| line.starts_with(m));
+        match items.last_mut() {
+            Some(item) if !bullet => {
+                item.push(' ');
+                item.push_str(line);
+            }
+            _ => items.push(line.to_string()),
+        }
+    }
+    items
+        .iter()
+        .map(|item| {
+            let indent = match ["- ", "* ", "• "].iter().any(|m| item.starts_with(m)) {
+                true => "  ",
+                false => "",
+            };
+            wrap(item, indent)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn wrap(text: &str, indent: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > BODY_WIDTH {
+            lines.push(std::mem::take(&mut line));
+            line.push_str(indent);
+        } else if !line.is_empty() && !line.ends_with(' ') {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    lines.push(line);
+    lines.join("\n")
+}
+
+/// The footers passed on the command line: `Refs:` for issues and tickets, and one
+/// `Co-authored-by:` per co-author.
+pub fn passthrough_footers(refs: &[&str], co_authors: &[&str]) -> Vec<String> {
+    let mut footers = Vec::new();
+    if !refs.is_empty() {
+        footers.push(format!("Refs: {}", refs.join(", ")));
+    }
+    footers.extend(
+        co_authors
+            .iter()
+            .map(|author| format!("Co-authored-by: {}", author)),
+    );
+    footers
+}
+
+/// A commit message split along the Conventional Commits spec.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConventionalCommit {
+    pub kind: String,
+    pub scope: Option<String>,
+    pub breaking: bool,
+    pub description: String,
+    pub body: Option<String>,
+}
+
+/// Why a commit message does not follow Conventional Commits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    Empty,
+    /// The subject is not `type(scope): description`.
+    NoHeader(String),
+    /// The type of the subject and the types accepted.
+    UnknownType(String, Vec<String>),
+    EmptyDescription,
+    /// The length of the subject and the longest one accepted.
+    SubjectTooLong(usize, usize),
+    TrailingPeriod,
+    /// The body starts right after the subject, without a blank line.
+    NoBlankLine,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Empty => write!(f, "the message is empty"),
+            Violation::NoHeader(subject) => write!(
+                f,
+                "the subject `{}` is not in the form `type(scope): description`",
+                subject
+            ),
+            Violation::UnknownType(kind, types) => write!(
+                f,
+                "`{}` is not a commit type, use one of {}",
+                kind,
+                types.join(", ")
+            ),
+            Violation::EmptyDescription => write!(f, "the subject has no description"),
+            Violation::SubjectTooLong(length, max_length) => write!(
+                f,
+                "the subject is {} characters long, keep it within {}",
+                length, max_length
+            ),
+            Violation::TrailingPeriod => write!(f, "the subject ends with a period"),
+            Violation::NoBlankLine => write!(
+                f,
+                "the body must be separated from the subject by a blank line"
+            ),
+        }
+    }
+}
+
+/// Matches `type(scope)!: description` subjects, in any case.
+pub fn header_regex() -> &'static Regex {
+    static HEADER: OnceLock<Regex> = OnceLock::new();
+    HEADER.get_or_init(|| {
+        Regex::new(r"^(?P<kind>[A-Za-z]+)(?:\((?P<scope>[^()\s][^()]*)\))?(?P<breaking>!)?: ?(?P<description>.*)$")
+            .unwrap()
+    })
+}
+
+/// Parses a commit message whose type is one of `types` and whose subject is at most
+/// `max_length` characters long, or returns the first rule it breaks.
+pub fn parse_commit(
+    message: &str,
+    types: &[&str],
+    max_length: usize,
+) -> Result<ConventionalCommit, Violation> {
+    let message = message.trim();
+    let mut lines = message.lines();
+    let subject = lines
+        .next()
+        .filter(|s| !s.trim().is_empty())
+        .ok_or(Violation::Empty)?;
+    let captures = header_regex()
+        .captures(subject)
+        .filter(|c| subject[c["kind"].len()..].contains(": "))
+        .ok_or_else(|| Violation::NoHeader(subject.to_string()))?;
+    let kind = captures["kind"].to_string();
+    if !types.contains(&kind.as_str()) {
+        let types = types.iter().map(|kind| kind.to_string()).collect();
+        return Err(Violation::UnknownType(kind, types));
+    }
+    let description = captures["description"].trim().to_string();
+    if description.is_empty() {
+        return Err(Violation::EmptyDescription);
+    }
+    if description.ends_with('.') {
+        return Err(Violation::TrailingPeriod);
+    }
+    let length = subject.chars().count();
+    if length > max_length {
+        return Err(Violation::SubjectTooLong(length, max_length));
+    }
+    if lines.next().is_some_and(|line| !line.trim().is_empty()) {
+        return Err(Violation::NoBlankLine);
+    }
+    let body = lines.collect::<Vec<&str>>().join("\n");
+    let breaking_footer = body
+        .lines()
+        .any(|line| line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:"));
+    Ok(ConventionalCommit {
+        scope: captures.name("scope").map(|s| s.as_str().to_string()),
+        breaking: captures.name("breaking").is_some() || breaking_footer,
+        kind,
+        description,
+        body: Some(body.trim().to_string()).filter(|b| !b.is_empty()),
+    })
+}
+
+/// Strips what models put around a commit message, whatever its style: code fences, a
+/// preamble like "Here is your commit message:", and quotes or bold markers around it.
+pub fn clean_commit_message(answer: &str) -> String {
+    let answer = answer.trim();
+    let answer = match split_code_block(answer) {
+        Some((_, code)) if !code.is_empty() => code,
+        _ => answer.to_string(),
+    };
+    static LABEL: OnceLock<Regex> = OnceLock::new();
+    let label = LABEL.get_or_init(|| {
+        Regex::new(r"(?i)^\s*(\*\*)?(suggested |proposed )?commit message(\*\*)?:(\*\*)?\s*")
+            .unwrap()
+    });
+    // A fence left unclosed is not a code block.
+    let mut lines = answer
+        .lines()
+        .map(|l| l.trim_end())
+        .filter(|l| !l.trim_start().starts_with("```"))
+        .skip_while(|l| l.trim().is_empty())
+        .collect::<Vec<&str>>();
+    while lines.len() > 1 && is_preamble(lines[0]) {
+        lines.remove(0);
+        while lines.first().is_some_and(|l| l.trim().is_empty()) {
+            lines.remove(0);
+        }
+    }
+    let message = lines.join("\n");
+    let message = label.replace(message.trim(), "");
+    let message = unquote(message.trim());
+    let (subject, rest) = message.split_once('\n').unwrap_or((message, ""));
+    let subject = unquote(
+        subject
+            .trim()
+            .trim_start_matches("**")
+            .trim_end_matches("**"),
+    );
+    format!("{}\n{}", subject, rest).trim().to_string()
+}
+
+/// Whether a line is what models say before the message, like "Sure!" or "Here is a
+/// commit message for these changes:". Subjects never end with a colon.
+fn is_preamble(line: &str) -> bool {
+    static PREAMBLE: OnceLock<Regex> = OnceLock::new();
+    let preamble = PREAMBLE.get_or_init(|| {
+        Regex::new(r"(?i)^((sure|certainly|of course|okay|ok|absolutely)[!,.]|(here's|here is|here are|below is|based on|i've|i have|i would|i suggest)\b)")
+            .unwrap()
+    });
+    let line = line.trim().trim_matches('*').trim();
+    line.ends_with(':') || preamble.is_match(line)
+}
+
+/// `message` from its conventional subject on, dropping a preamble the cleaning did not
+/// recognize. The subject is looked for in the first paragraph, or after preamble lines
+/// only, and its type must be one of `types`, so that a body line or a footer like
+/// `Refs: #12` is never taken for it.
+fn from_header(message: &str, types: &[&str]) -> String {
+    let mut lines = message.lines().collect::<Vec<&str>>();
+    let mut start = None;
+    for (i, line) in lines.iter().enumerate() {
+        let line = unquote(line.trim());
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(captures) = header_regex().captures(line) {
+            if types.contains(&canonical_type(&captures["kind"]).as_str()) {
+                start = Some(i);
+                break;
+            }
+        }
+        // The first paragraph that is not a preamble is the last one searched.
+        let last_line = lines.get(i + 1).is_none_or(|next| next.trim().is_empty());
+        if last_line && !is_preamble(line) {
+            break;
+        }
+    }
+    match start {
+        Some(start) => {
+            lines[start] = unquote(lines[start].trim());
+            lines[start..].join("\n")
+        }
+        None => message.to_string(),
+    }
+}
+
+/// A commit type in lowercase, with its common misspellings fixed.
+fn canonical_type(kind: &str) -> String {
+    let kind = kind.to_lowercase();
+    TYPE_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == kind)
+        .map(|(_, kind)| kind.to_string())
+        .unwrap_or(kind)
+}
+
+/// `text` without the quotes or backticks around it, if it has them.
+fn unquote(text: &str) -> &str {
+    for quote in ["\"", "'", "`"] {
+        if text.len() > 1 && text.starts_with(quote) && text.ends_with(quote) {
+            return &text[1..text.len() - 1];
+        }
+    }
+    text
+}
+
+/// Repairs what can be fixed without the model: the casing and misspellings of the type,
+/// a final period, a missing blank line before the body and the casing of a
+/// `BREAKING CHANGE:` footer.
+pub fn repair_commit_message(message: &str) -> String {
+    let message = message.trim();
+    let (subject, rest) = message.split_once('\n').unwrap_or((message, ""));
+    let mut subject = subject.trim().to_string();
+    if let Some(captures) = header_regex().captures(&subject) {
+        let kind = canonical_type(&captures["kind"]);
+        let scope = captures
+            .name("scope")
+            .map(|s| format!("({})", s.as_str().trim()))
+            .unwrap_or_default();
+        let breaking = captures.name("breaking").map(|_| "!").unwrap_or_default();
+        let description = captures["description"].trim();
+        let description = description
+            .strip_suffix('.')
+            .filter(|d| !d.ends_with('.'))
+            .unwrap_or(description);
+        subject = format!("{}{}{}: {}", kind, scope, breaking, description);
+    }
+    static BREAKING: OnceLock<Regex> = OnceLock::new();
+    let breaking = BREAKING.get_or_init(|| Regex::new(r"(?im)^breaking[ -]change:").unwrap());
+    let rest = breaking.replace_all(rest.trim(), "BREAKING CHANGE:");
+    match rest.is_empty() {
+        true => subject,
+        false => format!("{}\n\n{}", subject, rest),
+    }
+}
+
+/// Cleans and repairs a generated commit message, asking the model to fix it with the rule
+/// it breaks when it cannot be repaired. The types and the subject length accepted are the
+/// ones of `commit_style`. Returns the message and the rule it still breaks, if any.
+pub async fn conventional_commit(
+    shared_state: &SharedState,
+    answer: &str,
+    commit_style: &CommitStyle,
+) -> Result<(String, Option<Violation>), CopilotError> {
+    let types = commit_style.accepted_types();
+    let max_length = commit_style.subject_length();
+    let mut message = repair_commit_message(&from_header(&clean_commit_message(answer), &types));
+    for _ in 0..MAX_REPAIRS {
+        let Err(violation) = parse_commit(&message, &types, max_length) else {
+            return Ok((message, None));
+        };
+        let chain = revise_commit_chain(
+            shared_state.llm(),
+            shared_state.prompt(REVISE_COMMIT_PROMPT.name),
+        );
+        let revised = invoke_chain(
+            shared_state,
+            &chain,
+            prompt_args! {
+                "to" => format!("follow the Conventional Commits format, {}", violation),
+                "commit" => message
+            },
+        )
+        .await?;
+        message = repair_commit_message(&from_header(&clean_commit_message(&revised), &types));
+    }
+    let violation = parse_commit(&message, &types, max_length).err();
+    Ok((message, violation))
+}
+
+/// Prepares a generated message for the menu: cleaned, held to Conventional Commits when
+/// the repository follows them or sets `conventional = true`, then formatted with `footers`.
+/// A rule the message still breaks is reported in text mode.
+pub async fn finish_commit_message(
+    shared_state: &SharedState,
+    answer: &str,
+    commit_style: &CommitStyle,
+    footers: &[String],
+) -> Result<String, CopilotError> {
+    let conventional = shared_state
+        .project()
+        .commit
+        .conventional
+        .unwrap_or(commit_style.is_conventional());
+    let message = match conventional {
+        true => {
+            let (message, violation) =
+                conventional_commit(shared_state, answer, commit_style).await?;
+            if let Some(violation) =
+                violation.filter(|_| shared_state.format() == OutputFormat::Text)
+            {
+                println!(
+                    "{} the commit message does not follow Conventional Commits, {}\n",
+                    style("Warning:").yellow().bold(),
+                    violation
+                );
+            }
+            message
+        }
+        false => clean_commit_message(answer),
+    };
+    Ok(format_commit_message(&message, footers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subjects_are_held_to_the_given_length() {
+        let subject = format!("feat: {}", "a".repeat(74));
+        assert_eq!(
+            parse_commit(&subject, &COMMIT_TYPES, MAX_SUBJECT_LENGTH),
+            Err(Violation::SubjectTooLong(80, 72))
+        );
+        assert!(parse_commit(&subject, &COMMIT_TYPES, 100).is_ok());
+        assert_eq!(
+            parse_commit("fix: handle the empty input case", &COMMIT_TYPES, 20),
+            Err(Violation::SubjectTooLong(32, 20))
+        );
+    }
+
+    #[test]
+    fn unknown_types_list_the_accepted_ones() {
+        let style = learned(&[
+            "deps: bump serde",
+            "deps: bump tokio",
+            "feat: add the export menu",
+            "fix: handle empty input",
+            "deps: bump regex",
+        ]);
+        let types = style.accepted_types();
+        assert!(parse_commit("deps: bump clap", &types, 72).is_ok());
+        let violation = parse_commit("wip: try things", &types, 72).unwrap_err();
+        assert!(violation.to_string().ends_with(", deps"));
+        assert!(violation.to_string().contains("feat, fix"));
+    }
+
+    fn learned(subjects: &[&str]) -> CommitStyle {
+        let messages = subjects
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+        CommitStyle::from_messages(&messages)
+    }
+
+    #[test]
+    fn gitmoji_messages_lose_their_preamble() {
+        let style = learned(&[
+            "✨ Add the export menu",
+            "🐛 Fix the crash on empty input",
+            "📝 Document the config file",
+            "♻️ Split the parser",
+            "✅ Test the exporter",
+        ]);
+        assert!(!style.is_conventional());
+        assert_eq!(
+            clean_commit_message(
+                "Sure! Here is a commit message for these changes:\n\n```\n✨ Add dark mode\n\nRefs: #12\n```"
+            ),
+            "✨ Add dark mode\n\nRefs: #12"
+        );
+        assert_eq!(
+            clean_commit_message("**Commit message:**\n✨ Add dark mode"),
+            "✨ Add dark mode"
+        );
+    }
+
+    #[test]
+    fn ticket_messages_lose_their_preamble() {
+        let style = learned(&[
+            "ABC-1: Add the export menu",
+            "ABC-2: Fix the crash on empty input",
+            "ABC-3: Document the config file",
+            "ABC-4: Split the parser",
+            "ABC-5: Test the exporter",
+        ]);
+        assert!(!style.is_conventional());
+        assert_eq!(
+            clean_commit_message("Based on the diff, I suggest\n\n```text\nABC-7: Add dark mode"),
+            "ABC-7: Add dark mode"
+        );
+        assert_eq!(
+            clean_commit_message("ABC-7: Add dark mode\n\nNote: the theme is stored per user"),
+            "ABC-7: Add dark mode\n\nNote: the theme is stored per user"
+        );
+    }
+
+    #[test]
+    fn conventional_messages_start_at_their_header() {
+        assert_eq!(
+            from_header(
+                &clean_commit_message(
+                    "The change adds caching.\n\"feat(cache): store answers\"\n\nKeyed by prompt."
+                ),
+                &COMMIT_TYPES
+            ),
+            "feat(cache): store answers\n\nKeyed by prompt."
+        );
+    }
+
+    #[test]
+    fn footers_are_not_taken_for_the_subject() {
+        let message = clean_commit_message("Add caching layer\n\nRefs: #12");
+        assert_eq!(from_header(&message, &COMMIT_TYPES), message);
+        assert_eq!(
+            repair_commit_message(&from_header(&message, &COMMIT_TYPES)),
+            "Add caching layer\n\nRefs: #12"
+        );
+        let message = "Add caching layer\n\nThe cache is keyed by prompt.\nfix: the old one leaked";
+        assert_eq!(from_header(message, &COMMIT_TYPES), message);
+        assert_eq!(
+            from_header("Note: this adds caching\nfeat: add caching", &COMMIT_TYPES),
+            "feat: add caching"
+        );
+    }
+}
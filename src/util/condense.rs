@@ -0,0 +1,396 @@
+use std::cmp::Reverse;
+
+use dialoguer::console::style;
+use langchain_rust::prompt_args;
+use serde::Serialize;
+
+use crate::{
+    chains::{summarize_diff_chain, SUMMARIZE_DIFF_PROMPT},
+    error::CopilotError,
+};
+
+use super::{
+    git::{FileDiff, FileStatus, StagedDiff},
+    shared::SharedState,
+    stream::invoke_chain,
+    tokens::{diff_budget, estimate_tokens},
+};
+
+const LOCK_FILES: [&str; 13] = [
+    "Cargo.lock",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "bun.lockb",
+    "poetry.lock",
+    "Pipfile.lock",
+    "uv.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "go.sum",
+    "flake.lock",
+];
+
+const VENDORED_DIRS: [&str; 4] = ["vendor", "node_modules", "third_party", ".yarn"];
+
+const GENERATED_DIRS: [&str; 1] = ["dist"];
+
+const GENERATED_SUFFIXES: [&str; 7] = [
+    ".min.js", ".min.css", ".map", ".pb.go", "_pb2.py", ".g.dart", ".snap",
+];
+
+const SEPARATOR: &str = "\n---------------------------\n";
+
+/// A file of the staged diff that is not sent whole, and what was sent instead.
+#[derive(Debug, Clone, Serialize)]
+pub struct Condensed {
+    pub path: String,
+    pub how: String,
+}
+
+/// The staged diff as it is sent to the model, within the token budget.
+pub struct CondensedDiff {
+    pub text: String,
+    pub condensed: Vec<Condensed>,
+}
+
+/// A file as it is sent to the model.
+struct Piece<'a> {
+    file: &'a FileDiff,
+    text: String,
+    tokens: usize,
+    how: Option<String>,
+    /// Whether its contents are still sent, summarized or not.
+    shrinkable: bool,
+}
+
+impl Piece<'_> {
+    fn replace(&mut self, provider: &str, note: &str, how: String) {
+        self.text = format!("{}\n  {}\n", self.file.header(), note);
+        self.tokens = estimate_tokens(provider, &self.text);
+        self.how = Some(how);
+    }
+}
+
+/// Fits the staged diff in the token budget of the model. Lock, vendored and generated files
+/// are left out, whitespace-only and rename-only changes are collapsed. When the diff is
+/// still too large, the largest files are summarized one by one by the model, and past
+/// that their contents are left out, keeping only their names.
+pub async fn condense_diff(
+    shared_state: &SharedState,
+    diff: &StagedDiff,
+) -> Result<CondensedDiff, CopilotError> {
+    let provider = shared_state.provider();
+    let budget = diff_budget(shared_state);
+    let mut pieces = diff
+        .files
+        .iter()
+        .map(|file| {
+            let text = file.render();
+            let mut piece = Piece {
+                file,
+                tokens: estimate_tokens(provider, &text),
+                text,
+                how: None,
+                shrinkable: !file.hunks.is_empty(),
+            };
+            match (generated_kind(&file.path), file.status) {
+                (Some(kind), _) if piece.shrinkable => {
+                    piece.replace(
+                        provider,
+                        &format!("contents left out, {}", kind),
+                        format!("{}, contents left out", kind),
+                    );
+                    piece.shrinkable = false;
+                }
+                _ if file.whitespace_only() => {
+                    piece.replace(
+                        provider,
+                        "whitespace-only changes",
+                        "whitespace-only changes, collapsed".to_string(),
+                    );
+                    piece.shrinkable = false;
+                }
+                (_, FileStatus::Renamed | FileStatus::Copied) if file.hunks.is_empty() => {
+                    piece.text = format!("{}\n  content unchanged\n", file.header());
+                    piece.tokens = estimate_tokens(provider, &piece.text);
+                }
+                _ => {}
+            }
+            piece
+        })
+        .collect::<Vec<Piece>>();
+
+    let total = |pieces: &[Piece]| pieces.iter().map(|p| p.tokens).sum::<usize>();
+    let mut largest = (0..pieces.len()).collect::<Vec<usize>>();
+    largest.sort_by_key(|&i| Reverse(pieces[i].tokens));
+
+    // Map: the largest files are summarized until the diff fits.
+    if total(&pieces) > budget {
+        let chain = summarize_diff_chain(
+            shared_state.llm(),
+            shared_state.prompt(SUMMARIZE_DIFF_PROMPT.name),
+        );
+        for &i in &largest {
+            if total(&pieces) <= budget {
+                break;
+            }
+            let piece = &mut pieces[i];
+            if !piece.shrinkable {
+                continue;
+            }
+            let (text, cut) = truncate(provider, &piece.text, budget);
+            let summary =
+                invoke_chain(shared_state, &chain, prompt_args! { "diff" => text }).await?;
+            let how = match cut {
+                true => format!("summarized from its first {} tokens", budget),
+                false => format!("summarized, {} tokens", piece.tokens),
+            };
+            piece.replace(provider, &format!("summary: {}", summary.trim()), how);
+        }
+    }
+
+    // Past that, the largest files are sent by name only.
+    for &i in &largest {
+        if total(&pieces) <= budget {
+            break;
+        }
+        if pieces[i].shrinkable {
+            pieces[i].replace(
+                provider,
+                "contents left out, over the token budget",
+                "contents left out, over the token budget".to_string(),
+            );
+            pieces[i].shrinkable = false;
+        }
+    }
+
+    let mut condensed = pieces
+        .iter()
+        .filter_map(|piece| {
+            Some(Condensed {
+                path: piece.file.path.clone(),
+                how: piece.how.clone()?,
+            })
+        })
+        .collect::<Vec<Condensed>>();
+    let text = pieces
+        .iter()
+        .map(|piece| piece.text.as_str())
+        .collect::<Vec<&str>>()
+        .join(SEPARATOR);
+    // Even the names alone may not fit.
+    let (text, cut) = truncate(provider, &text, budget);
+    if cut {
+        condensed.push(Condensed {
+            path: "the remaining files".to_string(),
+            how: format!("left out, the diff was cut at {} tokens", budget),
+        });
+    }
+    Ok(CondensedDiff { text, condensed })
+}
+
+/// Why the contents of a file are not worth sending, if they are not.
+fn generated_kind(path: &str) -> Option<&'static str> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let in_dir = |dirs: &[&str]| {
+        path.split('/')
+            .rev()
+            .skip(1)
+            .any(|component| dirs.contains(&component))
+    };
+    if LOCK_FILES.contains(&name) {
+        Some("lock file")
+    } else if in_dir(&VENDORED_DIRS) {
+        Some("vendored file")
+    } else if in_dir(&GENERATED_DIRS) || GENERATED_SUFFIXES.iter().any(|s| name.ends_with(s)) {
+        Some("generated file")
+    } else {
+        None
+    }
+}
+
+/// The start of `text` that fits in `budget` tokens, cut at a line end, and whether it was
+/// cut.
+fn truncate(provider: &str, text: &str, budget: usize) -> (String, bool) {
+    let tokens = estimate_tokens(provider, text);
+    if tokens <= budget {
+        return (text.to_string(), false);
+    }
+    let chars = text.chars().count() * budget / tokens;
+    let start = text.chars().take(chars).collect::<String>();
+    let start = match start.rsplit_once('\n') {
+        Some((start, _)) => start.to_string(),
+        None => start,
+    };
+    (start, true)
+}
+
+pub fn print_condensed(condensed: &[Condensed]) {
+    println!(
+        "{} some staged changes are not sent whole:",
+        style("Warning:").yellow().bold()
+    );
+    for file in condensed {
+        println!("  - {}: {}", style(&file.path).bold(), file.how);
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{
+        git::Hunk,
+        llm::LLMVariant,
+        mock::{Fixture, MockLLM},
+        project::ProjectConfig,
+    };
+
+    fn file(path: &str, lines: &[&str]) -> FileDiff {
+        FileDiff {
+            path: path.to_string(),
+            old_path: None,
+            status: FileStatus::Modified,
+            binary: false,
+            additions: lines.iter().filter(|l| l.starts_with('+')).count(),
+            deletions: lines.iter().filter(|l| l.starts_with('-')).count(),
+            hunks: vec![Hunk {
+                header: "@@ -1,1 +1,1 @@".to_string(),
+                lines: lines.iter().map(|l| l.to_string()).collect(),
+            }],
+        }
+    }
+
+    fn added(path: &str, count: usize) -> FileDiff {
+        let lines = (0..count)
+            .map(|i| format!("+let value_{} = compute({});", i, i))
+            .collect::<Vec<String>>();
+        file(
+            path,
+            &lines.iter().map(String::as_str).collect::<Vec<&str>>(),
+        )
+    }
+
+    /// A mock provider answering every summary with `summary`, and a diff budget of
+    /// `budget` tokens.
+    fn shared_state(dir: &std::path::Path, summary: &str, budget: usize) -> SharedState {
+        let path = dir.join("fx.toml");
+        Fixture {
+            default: Some(summary.to_string()),
+            responses: Vec::new(),
+        }
+        .save(&path)
+        .unwrap();
+        let mut project = ProjectConfig::default();
+        project.commit.max_diff_tokens = Some(budget);
+        SharedState::new(
+            LLMVariant::Mock(MockLLM::new(path)),
+            "linux",
+            "mock",
+            "mock",
+        )
+        .with_stream(false)
+        .with_project(project)
+    }
+
+    fn how(condensed: &CondensedDiff) -> Vec<(&str, &str)> {
+        condensed
+            .condensed
+            .iter()
+            .map(|c| (c.path.as_str(), c.how.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn lock_vendored_and_generated_files_are_recognized() {
+        assert_eq!(generated_kind("Cargo.lock"), Some("lock file"));
+        assert_eq!(generated_kind("web/yarn.lock"), Some("lock file"));
+        assert_eq!(
+            generated_kind("vendor/github.com/x/y.go"),
+            Some("vendored file")
+        );
+        assert_eq!(
+            generated_kind("app/node_modules/left-pad/index.js"),
+            Some("vendored file")
+        );
+        assert_eq!(generated_kind("dist/app.js"), Some("generated file"));
+        assert_eq!(generated_kind("static/app.min.js"), Some("generated file"));
+        assert_eq!(generated_kind("src/vendor.rs"), None);
+        assert_eq!(generated_kind("src/Cargo.lock.rs"), None);
+        assert_eq!(generated_kind("src/main.rs"), None);
+    }
+
+    #[test]
+    fn truncate_cuts_at_a_line_end() {
+        let text = "first line\nsecond line\nthird line\n";
+        assert_eq!(truncate("openai", text, 100), (text.to_string(), false));
+        let (start, cut) = truncate("openai", text, 5);
+        assert!(cut);
+        assert_eq!(start, "first line");
+        assert!(estimate_tokens("openai", &start) <= 5);
+    }
+
+    #[tokio::test]
+    async fn small_diffs_are_sent_whole_but_for_generated_and_whitespace_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared_state = shared_state(dir.path(), "unused", 10_000);
+        let diff = StagedDiff {
+            files: vec![
+                file("src/main.rs", &["-fn main() {}", "+fn main() { run() }"]),
+                file("src/lib.rs", &["-fn run() {", "+fn run()  {", "+", " }"]),
+                added("Cargo.lock", 20),
+            ],
+        };
+        let condensed = condense_diff(&shared_state, &diff).await.unwrap();
+        assert_eq!(
+            how(&condensed),
+            [
+                ("src/lib.rs", "whitespace-only changes, collapsed"),
+                ("Cargo.lock", "lock file, contents left out")
+            ]
+        );
+        assert!(condensed.text.contains("+fn main() { run() }"));
+        assert!(!condensed.text.contains("value_0"));
+    }
+
+    #[tokio::test]
+    async fn the_largest_files_are_summarized_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared_state = shared_state(dir.path(), "Adds values.", 300);
+        let diff = StagedDiff {
+            files: vec![
+                added("src/small.rs", 2),
+                added("src/large.rs", 60),
+                added("src/medium.rs", 20),
+            ],
+        };
+        let condensed = condense_diff(&shared_state, &diff).await.unwrap();
+        let how = how(&condensed);
+        assert_eq!(how.len(), 1);
+        assert_eq!(how[0].0, "src/large.rs");
+        assert!(how[0].1.starts_with("summarized"));
+        assert!(condensed.text.contains("summary: Adds values."));
+        assert!(condensed.text.contains("value_19"));
+    }
+
+    #[tokio::test]
+    async fn files_are_left_out_when_summaries_are_not_enough() {
+        let dir = tempfile::tempdir().unwrap();
+        let long_summary = "Adds many values. ".repeat(30);
+        let shared_state = shared_state(dir.path(), &long_summary, 200);
+        let diff = StagedDiff {
+            files: vec![added("src/large.rs", 60), added("src/medium.rs", 40)],
+        };
+        let condensed = condense_diff(&shared_state, &diff).await.unwrap();
+        assert_eq!(
+            how(&condensed),
+            [
+                ("src/large.rs", "contents left out, over the token budget"),
+                ("src/medium.rs", "summarized from its first 200 tokens")
+            ]
+        );
+        assert!(estimate_tokens("mock", &condensed.text) <= 200);
+    }
+}
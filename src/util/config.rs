@@ -1,11 +1,136 @@
-use std::{fs::File, io::Write};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
-use dialoguer::{console::style, theme::ColorfulTheme, Input, Select};
+use dialoguer::{console::style, theme::ColorfulTheme, Confirm, Input, Select};
 use serde::{Deserialize, Serialize};
 
-use crate::COPILOT_PATH;
+use crate::{error::CopilotError, COPILOT_PATH};
 
-#[derive(Debug, Serialize, Deserialize)]
+use super::{
+    cache::CacheConfig, environment::EnvironmentConfig, safety::SafetyConfig, shared::expand_home,
+};
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The whole configuration file: named provider profiles, the default one, and the
+/// profile each subcommand uses.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ConfigFile {
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub commands: BTreeMap<String, String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, LLMConfig>,
+    /// Prompt templates by chain name.
+    #[serde(default)]
+    pub prompts: BTreeMap<String, String>,
+    #[serde(default)]
+    pub safety: SafetyConfig,
+    /// Overrides of the detected environment.
+    #[serde(default)]
+    pub environment: EnvironmentConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Repositories whose `.copilot-cli.toml` may change the provider, endpoint, key and
+    /// fixture, by the path of their root.
+    #[serde(default)]
+    pub trusted_repositories: Vec<String>,
+}
+
+impl ConfigFile {
+    pub fn path() -> Option<PathBuf> {
+        let mut config_path = home::home_dir()?;
+        config_path.push(COPILOT_PATH);
+        Some(config_path)
+    }
+
+    /// Reads the config file, if there is one. A file holding a single flat provider record,
+    /// as written by older versions, is read as the `default` profile.
+    pub fn load() -> Result<Option<Self>, CopilotError> {
+        let Some(path) = ConfigFile::path() else {
+            return Ok(None);
+        };
+        let Ok(config_contents) = std::fs::read_to_string(&path) else {
+            return Ok(None);
+        };
+        let invalid = |e: toml::de::Error| {
+            CopilotError::Config(format!(
+                "Error reading {}, fix it or run `config` to write a new one: {}",
+                path.display(),
+                e
+            ))
+        };
+        let value: toml::Value = toml::from_str(&config_contents).map_err(invalid)?;
+
+        let config = if value.get("llm_type").is_some() {
+            value.try_into::<LLMConfig>().map(|profile| ConfigFile {
+                default_profile: Some(DEFAULT_PROFILE.to_string()),
+                commands: BTreeMap::new(),
+                profiles: BTreeMap::from([(DEFAULT_PROFILE.to_string(), profile)]),
+                prompts: BTreeMap::new(),
+                safety: SafetyConfig::default(),
+                environment: EnvironmentConfig::default(),
+                cache: CacheConfig::default(),
+                trusted_repositories: Vec::new(),
+            })
+        } else {
+            value.try_into::<ConfigFile>()
+        };
+        config.map(Some).map_err(invalid)
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let toml_string = toml::to_string_pretty(&self).map_err(std::io::Error::other)?;
+        let config_path = ConfigFile::path().ok_or(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "Home directory not found",
+        ))?;
+
+        let mut file = File::create(config_path)?;
+        file.write_all(toml_string.as_bytes())?;
+        Ok(())
+    }
+
+    /// Whether the repository at `root` is listed in `trusted_repositories`.
+    pub fn trusts(&self, root: &Path) -> bool {
+        let canonical = |path: &Path| path.canonicalize().unwrap_or(path.to_path_buf());
+        let root = canonical(root);
+        self.trusted_repositories
+            .iter()
+            .any(|path| canonical(&expand_home(path)) == root)
+    }
+
+    /// Picks the profile for a run: the `--profile` flag first, then the profile mapped to
+    /// the subcommand, then the default profile.
+    pub fn resolve(
+        &self,
+        profile: Option<&str>,
+        command: Option<&str>,
+    ) -> Result<Option<(String, LLMConfig)>, CopilotError> {
+        let name = profile
+            .map(|p| p.to_string())
+            .or_else(|| command.and_then(|c| self.commands.get(c).cloned()))
+            .or_else(|| self.default_profile.clone())
+            .unwrap_or(DEFAULT_PROFILE.to_string());
+
+        match self.profiles.get(&name) {
+            Some(cfg) => Ok(Some((name, cfg.clone()))),
+            None if profile.is_some() || self.commands.values().any(|p| p == &name) => {
+                Err(CopilotError::Config(format!(
+                    "Profile '{}' not found, create it with `config --profile {}`",
+                    name, name
+                )))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LLMConfig {
     pub llm_type: String,
     pub api_key: Option<String>,
@@ -13,6 +138,10 @@ pub struct LLMConfig {
     pub api_version: Option<String>,
     pub deployment: Option<String>,
     pub model: Option<String>,
+    /// Replay file for the `mock` provider. On any other provider, answers are recorded into it.
+    pub fixture: Option<String>,
+    /// Print answers token by token as they arrive, enabled by default.
+    pub stream: Option<bool>,
 }
 
 impl LLMConfig {
@@ -24,6 +153,8 @@ impl LLMConfig {
             api_version: None,
             deployment: None,
             model: Some("llama2".to_string()),
+            fixture: None,
+            stream: None,
         }
     }
 
@@ -35,6 +166,8 @@ impl LLMConfig {
             api_version: Some("2024-02-15-preview".to_string()),
             deployment: Some("chatGPT_GPT35-turbo-0301".to_string()),
             model: Some("chatGPT_GPT35-turbo-0301".to_string()),
+            fixture: None,
+            stream: None,
         }
     }
 
@@ -46,6 +179,8 @@ impl LLMConfig {
             api_version: None,
             deployment: None,
             model: Some("gpt-3.5-turbo".to_string()),
+            fixture: None,
+            stream: None,
         }
     }
 
@@ -57,11 +192,35 @@ impl LLMConfig {
             api_version: None,
             deployment: None,
             model: Some("claude-3-opus-20240229".to_string()),
+            fixture: None,
+            stream: None,
+        }
+    }
+
+    pub fn new_mock() -> Self {
+        LLMConfig {
+            llm_type: "mock".to_string(),
+            api_key: None,
+            api_base: None,
+            api_version: None,
+            deployment: None,
+            model: None,
+            fixture: Some("~/.free_copilot_fixture.toml".to_string()),
+            stream: None,
         }
     }
 
-    pub async fn new_config() {
-        let opciones = vec!["OpenAI", "Ollama", "Anthropic", "AzureOpenAI", "Exit"];
+    /// Runs the provider wizard and stores the result as `profile`, asking for a name when
+    /// none is given. `commands` are mapped to the profile.
+    pub async fn new_config(profile: Option<&str>, commands: &[&str]) -> Result<(), CopilotError> {
+        let opciones = [
+            "OpenAI",
+            "Ollama",
+            "Anthropic",
+            "AzureOpenAI",
+            "Mock",
+            "Exit",
+        ];
 
         let instrucciones = style("[Use arrows to move, type to filter]")
             .yellow()
@@ -72,10 +231,10 @@ impl LLMConfig {
             .with_prompt(&prompt)
             .default(0)
             .items(&opciones[..])
-            .interact()
-            .unwrap();
+            .interact_opt()?
+            .ok_or(CopilotError::Cancelled)?;
 
-        match seleccion {
+        let config = match seleccion {
             0 => {
                 let mut config = LLMConfig::new_openai();
 
@@ -83,8 +242,7 @@ impl LLMConfig {
                 let model_input: String = Input::new()
                     .with_prompt(model_prompt)
                     .default("gpt-3.5-turbo".into())
-                    .interact_text()
-                    .unwrap();
+                    .interact_text()?;
                 config.model = Some(model_input);
 
                 let key_prompt =
@@ -92,15 +250,12 @@ impl LLMConfig {
                 let key: String = Input::new()
                     .with_prompt(key_prompt)
                     .default("None".into())
-                    .interact_text()
-                    .unwrap();
+                    .interact_text()?;
                 if key != "None" {
                     config.api_key = Some(key);
                 }
 
-                save_config(&config)
-                    .map_err(|e| eprintln!("{}", e))
-                    .unwrap();
+                config
             }
             1 => {
                 let mut config = LLMConfig::new_ollama();
@@ -108,16 +263,14 @@ impl LLMConfig {
                 let model_input: String = Input::new()
                     .with_prompt(model_prompt)
                     .default("llama2".into())
-                    .interact_text()
-                    .unwrap();
+                    .interact_text()?;
                 config.model = Some(model_input);
 
                 let key_prompt = "Enter your OpenAi key (press Enter for use default 'ollama'):";
                 let key: String = Input::new()
                     .with_prompt(key_prompt)
                     .default("ollama".into())
-                    .interact_text()
-                    .unwrap();
+                    .interact_text()?;
                 config.api_key = Some(key);
 
                 let base_prompt =
@@ -125,13 +278,10 @@ impl LLMConfig {
                 let base: String = Input::new()
                     .with_prompt(base_prompt)
                     .default("http://localhost:11434/v1".into())
-                    .interact_text()
-                    .unwrap();
+                    .interact_text()?;
                 config.api_base = Some(base);
 
-                save_config(&config)
-                    .map_err(|e| eprintln!("{}", e))
-                    .unwrap();
+                config
             }
             2 => {
                 let mut config = LLMConfig::new_anthropic();
@@ -140,22 +290,18 @@ impl LLMConfig {
                 let model_input: String = Input::new()
                     .with_prompt(model_prompt)
                     .default("claude-3-opus-20240229".into())
-                    .interact_text()
-                    .unwrap();
+                    .interact_text()?;
                 config.model = Some(model_input);
 
                 let key_prompt =
-                    "Enter your Anthropic key (press Enter for use your env $CLOUDE_API_KEY):";
+                    "Enter your Anthropic key (press Enter for use your env $CLAUDE_API_KEY):";
                 let key: String = Input::new()
                     .with_prompt(key_prompt)
-                    .default("ollama".into())
-                    .interact_text()
-                    .unwrap();
-                config.api_key = Some(key);
+                    .allow_empty(true)
+                    .interact_text()?;
+                config.api_key = Some(key.trim().to_string()).filter(|key| !key.is_empty());
 
-                save_config(&config)
-                    .map_err(|e| eprintln!("{}", e))
-                    .unwrap();
+                config
             }
             3 => {
                 let mut config = LLMConfig::new_azure_openai();
@@ -163,57 +309,77 @@ impl LLMConfig {
                 let model_input: String = Input::new()
                     .with_prompt(model_prompt)
                     .default("chatGPT_GPT35-turbo-0301".into())
-                    .interact_text()
-                    .unwrap();
+                    .interact_text()?;
 
                 let api_key_prompt = "Enter your Azure OpenAi key";
                 let api_key: String = Input::new()
                     .with_prompt(api_key_prompt)
                     .default("REPLACE_ME_WITH_YOUR_API_KEY".into())
-                    .interact_text()
-                    .unwrap();
+                    .interact_text()?;
 
                 let api_version_prompt =
                     "Enter the api version (press Enter for default '2024-02-15-preview'):";
                 let api_version: String = Input::new()
                     .with_prompt(api_version_prompt)
                     .default("2024-02-15-preview".into())
-                    .interact_text()
-                    .unwrap();
+                    .interact_text()?;
 
                 let api_base_prompt =
                     "Enter the base URL (press Enter for default 'https://your-resource-name.openai.azure.com'):";
                 let api_base: String = Input::new()
                     .with_prompt(api_base_prompt)
                     .default("https://your-resource-name.openai.azure.com".into())
-                    .interact_text()
-                    .unwrap();
+                    .interact_text()?;
 
                 config.deployment = Some(model_input);
                 config.api_key = Some(api_key);
                 config.api_version = Some(api_version);
                 config.api_base = Some(api_base);
 
-                save_config(&config)
-                    .map_err(|e| eprintln!("{}", e))
-                    .unwrap();
+                config
+            }
+            4 => {
+                let mut config = LLMConfig::new_mock();
+                let fixture_prompt =
+                    "Enter the fixture file (press Enter for default '~/.free_copilot_fixture.toml'):";
+                let fixture: String = Input::new()
+                    .with_prompt(fixture_prompt)
+                    .default("~/.free_copilot_fixture.toml".into())
+                    .interact_text()?;
+                config.fixture = Some(fixture);
+
+                config
             }
-            4 => std::process::exit(0),
-            _ => eprintln!("Invalid option."),
+            5 => return Ok(()),
+            _ => {
+                eprintln!("Invalid option.");
+                return Ok(());
+            }
+        };
+
+        let mut file = ConfigFile::load()?.unwrap_or_default();
+        let name = match profile {
+            Some(profile) => profile.to_string(),
+            None => Input::new()
+                .with_prompt("Enter the profile name (press Enter for 'default'):")
+                .default(DEFAULT_PROFILE.to_string())
+                .interact_text()?,
+        };
+        let make_default = file.default_profile.is_none()
+            || file.default_profile.as_deref() == Some(name.as_str())
+            || Confirm::new()
+                .with_prompt(format!("Use '{}' as the default profile?", name))
+                .default(false)
+                .interact()?;
+        if make_default {
+            file.default_profile = Some(name.clone());
         }
-    }
-}
+        for command in commands {
+            file.commands.insert(command.to_string(), name.clone());
+        }
+        file.profiles.insert(name, config);
 
-pub fn save_config(config: &LLMConfig) -> std::io::Result<()> {
-    let toml_string = toml::to_string_pretty(&config)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-    let mut config_path = home::home_dir().ok_or(std::io::Error::new(
-        std::io::ErrorKind::NotFound,
-        "Home directory not found",
-    ))?;
-    config_path.push(COPILOT_PATH);
-
-    let mut file = File::create(config_path)?;
-    file.write_all(toml_string.as_bytes())?;
-    Ok(())
+        file.save()?;
+        Ok(())
+    }
 }
@@ -0,0 +1,272 @@
+use std::{
+    fmt,
+    path::Path,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use dialoguer::console::style;
+use regex::Regex;
+
+use super::{
+    availability::{invocations, programs},
+    environment::find_program,
+    process::capture,
+};
+
+/// How long `<program> --help` may take before it is killed.
+const HELP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How many lines of the documentation are kept for each option.
+const MAX_OPTION_LINES: usize = 4;
+
+/// Where the documentation of a program comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocSource {
+    Man,
+    Help,
+}
+
+impl fmt::Display for DocSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DocSource::Man => write!(f, "man page"),
+            DocSource::Help => write!(f, "--help"),
+        }
+    }
+}
+
+/// The local documentation of a program.
+#[derive(Debug, Clone)]
+pub struct ProgramDocs {
+    pub program: String,
+    pub source: DocSource,
+    pub text: String,
+}
+
+impl ProgramDocs {
+    /// Reads the man page of a program installed on `PATH`, or its `--help` output without
+    /// one. Programs given as a path, like `./install.sh`, are the user's own code and are
+    /// never looked up, since a script ignoring `--help` would do its real work.
+    pub fn lookup(program: &str) -> Option<Self> {
+        if program.contains('/') {
+            return None;
+        }
+        let path = find_program(program)?;
+        let (source, text) = match man_page(program) {
+            Some(text) => (DocSource::Man, text),
+            None => (DocSource::Help, help_text(&path)?),
+        };
+        Some(ProgramDocs {
+            program: program.to_string(),
+            source,
+            text,
+        })
+    }
+
+    /// Whether `option` is mentioned in the documentation.
+    pub fn mentions(&self, option: &str) -> bool {
+        option_regex(option).is_match(&self.text)
+    }
+
+    /// The one line summary of the program, from the NAME section of a man page or the
+    /// first line of `--help` after its usage.
+    fn summary(&self) -> Option<String> {
+        let mut lines = self.text.lines().map(str::trim).filter(|l| !l.is_empty());
+        let line = match self.source {
+            DocSource::Man => lines.by_ref().find(|l| *l == "NAME").and(lines.next()),
+            DocSource::Help => {
+                let usage =
+                    |l: &&str| l.to_lowercase().starts_with("usage") || l.starts_with("or:");
+                let mut lines = lines.peekable();
+                let first = lines.peek().copied();
+                lines.find(|l| !usage(l)).or(first)
+            }
+        };
+        line.map(|l| l.to_string())
+    }
+
+    /// The entry of `option` in the documentation: the line introducing it and the
+    /// description under it.
+    fn entry(&self, option: &str) -> Option<String> {
+        let re = option_regex(option);
+        let lines = self.text.lines().collect::<Vec<&str>>();
+        let start = lines.iter().position(|line| {
+            let line = line.trim_start();
+            // Only lines listing options, e.g. `-a, --all  do not ignore entries...`.
+            line.starts_with('-') && re.is_match(line.split("  ").next().unwrap_or(line))
+        })?;
+        let indent = indentation(lines[start]);
+        let mut entry = vec![lines[start].trim()];
+        for line in lines.iter().skip(start + 1) {
+            if line.trim().is_empty()
+                || line.trim_start().starts_with('-')
+                || indentation(line) <= indent
+                || entry.len() == MAX_OPTION_LINES
+            {
+                break;
+            }
+            entry.push(line.trim());
+        }
+        Some(entry.join(" "))
+    }
+}
+
+/// The documentation of every program in a command.
+#[derive(Debug, Clone, Default)]
+pub struct Documentation {
+    command: String,
+    pub programs: Vec<ProgramDocs>,
+}
+
+impl Documentation {
+    pub fn lookup(command: &str) -> Self {
+        Documentation {
+            command: command.to_string(),
+            programs: programs(command)
+                .iter()
+                .filter_map(|p| ProgramDocs::lookup(p))
+                .collect(),
+        }
+    }
+
+    /// The summary of each program and the entries of the options the command uses, for
+    /// the prompts.
+    pub fn describe(&self) -> String {
+        if self.programs.is_empty() {
+            return "No local documentation was found.".to_string();
+        }
+        let mut lines = Vec::new();
+        for docs in &self.programs {
+            lines.push(format!(
+                "{} ({}): {}",
+                docs.program,
+                docs.source,
+                docs.summary().unwrap_or_default()
+            ));
+            let used = invocations(&self.command)
+                .into_iter()
+                .filter(|words| words[0] == docs.program)
+                .flat_map(|words| words.into_iter().skip(1))
+                .flat_map(|word| options(&word, |o| docs.mentions(o)))
+                .fold(Vec::new(), |mut acc: Vec<String>, option| {
+                    if !acc.contains(&option) {
+                        acc.push(option);
+                    }
+                    acc
+                });
+            for option in used {
+                match docs.entry(&option) {
+                    Some(entry) => lines.push(format!("  {}", entry)),
+                    None => lines.push(format!("  {}: not in the local documentation", option)),
+                }
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// The options an explanation mentions in backticks that none of the documentation
+    /// has. Nothing is reported when no documentation was found.
+    pub fn undocumented(&self, explanation: &str) -> Vec<String> {
+        if self.programs.is_empty() {
+            return Vec::new();
+        }
+        let re = Regex::new(r"`(--?[A-Za-z0-9][\w-]*)[^`]*`").unwrap(); //safe to unwrap
+        let mut undocumented: Vec<String> = Vec::new();
+        for cap in re.captures_iter(explanation) {
+            let documented = |o: &str| self.programs.iter().any(|d| d.mentions(o));
+            for option in options(&cap[1], documented) {
+                if !documented(&option) && !undocumented.contains(&option) {
+                    undocumented.push(option);
+                }
+            }
+        }
+        undocumented
+    }
+}
+
+/// Prints a warning about options missing from the local documentation.
+pub fn print_undocumented(options: &[String]) {
+    println!(
+        "{} not in the local documentation, check them before relying on the explanation: {}\n",
+        style("Warning:").yellow().bold(),
+        options.join(", ")
+    );
+}
+
+/// The options of a word: `--color=auto` is `--color`, and `-la` is `-l` and `-a` unless
+/// `documented` knows `-la` as a single option, like `find -name`.
+fn options(word: &str, documented: impl Fn(&str) -> bool) -> Vec<String> {
+    let option = word.split('=').next().unwrap_or(word);
+    if !option.starts_with('-') || option == "-" || option == "--" {
+        return Vec::new();
+    }
+    let combined = !option.starts_with("--")
+        && option.len() > 2
+        && option[1..].chars().all(|c| c.is_ascii_alphabetic());
+    match combined && !documented(option) {
+        true => option[1..].chars().map(|c| format!("-{}", c)).collect(),
+        false => vec![option.to_string()],
+    }
+}
+
+fn option_regex(option: &str) -> Regex {
+    //safe to unwrap, the option is escaped
+    Regex::new(&format!(r"(^|[^\w-]){}($|[^\w-])", regex::escape(option))).unwrap()
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// The man page of a program as plain text.
+fn man_page(program: &str) -> Option<String> {
+    let output = Command::new("man")
+        .arg(program)
+        .env("MANPAGER", "cat")
+        .env("MANWIDTH", "100")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    // Bold and underlined text is printed with backspaces.
+    let re = Regex::new(".\x08").unwrap(); //safe to unwrap
+    let text = re.replace_all(&text, "").to_string();
+    (output.status.success() && !text.trim().is_empty()).then_some(text)
+}
+
+/// The `--help` output of the program at `path`, given up on after `HELP_TIMEOUT`.
+fn help_text(path: &Path) -> Option<String> {
+    let output = capture(Command::new(path).arg("--help"), HELP_TIMEOUT)?;
+    // Some programs print their usage to stderr, or only there.
+    let text = match output.stdout {
+        text if text.trim().is_empty() => output.stderr,
+        text => text,
+    };
+    (!text.trim().is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn local_scripts_are_never_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let ran = dir.path().join("ran");
+        let script = dir.path().join("install.sh");
+        fs::write(&script, format!("#!/bin/sh\ntouch '{}'\n", ran.display())).unwrap();
+        let mut perms = fs::metadata(&script).unwrap().permissions();
+        std::os::unix::fs::PermissionsExt::set_mode(&mut perms, 0o755);
+        fs::set_permissions(&script, perms).unwrap();
+
+        assert!(ProgramDocs::lookup(&script.display().to_string()).is_none());
+        let documentation = Documentation::lookup(&format!("{} --force", script.display()));
+        assert!(documentation.programs.is_empty());
+        assert!(!ran.exists());
+    }
+}
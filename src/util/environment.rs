@@ -0,0 +1,211 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Tools worth telling the model about when they are on `PATH`.
+const TOOLS: [&str; 30] = [
+    "git",
+    "gh",
+    "docker",
+    "podman",
+    "kubectl",
+    "helm",
+    "terraform",
+    "aws",
+    "gcloud",
+    "az",
+    "jq",
+    "yq",
+    "curl",
+    "wget",
+    "rg",
+    "fd",
+    "fzf",
+    "bat",
+    "python3",
+    "node",
+    "npm",
+    "cargo",
+    "go",
+    "java",
+    "make",
+    "systemctl",
+    "gawk",
+    "gsed",
+    "rsync",
+    "tmux",
+];
+
+/// Package managers in the order they are looked for.
+const PACKAGE_MANAGERS: [&str; 10] = [
+    "apt", "dnf", "yum", "pacman", "apk", "zypper", "emerge", "brew", "port", "pkg",
+];
+
+/// Settings that replace what is detected, e.g. inside containers or remote shells.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct EnvironmentConfig {
+    pub shell: Option<String>,
+    pub distro: Option<String>,
+    pub package_manager: Option<String>,
+    /// `GNU`, `BSD` or `BusyBox`.
+    pub coreutils: Option<String>,
+    pub tools: Option<Vec<String>>,
+    /// Anything else the model should know, e.g. "use doas instead of sudo".
+    pub notes: Option<String>,
+}
+
+impl EnvironmentConfig {
+    /// Overlays the settings of `other` on these ones.
+    pub fn merge(self, other: &EnvironmentConfig) -> Self {
+        let other = other.clone();
+        EnvironmentConfig {
+            shell: other.shell.or(self.shell),
+            distro: other.distro.or(self.distro),
+            package_manager: other.package_manager.or(self.package_manager),
+            coreutils: other.coreutils.or(self.coreutils),
+            tools: other.tools.or(self.tools),
+            notes: other.notes.or(self.notes),
+        }
+    }
+}
+
+/// What the suggested and explained commands run on.
+#[derive(Debug, Clone, Serialize)]
+pub struct Environment {
+    pub os: String,
+    pub shell: Option<String>,
+    pub distro: Option<String>,
+    pub package_manager: Option<String>,
+    pub coreutils: Option<String>,
+    pub tools: Vec<String>,
+    pub notes: Option<String>,
+}
+
+impl Environment {
+    /// Inspects the current machine, keeping every value set in `config` instead.
+    pub fn detect(os: &str, config: &EnvironmentConfig) -> Self {
+        Environment {
+            os: os.to_string(),
+            shell: config.shell.clone().or_else(detect_shell),
+            distro: config.distro.clone().or_else(|| detect_distro(os)),
+            package_manager: config.package_manager.clone().or_else(|| {
+                PACKAGE_MANAGERS
+                    .iter()
+                    .find(|p| find_program(p).is_some())
+                    .map(|p| p.to_string())
+            }),
+            coreutils: config.coreutils.clone().or_else(|| detect_coreutils(os)),
+            tools: config.tools.clone().unwrap_or_else(|| {
+                TOOLS
+                    .iter()
+                    .filter(|t| find_program(t).is_some())
+                    .map(|t| t.to_string())
+                    .collect()
+            }),
+            notes: config.notes.clone(),
+        }
+    }
+
+    /// A description of the environment for the prompts.
+    pub fn describe(&self) -> String {
+        let mut lines = vec![format!(
+            "Operating system: {}{}",
+            self.os,
+            self.distro
+                .as_ref()
+                .map(|d| format!(" ({})", d))
+                .unwrap_or_default()
+        )];
+        if let Some(shell) = &self.shell {
+            lines.push(format!("Shell: {}", shell));
+        }
+        if let Some(package_manager) = &self.package_manager {
+            lines.push(format!("Package manager: {}", package_manager));
+        }
+        if let Some(coreutils) = &self.coreutils {
+            lines.push(format!("Core utilities: {}", coreutils));
+        }
+        if !self.tools.is_empty() {
+            lines.push(format!("Installed tools: {}", self.tools.join(", ")));
+        }
+        if let Some(notes) = &self.notes {
+            lines.push(format!("Notes: {}", notes));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Resolves a program the way the shell does, on `PATH` unless it is a path itself.
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return is_file(&path).then_some(path);
+    }
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| is_file(candidate))
+}
+
+fn is_file(path: &Path) -> bool {
+    path.metadata().map(|m| m.is_file()).unwrap_or(false)
+}
+
+/// The shell of `$SHELL` with its version, e.g. `fish 3.6.1`.
+fn detect_shell() -> Option<String> {
+    let shell = env::var("SHELL").ok().filter(|s| !s.is_empty())?;
+    let name = Path::new(&shell).file_name()?.to_string_lossy().to_string();
+    let version = Command::new(&shell)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| {
+            let text = String::from_utf8_lossy(&output.stdout).to_string();
+            let re = Regex::new(r"\d+\.\d+(\.\d+)?").unwrap(); //safe to unwrap
+            re.find(&text).map(|m| m.as_str().to_string())
+        });
+    Some(match version {
+        Some(version) => format!("{} {}", name, version),
+        None => name,
+    })
+}
+
+fn detect_distro(os: &str) -> Option<String> {
+    if os == "macos" {
+        let output = Command::new("sw_vers")
+            .arg("-productVersion")
+            .output()
+            .ok()?;
+        let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        return Some(format!("macOS {}", version));
+    }
+    let release = fs::read_to_string("/etc/os-release").ok()?;
+    release
+        .lines()
+        .find_map(|line| line.strip_prefix("PRETTY_NAME="))
+        .map(|name| name.trim_matches('"').to_string())
+}
+
+/// Tells GNU, BusyBox and BSD userlands apart, which take different flags.
+fn detect_coreutils(os: &str) -> Option<String> {
+    let output = Command::new("ls").arg("--version").output().ok()?;
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    if text.contains("BusyBox") {
+        Some("BusyBox".to_string())
+    } else if output.status.success() && text.contains("GNU") {
+        Some("GNU".to_string())
+    } else if os == "macos" || os.ends_with("bsd") {
+        Some("BSD".to_string())
+    } else {
+        None
+    }
+}
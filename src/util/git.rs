@@ -0,0 +1,382 @@
+use std::{
+    env, fmt,
+    io::Write,
+    path::Path,
+    process::{Command, ExitStatus},
+};
+
+use git2::{Delta, DiffFindOptions, Patch, Repository};
+use regex::Regex;
+use serde::Serialize;
+use tempfile::Builder;
+
+use crate::error::CopilotError;
+
+/// How a file changed in the staged diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+    Copied,
+    /// The file became a symlink or a submodule, or stopped being one.
+    TypeChange,
+}
+
+impl fmt::Display for FileStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileStatus::Added => write!(f, "added"),
+            FileStatus::Modified => write!(f, "modified"),
+            FileStatus::Deleted => write!(f, "deleted"),
+            FileStatus::Renamed => write!(f, "renamed"),
+            FileStatus::Copied => write!(f, "copied"),
+            FileStatus::TypeChange => write!(f, "type changed"),
+        }
+    }
+}
+
+/// A block of changed lines, each keeping its `+`, `-` or ` ` marker.
+#[derive(Debug, Clone, Serialize)]
+pub struct Hunk {
+    /// The `@@ -1,3 +1,4 @@` line, with the enclosing function when git finds one.
+    pub header: String,
+    pub lines: Vec<String>,
+}
+
+/// The staged changes of a single file.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileDiff {
+    pub path: String,
+    /// The path before a rename or the source of a copy.
+    pub old_path: Option<String>,
+    pub status: FileStatus,
+    pub binary: bool,
+    pub additions: usize,
+    pub deletions: usize,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FileDiff {
+    /// The line naming the file, with its status and line counts.
+    pub fn header(&self) -> String {
+        let mut status = self.status.to_string();
+        if let Some(old_path) = &self.old_path {
+            status = format!("{} from {}", status, old_path);
+        }
+        let counts = match self.binary {
+            true => "binary".to_string(),
+            false => format!("+{} -{}", self.additions, self.deletions),
+        };
+        format!("name: {} ({}, {})", self.path, status, counts)
+    }
+
+    /// The file as it is shown to the model: its header, then its hunks.
+    pub fn render(&self) -> String {
+        let mut rendered = format!("{}\n", self.header());
+        for hunk in &self.hunks {
+            rendered.push_str(&hunk.header);
+            rendered.push('\n');
+            for line in &hunk.lines {
+                rendered.push_str(line);
+                rendered.push('\n');
+            }
+        }
+        rendered
+    }
+
+    /// Whether the file changed only in whitespace: the removed and the added lines hold the
+    /// same text once every space, tab and newline is left out.
+    pub fn whitespace_only(&self) -> bool {
+        let text = |marker: char| {
+            self.hunks
+                .iter()
+                .flat_map(|hunk| &hunk.lines)
+                .filter_map(|line| line.strip_prefix(marker))
+                .flat_map(|line| line.chars().filter(|c| !c.is_whitespace()))
+                .collect::<String>()
+        };
+        !self.binary && self.additions + self.deletions > 0 && text('-') == text('+')
+    }
+}
+
+/// The changes staged for the next commit, file by file.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StagedDiff {
+    pub files: Vec<FileDiff>,
+}
+
+impl StagedDiff {
+    /// Reads the staged changes of the repository containing the current directory, against
+    /// `HEAD` or against nothing before the first commit. Files matching one of `excludes`
+    /// are left out.
+    pub fn load(excludes: &[Exclude]) -> Result<Self, CopilotError> {
+        let repo = Repository::discover(".")
+            .map_err(|_| CopilotError::Git("Not inside a git repository".to_string()))?;
+        let head = repo.head().ok().and_then(|head| head.peel_to_tree().ok());
+        let mut diff = repo.diff_tree_to_index(head.as_ref(), None, None)?;
+        diff.find_similar(Some(DiffFindOptions::new().renames(true).copies(true)))?;
+
+        let mut files = Vec::new();
+        for index in 0..diff.deltas().len() {
+            let Some(mut patch) = Patch::from_diff(&diff, index)? else {
+                continue;
+            };
+            let file = file_diff(&mut patch)?;
+            if !excludes.iter().any(|exclude| exclude.matches(&file.path)) {
+                files.push(file);
+            }
+        }
+        Ok(StagedDiff { files })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Every file as it is shown to the model, separated by a line of dashes.
+    pub fn render(&self) -> String {
+        self.files
+            .iter()
+            .map(|file| file.render())
+            .collect::<Vec<String>>()
+            .join("\n---------------------------\n")
+    }
+}
+
+/// Files left out of the commit diff: a regular expression matching whole paths, as
+/// `Cargo.lock` or `.*\.snap`, or a directory, excluding everything inside it.
+#[derive(Debug, Clone)]
+pub struct Exclude(Regex);
+
+impl Exclude {
+    /// A pattern from the repository config, relative to the repository root.
+    pub fn from_root(pattern: &str) -> Result<Self, CopilotError> {
+        Exclude::new("", pattern).map_err(|e| {
+            CopilotError::Config(format!(
+                "Invalid `excluded` pattern `{}` in .copilot-cli.toml: {}",
+                pattern, e
+            ))
+        })
+    }
+
+    /// A pattern from the command line, relative to the current directory.
+    pub fn from_cwd(pattern: &str) -> Result<Self, CopilotError> {
+        let cwd = env::current_dir()?;
+        Exclude::new(&prefix(&cwd)?, pattern).map_err(|e| {
+            CopilotError::Usage(format!("Invalid `--excluded` pattern `{}`: {}", pattern, e))
+        })
+    }
+
+    /// `pattern` relative to `prefix`, the path of its directory from the repository root
+    /// with a trailing `/`. Leading `./` and `../` are resolved against the prefix.
+    fn new(prefix: &str, pattern: &str) -> Result<Self, regex::Error> {
+        let mut dirs = prefix.split_terminator('/').collect::<Vec<&str>>();
+        let mut pattern = pattern.trim_end_matches('/');
+        loop {
+            if let Some(rest) = pattern.strip_prefix("./") {
+                pattern = rest;
+            } else if let Some(rest) = pattern.strip_prefix("../") {
+                dirs.pop();
+                pattern = rest;
+            } else {
+                break;
+            }
+        }
+        let prefix = dirs
+            .iter()
+            .map(|dir| format!("{}/", dir))
+            .collect::<String>();
+        Regex::new(&format!(
+            "^{}(?:{})(?:/.*)?$",
+            regex::escape(&prefix),
+            pattern
+        ))
+        .map(Exclude)
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        self.0.is_match(path)
+    }
+}
+
+/// The path of `dir` from the root of its repository, with a trailing `/`, or nothing at
+/// the root.
+fn prefix(dir: &Path) -> Result<String, CopilotError> {
+    let repo = Repository::discover(dir)
+        .map_err(|_| CopilotError::Git("Not inside a git repository".to_string()))?;
+    let root = repo
+        .workdir()
+        .ok_or_else(|| CopilotError::Git("The repository has no working tree".to_string()))?
+        .canonicalize()?;
+    let dir = dir.canonicalize()?;
+    let relative = dir.strip_prefix(&root).unwrap_or(Path::new(""));
+    Ok(relative
+        .components()
+        .map(|dir| format!("{}/", dir.as_os_str().to_string_lossy()))
+        .collect())
+}
+
+/// Commits the staged changes with `message`, passed to `git commit -F` through a new
+/// temporary file so that quotes, newlines and `$` reach git untouched. Runs the git binary
+/// rather than libgit2 so that hooks and commit signing still apply.
+pub fn commit(message: &str) -> Result<ExitStatus, CopilotError> {
+    let mut file = Builder::new()
+        .prefix("copilot-commit-")
+        .suffix(".txt")
+        .tempfile()?;
+    writeln!(file, "{}", message.trim())?;
+    Command::new("git")
+        .args(["commit", "--cleanup=whitespace", "-F"])
+        .arg(file.path())
+        .status()
+        .map_err(|e| CopilotError::Git(format!("Could not run git: {}", e)))
+}
+
+/// The messages of the last `limit` commits reachable from `HEAD`, newest first, merges left
+/// out. Empty before the first commit.
+pub fn recent_messages(limit: usize) -> Result<Vec<String>, CopilotError> {
+    let repo = Repository::discover(".")
+        .map_err(|_| CopilotError::Git("Not inside a git repository".to_string()))?;
+    let mut revwalk = repo.revwalk()?;
+    if revwalk.push_head().is_err() {
+        return Ok(Vec::new());
+    }
+    let mut messages = Vec::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() > 1 {
+            continue;
+        }
+        if let Some(message) = commit.message() {
+            messages.push(message.trim().to_string());
+        }
+        if messages.len() == limit {
+            break;
+        }
+    }
+    Ok(messages)
+}
+
+/// The name of the checked out branch, if any.
+pub fn branch_name() -> Option<String> {
+    let repo = Repository::discover(".").ok()?;
+    let head = repo.head().ok()?;
+    match head.is_branch() {
+        true => head.shorthand().map(|name| name.to_string()),
+        false => None,
+    }
+}
+
+fn file_diff(patch: &mut Patch) -> Result<FileDiff, CopilotError> {
+    let delta = patch.delta();
+    let path_of = |path: Option<&Path>| path.map(|p| p.to_string_lossy().to_string());
+    let new_path = path_of(delta.new_file().path());
+    let old_path = path_of(delta.old_file().path());
+    let status = match delta.status() {
+        Delta::Added | Delta::Untracked => FileStatus::Added,
+        Delta::Deleted => FileStatus::Deleted,
+        Delta::Renamed => FileStatus::Renamed,
+        Delta::Copied => FileStatus::Copied,
+        Delta::Typechange => FileStatus::TypeChange,
+        _ => FileStatus::Modified,
+    };
+    let binary = delta.flags().is_binary();
+    let (path, old_path) = match status {
+        FileStatus::Renamed | FileStatus::Copied => (new_path, old_path),
+        FileStatus::Deleted => (old_path, None),
+        _ => (new_path.or(old_path), None),
+    };
+
+    let mut hunks = Vec::new();
+    for hunk_index in 0..patch.num_hunks() {
+        let (hunk, line_count) = patch.hunk(hunk_index)?;
+        let mut lines = Vec::with_capacity(line_count);
+        for line_index in 0..line_count {
+            let line = patch.line_in_hunk(hunk_index, line_index)?;
+            // The "No newline at end of file" markers are left out.
+            if matches!(line.origin(), '+' | '-' | ' ') {
+                let content = String::from_utf8_lossy(line.content());
+                lines.push(format!(
+                    "{}{}",
+                    line.origin(),
+                    content.trim_end_matches('\n')
+                ));
+            }
+        }
+        hunks.push(Hunk {
+            header: String::from_utf8_lossy(hunk.header())
+                .trim_end()
+                .to_string(),
+            lines,
+        });
+    }
+    let (_, additions, deletions) = patch.line_stats()?;
+
+    Ok(FileDiff {
+        path: path.unwrap_or_default(),
+        old_path,
+        status,
+        binary,
+        additions,
+        deletions,
+        hunks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn excludes_match_whole_paths_from_the_root() {
+        let exclude = Exclude::from_root("Cargo.lock").unwrap();
+        assert!(exclude.matches("Cargo.lock"));
+        assert!(!exclude.matches("sub/Cargo.lock"));
+        assert!(!exclude.matches("Cargo.lock.bak"));
+    }
+
+    #[test]
+    fn excludes_are_regular_expressions() {
+        let exclude = Exclude::from_root(r".*\.snap").unwrap();
+        assert!(exclude.matches("a.snap"));
+        assert!(exclude.matches("tests/snapshots/b.snap"));
+        assert!(!exclude.matches("a.snapshot"));
+        let exclude = Exclude::from_root("(package|yarn)-lock.json").unwrap();
+        assert!(exclude.matches("package-lock.json"));
+        assert!(!exclude.matches("web/yarn-lock.json"));
+        assert!(Exclude::from_root("(unclosed").is_err());
+    }
+
+    #[test]
+    fn excluded_directories_cover_their_files() {
+        let exclude = Exclude::from_root("dist/").unwrap();
+        assert!(exclude.matches("dist/bundle.js"));
+        assert!(exclude.matches("dist/css/site.css"));
+        assert!(!exclude.matches("distribution/x"));
+    }
+
+    #[test]
+    fn excludes_from_a_subdirectory_are_relative_to_it() {
+        let exclude = Exclude::new("src/util/", "mod.rs").unwrap();
+        assert!(exclude.matches("src/util/mod.rs"));
+        assert!(!exclude.matches("mod.rs"));
+        let exclude = Exclude::new("src/util/", "./gen/.*").unwrap();
+        assert!(exclude.matches("src/util/gen/a.rs"));
+        let exclude = Exclude::new("src/util/", "../../README.md").unwrap();
+        assert!(exclude.matches("README.md"));
+        assert!(!exclude.matches("src/README.md"));
+    }
+
+    #[test]
+    fn prefix_is_the_path_from_the_root() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("src").join("util");
+        std::fs::create_dir_all(&dir).unwrap();
+        Repository::init(root.path()).unwrap();
+        assert_eq!(prefix(root.path()).unwrap(), "");
+        assert_eq!(prefix(&dir).unwrap(), "src/util/");
+    }
+}
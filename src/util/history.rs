@@ -0,0 +1,245 @@
+use std::{
+    collections::BTreeSet,
+    fmt, fs, io,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use dialoguer::console::style;
+use serde::{Deserialize, Serialize};
+
+use super::shared::{expand_home, SharedState};
+
+/// File holding one JSON entry per line for every answer generated.
+pub const HISTORY_PATH: &str = "~/.free_copilot_history.jsonl";
+
+/// How many entries are kept, the oldest ones are dropped first.
+const MAX_ENTRIES: usize = 1000;
+
+/// How alike two requests must be, by the share of words they have in common, for a past
+/// answer to be offered again.
+const SIMILARITY: f64 = 0.8;
+
+/// What the user did with an answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Copied,
+    Executed,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Copied => write!(f, "copied"),
+            Action::Executed => write!(f, "executed"),
+        }
+    }
+}
+
+/// An answer generated for a request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    /// The subcommand, e.g. `suggest`.
+    pub command: String,
+    /// The chain that generated the answer, e.g. `recommend_git`.
+    pub chain: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    pub output: String,
+    pub provider: String,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<Action>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_status: Option<i32>,
+}
+
+impl Entry {
+    /// Whether the answer is a command that can be run again.
+    pub fn is_command(&self) -> bool {
+        matches!(self.command.as_str(), "suggest" | "fix" | "revise")
+    }
+
+    /// What was done with the answer, e.g. `executed, exit 0`.
+    pub fn outcome(&self) -> Option<String> {
+        let action = self.action?;
+        Some(match (action, self.exit_status) {
+            (Action::Executed, Some(status)) => format!("{}, exit {}", action, status),
+            _ => action.to_string(),
+        })
+    }
+
+    /// How long ago the answer was generated, e.g. `3h ago`.
+    pub fn age(&self) -> String {
+        let seconds = now().saturating_sub(self.time);
+        match seconds {
+            s if s < 60 => "just now".to_string(),
+            s if s < 3600 => format!("{}m ago", s / 60),
+            s if s < 86400 => format!("{}h ago", s / 3600),
+            s => format!("{}d ago", s / 86400),
+        }
+    }
+
+    /// One line describing the entry, for listings.
+    pub fn summary(&self, styled: bool) -> String {
+        let first_line = |text: &str| text.lines().next().unwrap_or_default().to_string();
+        let input = self.input.as_deref().map(first_line).unwrap_or_default();
+        let output = first_line(&self.output);
+        let outcome = self
+            .outcome()
+            .map(|o| format!(" ({})", o))
+            .unwrap_or_default();
+        match styled {
+            true => format!(
+                "{} {} {} {} {} {}{}",
+                style(format!("{:>4}", self.id)).cyan().bold(),
+                style(format!("{:>8}", self.age())).dim(),
+                style(&self.command).bold(),
+                input,
+                style("→").dim(),
+                style(output).yellow(),
+                style(outcome).dim()
+            ),
+            false => format!(
+                "{}\t{}\t{}\t{}\t{}",
+                self.id, self.time, self.command, input, output
+            ),
+        }
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        self.output.to_lowercase().contains(&query)
+            || self
+                .input
+                .as_ref()
+                .is_some_and(|i| i.to_lowercase().contains(&query))
+    }
+}
+
+fn history_file() -> PathBuf {
+    expand_home(HISTORY_PATH)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Every entry, oldest first. Lines that do not parse are skipped.
+pub fn load() -> Vec<Entry> {
+    fs::read_to_string(history_file())
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+fn store(entries: &[Entry]) -> io::Result<()> {
+    let entries = &entries[entries.len().saturating_sub(MAX_ENTRIES)..];
+    let lines = entries
+        .iter()
+        //safe to unwrap, entries are always serializable
+        .map(|e| serde_json::to_string(e).unwrap() + "\n")
+        .collect::<String>();
+    fs::write(history_file(), lines)
+}
+
+/// Records an answer. The history is best effort, failing to write it does not fail the run.
+pub fn record(
+    shared_state: &SharedState,
+    command: &str,
+    chain: &str,
+    input: Option<&str>,
+    output: &str,
+) {
+    let mut entries = load();
+    let id = entries.last().map(|e| e.id + 1).unwrap_or(1);
+    entries.push(Entry {
+        id,
+        time: now(),
+        command: command.to_string(),
+        chain: chain.to_string(),
+        input: input.map(|i| i.to_string()),
+        output: output.trim().to_string(),
+        provider: shared_state.provider().to_string(),
+        model: shared_state.model().to_string(),
+        profile: shared_state.profile().map(|p| p.to_string()),
+        action: None,
+        exit_status: None,
+    });
+    let _ = store(&entries);
+}
+
+/// Records what was done with the latest answer equal to `output`. Running it wins over
+/// copying it.
+pub fn mark(output: &str, action: Action, exit_status: Option<i32>) {
+    let mut entries = load();
+    let Some(entry) = entries.iter_mut().rev().find(|e| e.output == output.trim()) else {
+        return;
+    };
+    if entry.action == Some(Action::Executed) && action == Action::Copied {
+        return;
+    }
+    entry.action = Some(action);
+    entry.exit_status = exit_status;
+    let _ = store(&entries);
+}
+
+pub fn find(id: u64) -> Option<Entry> {
+    load().into_iter().find(|e| e.id == id)
+}
+
+/// The entries whose request or answer contains `query`, newest first.
+pub fn search(query: &str) -> Vec<Entry> {
+    load()
+        .into_iter()
+        .rev()
+        .filter(|e| e.matches(query))
+        .collect()
+}
+
+/// Deletes the history and returns how many entries it had.
+pub fn clear() -> io::Result<usize> {
+    let count = load().len();
+    match fs::remove_file(history_file()) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(count),
+    }
+}
+
+/// The latest answer of `chain` to a request like `input`, unless running it failed.
+pub fn similar(chain: &str, input: &str) -> Option<Entry> {
+    let words = words(input);
+    load().into_iter().rev().find(|e| {
+        e.chain == chain
+            && !matches!(e.exit_status, Some(status) if status != 0)
+            && e.input
+                .as_deref()
+                .is_some_and(|i| similarity(&words, &self::words(i)) >= SIMILARITY)
+    })
+}
+
+fn words(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// The share of words two requests have in common.
+fn similarity(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    let union = a.union(b).count();
+    match union {
+        0 => 0.0,
+        n => a.intersection(b).count() as f64 / n as f64,
+    }
+}
@@ -9,11 +9,15 @@ use langchain_rust::{
     tools::OpenAIConfig,
 };
 
+use super::{cache::CachedLLM, mock::MockLLM};
+
 #[derive(Clone)]
 pub enum LLMVariant {
     OpenAI(OpenAI<OpenAIConfig>),
     AzureOpenAI(OpenAI<AzureConfig>),
     Anthropic(Claude),
+    Mock(MockLLM),
+    Cached(CachedLLM),
 }
 
 #[async_trait]
@@ -23,6 +27,8 @@ impl LLM for LLMVariant {
             LLMVariant::OpenAI(llm) => llm.generate(messages).await,
             LLMVariant::Anthropic(llm) => llm.generate(messages).await,
             LLMVariant::AzureOpenAI(llm) => llm.generate(messages).await,
+            LLMVariant::Mock(llm) => llm.generate(messages).await,
+            LLMVariant::Cached(llm) => llm.generate(messages).await,
         }
     }
 
@@ -34,6 +40,8 @@ impl LLM for LLMVariant {
             LLMVariant::OpenAI(llm) => llm.stream(_messages).await,
             LLMVariant::Anthropic(llm) => llm.stream(_messages).await,
             LLMVariant::AzureOpenAI(llm) => llm.stream(_messages).await,
+            LLMVariant::Mock(llm) => llm.stream(_messages).await,
+            LLMVariant::Cached(llm) => llm.stream(_messages).await,
         }
     }
 }
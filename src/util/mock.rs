@@ -0,0 +1,265 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use futures::{future, stream, Stream, StreamExt};
+use langchain_rust::{
+    language_models::{llm::LLM, GenerateResult, LLMError},
+    schemas::{Message, StreamData},
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::llm::LLMVariant;
+
+/// Canned answers keyed by a regex over the rendered prompt.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Fixture {
+    pub default: Option<String>,
+    #[serde(default)]
+    pub responses: Vec<FixtureEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureEntry {
+    pub pattern: String,
+    pub response: String,
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> Result<Self, LLMError> {
+        if !path.exists() {
+            return Ok(Fixture::default());
+        }
+        let contents = fs::read_to_string(path)?;
+        toml::from_str(&contents)
+            .map_err(|e| LLMError::OtherError(format!("Invalid fixture {}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), LLMError> {
+        let contents = toml::to_string_pretty(self)
+            .map_err(|e| LLMError::OtherError(format!("Could not write fixture: {}", e)))?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Sets the response to an exact prompt, replacing the one recorded before, if any.
+    pub fn record(&mut self, prompt: &str, response: &str) {
+        let pattern = format!("^{}$", regex::escape(prompt));
+        match self.responses.iter_mut().find(|e| e.pattern == pattern) {
+            Some(entry) => entry.response = response.to_string(),
+            None => self.responses.push(FixtureEntry {
+                pattern,
+                response: response.to_string(),
+            }),
+        }
+    }
+
+    /// Returns the response of the first entry whose pattern matches, or the default one.
+    pub fn answer(&self, prompt: &str) -> Result<String, LLMError> {
+        for entry in &self.responses {
+            let re = Regex::new(&entry.pattern).map_err(|e| {
+                LLMError::OtherError(format!("Invalid fixture pattern {}: {}", entry.pattern, e))
+            })?;
+            if re.is_match(prompt) {
+                return Ok(entry.response.clone());
+            }
+        }
+        self.default
+            .clone()
+            .ok_or_else(|| LLMError::OtherError("No fixture response matches the prompt".into()))
+    }
+}
+
+/// An offline provider answering from a fixture file.
+/// When `recorder` is set, prompts are sent to it and its answers are recorded in the
+/// fixture, streamed ones once they are complete.
+#[derive(Clone)]
+pub struct MockLLM {
+    path: PathBuf,
+    recorder: Option<Box<LLMVariant>>,
+}
+
+impl MockLLM {
+    pub fn new(path: PathBuf) -> Self {
+        MockLLM {
+            path,
+            recorder: None,
+        }
+    }
+
+    pub fn with_recorder(mut self, llm: LLMVariant) -> Self {
+        self.recorder = Some(Box::new(llm));
+        self
+    }
+
+    fn record(&self, prompt: &str, response: &str) -> Result<(), LLMError> {
+        let mut fixture = Fixture::load(&self.path)?;
+        fixture.record(prompt, response);
+        fixture.save(&self.path)
+    }
+
+    fn prompt(messages: &[Message]) -> String {
+        messages
+            .iter()
+            .map(|m| m.content.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n")
+    }
+}
+
+#[async_trait]
+impl LLM for MockLLM {
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        let prompt = MockLLM::prompt(messages);
+        let generation = match &self.recorder {
+            Some(llm) => {
+                let generation = llm.generate(messages).await?.generation;
+                self.record(&prompt, &generation)?;
+                generation
+            }
+            None => Fixture::load(&self.path)?.answer(&prompt)?,
+        };
+
+        Ok(GenerateResult {
+            tokens: None,
+            generation,
+        })
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        if let Some(llm) = &self.recorder {
+            let prompt = MockLLM::prompt(messages);
+            let content = Arc::new(Mutex::new(Some(String::new())));
+            let received = content.clone();
+            let answer = llm.stream(messages).await?.inspect(move |data| {
+                //safe to unwrap, the lock is never held across a panic
+                let mut received = received.lock().unwrap();
+                match data {
+                    Ok(data) => {
+                        if let Some(content) = received.as_mut() {
+                            content.push_str(&data.content);
+                        }
+                    }
+                    // A failed answer is not recorded.
+                    Err(_) => *received = None,
+                }
+            });
+            let mock = self.clone();
+            let record = stream::once(async move {
+                //safe to unwrap, the lock is never held across a panic
+                match content.lock().unwrap().take() {
+                    Some(content) => mock.record(&prompt, &content),
+                    None => Ok(()),
+                }
+            })
+            .filter_map(|recorded| future::ready(recorded.err().map(Err)));
+            return Ok(Box::pin(answer.chain(record)));
+        }
+
+        let generation = self.generate(messages).await?.generation;
+        let chunks = generation
+            .split_inclusive(char::is_whitespace)
+            .map(|chunk| Ok(StreamData::new(json!({ "content": chunk }), chunk)))
+            .collect::<Vec<_>>();
+        Ok(Box::pin(stream::iter(chunks)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use langchain_rust::{chain::Chain, prompt_args};
+
+    use super::*;
+    use crate::chains::explain_command_chain;
+
+    fn fixture(dir: &Path, name: &str, fixture: &Fixture) -> PathBuf {
+        let path = dir.join(name);
+        fixture.save(&path).unwrap();
+        path
+    }
+
+    fn answering(response: &str) -> Fixture {
+        Fixture {
+            default: Some(response.to_string()),
+            responses: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn chains_answer_from_the_fixture() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = fixture(
+            dir.path(),
+            "fx.toml",
+            &Fixture {
+                default: None,
+                responses: vec![FixtureEntry {
+                    pattern: "(?s)linux command: ls -la".to_string(),
+                    response: "Lists every file.".to_string(),
+                }],
+            },
+        );
+        let chain = explain_command_chain(LLMVariant::Mock(MockLLM::new(path)), None);
+        let args = || {
+            prompt_args! {
+                "os" => "linux",
+                "environment" => "",
+                "documentation" => "",
+                "command" => "ls -la"
+            }
+        };
+        assert_eq!(chain.invoke(args()).await.unwrap(), "Lists every file.");
+        let streamed = chain
+            .stream(args())
+            .await
+            .unwrap()
+            .map(|data| data.unwrap().content)
+            .collect::<Vec<String>>()
+            .await;
+        assert_eq!(streamed, ["Lists ", "every ", "file."]);
+    }
+
+    #[tokio::test]
+    async fn recording_a_prompt_again_replaces_its_answer() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorded = dir.path().join("recorded.toml");
+        let messages = [Message::new_human_message("hello")];
+        for answer in ["first", "second"] {
+            let provider = fixture(dir.path(), "provider.toml", &answering(answer));
+            let mock = MockLLM::new(recorded.clone())
+                .with_recorder(LLMVariant::Mock(MockLLM::new(provider)));
+            assert_eq!(mock.generate(&messages).await.unwrap().generation, answer);
+        }
+        let fixture = Fixture::load(&recorded).unwrap();
+        assert_eq!(fixture.responses.len(), 1);
+        assert_eq!(fixture.answer("hello").unwrap(), "second");
+    }
+
+    #[tokio::test]
+    async fn recorded_answers_are_streamed() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorded = dir.path().join("recorded.toml");
+        let provider = fixture(dir.path(), "provider.toml", &answering("one two three"));
+        let mock =
+            MockLLM::new(recorded.clone()).with_recorder(LLMVariant::Mock(MockLLM::new(provider)));
+        let chunks = mock
+            .stream(&[Message::new_human_message("count")])
+            .await
+            .unwrap()
+            .map(|data| data.unwrap().content)
+            .collect::<Vec<String>>()
+            .await;
+        assert_eq!(chunks, ["one ", "two ", "three"]);
+        let fixture = Fixture::load(&recorded).unwrap();
+        assert_eq!(fixture.answer("count").unwrap(), "one two three");
+    }
+}
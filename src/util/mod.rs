@@ -1,3 +1,25 @@
+pub mod availability;
+pub mod cache;
+pub mod commit;
+pub mod condense;
 pub mod config;
+pub mod docs;
+pub mod environment;
+pub mod git;
+pub mod history;
 pub mod llm;
+pub mod mock;
+pub mod output;
+pub mod placeholder;
+pub mod plan;
+pub mod process;
+pub mod project;
+pub mod safety;
+pub mod script;
+pub mod session;
 pub mod shared;
+pub mod shell;
+pub mod stream;
+pub mod style;
+pub mod templates;
+pub mod tokens;
@@ -0,0 +1,260 @@
+use std::{str::FromStr, time::Duration};
+
+use dialoguer::console::style;
+use serde::Serialize;
+
+use super::{
+    availability::missing_programs,
+    condense::Condensed,
+    docs::print_undocumented,
+    placeholder::detect_placeholders,
+    safety::{Risk, RiskLevel},
+    script::{describe_side_effects, Section, SideEffect},
+    shared::{apply_styles_to_backticks, SharedState},
+    shell::Part,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Raw,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "raw" => Ok(OutputFormat::Raw),
+            _ => Err(format!("Unsupported output format: {}", s)),
+        }
+    }
+}
+
+/// The kind of answer shown to the user, which decides its title and styling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Answer {
+    Explanation,
+    Suggestion,
+}
+
+impl Answer {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Answer::Explanation => "Explanation:",
+            Answer::Suggestion => "Sugestion:",
+        }
+    }
+
+    pub fn print(&self, text: &str) {
+        println!("{}\n", self.title());
+        match self {
+            Answer::Explanation => println!("{}\n", apply_styles_to_backticks(text)),
+            Answer::Suggestion => println!("{}\n", style(text).yellow().bold()),
+        }
+    }
+}
+
+/// The result of a subcommand, printed as styled text, plain text or JSON.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub command: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    pub output: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub bullets: Vec<String>,
+    /// The parse tree of an explained command, with the explanation of each part.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tree: Option<Part>,
+    /// The sections of an explained script, with their line ranges.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sections: Vec<Section>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub side_effects: Option<Vec<SideEffect>>,
+    /// Why a fixed command failed, shown before the fix.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnosis: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub risk: Option<Risk>,
+    /// Placeholders of a suggestion the user has to fill in.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub placeholders: Vec<String>,
+    /// Options of an explanation that the local documentation does not have.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub undocumented: Vec<String>,
+    /// Programs of a suggestion that are not installed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missing: Vec<String>,
+    /// Files of a staged diff that were not sent whole.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub condensed: Vec<Condensed>,
+    pub provider: String,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    pub elapsed_ms: u128,
+    /// Whether the output was already streamed to the terminal.
+    #[serde(skip)]
+    streamed: bool,
+}
+
+impl Report {
+    pub fn new(
+        shared_state: &SharedState,
+        command: &str,
+        input: Option<&str>,
+        output: &str,
+        elapsed: Duration,
+    ) -> Self {
+        Report {
+            command: command.to_string(),
+            input: input.map(|i| i.to_string()),
+            output: output.trim().to_string(),
+            bullets: Vec::new(),
+            tree: None,
+            sections: Vec::new(),
+            side_effects: None,
+            diagnosis: None,
+            risk: None,
+            placeholders: Vec::new(),
+            undocumented: Vec::new(),
+            missing: Vec::new(),
+            condensed: Vec::new(),
+            provider: shared_state.provider().to_string(),
+            model: shared_state.model().to_string(),
+            profile: shared_state.profile().map(|p| p.to_string()),
+            elapsed_ms: elapsed.as_millis(),
+            streamed: shared_state.streaming(),
+        }
+    }
+
+    pub fn with_bullets(mut self) -> Self {
+        self.bullets = parse_bullets(&self.output);
+        self
+    }
+
+    /// Adds the annotated syntax tree, printed even after the answer it was built from was
+    /// streamed.
+    pub fn with_tree(mut self, tree: Part) -> Self {
+        self.tree = Some(tree);
+        self
+    }
+
+    pub fn with_script(mut self, sections: Vec<Section>, side_effects: Vec<SideEffect>) -> Self {
+        self.sections = sections;
+        self.side_effects = Some(side_effects);
+        self.streamed = false;
+        self
+    }
+
+    pub fn with_diagnosis(mut self, diagnosis: &str) -> Self {
+        self.diagnosis = Some(diagnosis.trim().to_string()).filter(|d| !d.is_empty());
+        self.streamed = false;
+        self
+    }
+
+    /// Marks the output as not streamed, for answers generated whole and reformatted.
+    pub fn with_streamed(mut self, streamed: bool) -> Self {
+        self.streamed = self.streamed && streamed;
+        self
+    }
+
+    /// Marks the output as already shown, so it is not printed again, like a streamed one.
+    pub fn with_shown(mut self, shown: bool) -> Self {
+        self.streamed |= shown;
+        self
+    }
+
+    pub fn with_risk(mut self, risk: Risk) -> Self {
+        self.risk = Some(risk);
+        self
+    }
+
+    pub fn with_placeholders(mut self) -> Self {
+        self.placeholders = detect_placeholders(&self.output)
+            .into_iter()
+            .map(|p| p.token)
+            .collect();
+        self
+    }
+
+    pub fn with_undocumented(mut self, options: Vec<String>) -> Self {
+        self.undocumented = options;
+        self
+    }
+
+    pub fn with_missing_programs(mut self) -> Self {
+        self.missing = missing_programs(&self.output);
+        self
+    }
+
+    pub fn with_condensed(mut self, condensed: Vec<Condensed>) -> Self {
+        self.condensed = condensed;
+        self
+    }
+
+    pub fn answer(&self) -> Answer {
+        match self.command.as_str() {
+            "explain" => Answer::Explanation,
+            _ => Answer::Suggestion,
+        }
+    }
+
+    /// Prints the report, unless it was already streamed to the terminal. Scripts and fixes
+    /// are never streamed. A tree is printed below the streamed answer. In text mode, risky
+    /// commands and undocumented options get a warning either way.
+    pub fn print(&self, shared_state: &SharedState) {
+        match shared_state.format() {
+            OutputFormat::Text => {
+                if let Some(diagnosis) = &self.diagnosis {
+                    Answer::Explanation.print(diagnosis);
+                }
+                if let Some(tree) = &self.tree {
+                    if !self.streamed {
+                        println!("{}\n", self.answer().title());
+                    }
+                    println!("{}\n", tree.render(true));
+                } else if !self.streamed {
+                    self.answer().print(&self.output);
+                }
+                if let Some(side_effects) = &self.side_effects {
+                    println!("{}\n", describe_side_effects(side_effects, true));
+                }
+                if let Some(risk) = self.risk.as_ref().filter(|r| r.level > RiskLevel::Low) {
+                    risk.print();
+                }
+                if !self.undocumented.is_empty() {
+                    print_undocumented(&self.undocumented);
+                }
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(self).unwrap() //safe to unwrap
+            ),
+            OutputFormat::Raw => {
+                println!("{}", self.output);
+                if let Some(side_effects) = &self.side_effects {
+                    println!("\n{}", describe_side_effects(side_effects, false));
+                }
+            }
+        }
+    }
+}
+
+/// Extracts the bullet points of a markdown answer, without their markers.
+pub fn parse_bullets(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter_map(|line| {
+            ["• ", "- ", "* "]
+                .iter()
+                .find_map(|marker| line.strip_prefix(marker))
+        })
+        .map(|bullet| bullet.trim().to_string())
+        .filter(|bullet| !bullet.is_empty())
+        .collect()
+}
@@ -0,0 +1,265 @@
+use std::{fs, process::Command};
+
+use regex::Regex;
+
+/// A value the model left for the user to fill in, like `<repository_url>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placeholder {
+    /// The placeholder as it appears in the command.
+    pub token: String,
+    /// The placeholder without its brackets, in lowercase.
+    pub name: String,
+}
+
+impl Placeholder {
+    /// Guesses a value from the git repository, the environment or the current directory.
+    pub fn default(&self) -> Option<String> {
+        let name = self.name.as_str();
+        let has = |words: &[&str]| words.iter().any(|w| name.contains(w));
+        if has(&["branch"]) {
+            git(&["rev-parse", "--abbrev-ref", "HEAD"])
+        } else if has(&["url", "repository", "repo"]) {
+            git(&["remote", "get-url", "origin"])
+        } else if has(&["remote"]) {
+            git(&["remote"]).and_then(|r| r.lines().next().map(|l| l.to_string()))
+        } else if has(&["email"]) {
+            git(&["config", "user.email"])
+        } else if has(&["user"]) {
+            std::env::var("USER").ok()
+        } else if has(&["dir", "folder"]) {
+            std::env::current_dir()
+                .ok()
+                .map(|d| d.display().to_string())
+        } else if has(&["file", "path"]) {
+            first_file(name.rsplit_once('.').map(|(_, ext)| ext))
+        } else {
+            None
+        }
+    }
+}
+
+/// HTML elements models quote in commands, like `echo '<title>' > index.html`.
+const HTML_TAGS: &[&str] = &[
+    "a", "b", "body", "br", "code", "div", "em", "footer", "form", "h1", "h2", "h3", "h4", "h5",
+    "h6", "head", "header", "hr", "html", "i", "img", "input", "li", "link", "main", "meta", "nav",
+    "ol", "p", "pre", "script", "section", "span", "strong", "style", "table", "td", "th", "title",
+    "tr", "ul",
+];
+
+/// Where a character of a command is, for the shell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quoting {
+    Plain,
+    Quoted,
+    Comment,
+}
+
+/// Finds every distinct placeholder of `input`: `<name>`, `{name}` (but not `${name}`,
+/// `@{u}` or the brace expansion `{a..z}`), `YOUR_NAME` and `your-name`. A `<name>` glued
+/// to a word, like the redirects of `sort <in.txt >out.txt`, is not one, nor is a quoted
+/// HTML tag, and `YOUR_NAME` and `your-name` must be words of their own, so `$YOUR_CONST`
+/// or `your_table` are left alone. Comments are skipped.
+pub fn detect_placeholders(input: &str) -> Vec<Placeholder> {
+    let quoting = quoting(input);
+    let patterns = [
+        r"<([A-Za-z][\w.-]*(?: [\w.-]+)*)>",
+        r"(?:^|[^$@\w])\{([A-Za-z][\w.-]*)\}",
+        r"\b(YOUR_[A-Z0-9_]*[A-Z0-9])\b",
+        r"\b(your-[\w.-]*\w)",
+    ];
+    let mut found: Vec<(usize, Placeholder)> = Vec::new();
+    for pattern in patterns {
+        let re = Regex::new(pattern).unwrap(); //safe to unwrap
+        for cap in re.captures_iter(input) {
+            //safe to unwrap, both groups always take part in a match
+            let whole = cap.get(0).unwrap();
+            let name = cap.get(1).unwrap();
+            let at = quoting[name.start()];
+            if at == Quoting::Comment {
+                continue;
+            }
+            let before = input[..whole.start()].chars().next_back();
+            let after = input[whole.end()..].chars().next();
+            let standalone = match whole.as_str().chars().next() {
+                // Redirects are written right against a word or a file descriptor.
+                Some('<') => {
+                    let tag = name.as_str().to_lowercase();
+                    let html = input.contains(&format!("</{}>", name.as_str()))
+                        || (at == Quoting::Quoted && HTML_TAGS.contains(&tag.as_str()));
+                    !html
+                        && !before.is_some_and(|c| c.is_alphanumeric() || "<>&".contains(c))
+                        && !after.is_some_and(|c| c.is_alphanumeric() || "_~$".contains(c))
+                }
+                Some('Y' | 'y') => {
+                    !before.is_some_and(|c| c.is_alphanumeric() || "$_{.-".contains(c))
+                        && !after.is_some_and(|c| c.is_alphanumeric() || "=(_".contains(c))
+                }
+                _ => !name.as_str().contains(".."),
+            };
+            if !standalone {
+                continue;
+            }
+            // The brace pattern also matches the character before the brace.
+            let token = match whole.as_str().ends_with('}') {
+                true => format!("{{{}}}", name.as_str()),
+                false => whole.as_str().to_string(),
+            };
+            if found.iter().all(|(_, p)| p.token != token) {
+                found.push((
+                    name.start(),
+                    Placeholder {
+                        token,
+                        name: name.as_str().to_lowercase(),
+                    },
+                ));
+            }
+        }
+    }
+    found.sort_by_key(|(start, _)| *start);
+    found.into_iter().map(|(_, p)| p).collect()
+}
+
+/// Replaces every placeholder token with its value.
+pub fn substitute(input: &str, values: &[(String, String)]) -> String {
+    values
+        .iter()
+        .fold(input.to_string(), |acc, (token, value)| {
+            acc.replace(token, value)
+        })
+}
+
+/// The quoting of every byte of `input`: inside quotes, in a comment, or neither.
+fn quoting(input: &str) -> Vec<Quoting> {
+    let mut quoting = vec![Quoting::Plain; input.len() + 1];
+    let mut quote = None;
+    let mut comment = false;
+    let mut escaped = false;
+    let mut previous = None;
+    for (i, c) in input.char_indices() {
+        let here = if comment {
+            comment = c != '\n';
+            Quoting::Comment
+        } else if escaped {
+            escaped = false;
+            if quote.is_some() {
+                Quoting::Quoted
+            } else {
+                Quoting::Plain
+            }
+        } else {
+            match (quote, c) {
+                (Some('\''), '\'') | (Some('"'), '"') => {
+                    quote = None;
+                    Quoting::Quoted
+                }
+                (Some('"'), '\\') => {
+                    escaped = true;
+                    Quoting::Quoted
+                }
+                (Some(_), _) => Quoting::Quoted,
+                (None, '\'' | '"') => {
+                    quote = Some(c);
+                    Quoting::Quoted
+                }
+                (None, '\\') => {
+                    escaped = true;
+                    Quoting::Plain
+                }
+                (None, '#')
+                    if previous.is_none_or(|p: char| p.is_whitespace() || ";|&(".contains(p)) =>
+                {
+                    comment = true;
+                    Quoting::Comment
+                }
+                _ => Quoting::Plain,
+            }
+        };
+        quoting[i..i + c.len_utf8()].fill(here);
+        previous = Some(c);
+    }
+    quoting
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !value.is_empty()).then_some(value)
+}
+
+/// The first file of the current directory, with the extension `ext` when given.
+fn first_file(ext: Option<&str>) -> Option<String> {
+    let mut files = fs::read_dir(".")
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|f| !f.starts_with('.'))
+        .collect::<Vec<String>>();
+    files.sort();
+    match ext {
+        Some(ext) => files
+            .iter()
+            .find(|f| f.ends_with(&format!(".{}", ext)))
+            .or(files.first())
+            .cloned(),
+        None => files.into_iter().next(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<String> {
+        detect_placeholders(input)
+            .into_iter()
+            .map(|p| p.token)
+            .collect()
+    }
+
+    #[test]
+    fn finds_placeholders() {
+        assert_eq!(tokens("git clone <repository_url>"), ["<repository_url>"]);
+        assert_eq!(tokens("curl https://<host>/api"), ["<host>"]);
+        assert_eq!(tokens("git push origin {branch}"), ["{branch}"]);
+        assert_eq!(tokens("export TOKEN=YOUR_API_TOKEN"), ["YOUR_API_TOKEN"]);
+        assert_eq!(tokens("aws s3 cp a.txt s3://your-bucket/"), ["your-bucket"]);
+        assert_eq!(tokens("cat 'your-file.txt'"), ["your-file.txt"]);
+    }
+
+    #[test]
+    fn redirects_are_not_placeholders() {
+        assert!(tokens("sort <in.txt >out.txt").is_empty());
+        assert!(tokens("sort<in.txt>out.txt").is_empty());
+        assert!(tokens("cat <input>output").is_empty());
+        assert!(tokens("cmd 2<err>log").is_empty());
+    }
+
+    #[test]
+    fn identifiers_are_not_placeholders() {
+        assert!(tokens("psql -c 'SELECT * FROM your_table'").is_empty());
+        assert!(tokens("echo $YOUR_CONST").is_empty());
+        assert!(tokens("echo ${YOUR_CONST}").is_empty());
+        assert!(tokens("YOUR_CONST=1 make").is_empty());
+        assert!(tokens("python -c 'print(MY_YOUR_CONST)'").is_empty());
+        assert!(tokens("echo ${name}").is_empty());
+    }
+
+    #[test]
+    fn shell_syntax_is_not_placeholders() {
+        assert!(tokens("echo {a..z}").is_empty());
+        assert!(tokens("cp file.{txt,bak}").is_empty());
+        assert!(tokens("git log @{u}..").is_empty());
+        assert!(tokens("git log --oneline # <commit>").is_empty());
+        assert!(tokens("# set {branch} first\ngit status").is_empty());
+        assert_eq!(tokens("echo '# x' && git show <commit>"), ["<commit>"]);
+    }
+
+    #[test]
+    fn html_is_not_placeholders() {
+        assert!(tokens("echo '<title>' > x.html").is_empty());
+        assert!(tokens("echo \"<title>Home</title>\" > x.html").is_empty());
+        assert_eq!(tokens("git clone '<repository_url>'"), ["<repository_url>"]);
+        assert_eq!(tokens("curl -H 'Host: <host>' <url>"), ["<host>", "<url>"]);
+    }
+}
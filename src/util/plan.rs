@@ -0,0 +1,494 @@
+use std::{
+    env, fmt, fs,
+    io::{self, IsTerminal, Read, Write},
+    path::PathBuf,
+    process::{Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex, OnceLock},
+    thread,
+};
+
+use dialoguer::console::style;
+use regex::Regex;
+use tempfile::Builder;
+
+use crate::error::CopilotError;
+
+use super::{placeholder::detect_placeholders, shell::is_incomplete};
+
+/// What happened to a step of a plan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepStatus {
+    Pending,
+    Succeeded,
+    Failed(Option<i32>),
+    Skipped,
+    /// Not run because the command still has placeholders.
+    Blocked,
+}
+
+impl fmt::Display for StepStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepStatus::Pending => write!(f, "not run"),
+            StepStatus::Succeeded => write!(f, "exit 0"),
+            StepStatus::Failed(Some(code)) => write!(f, "exit {}", code),
+            StepStatus::Failed(None) => write!(f, "killed by a signal"),
+            StepStatus::Skipped => write!(f, "skipped"),
+            StepStatus::Blocked => {
+                write!(
+                    f,
+                    "blocked, fill in its placeholders or keep them as written"
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub command: String,
+    pub status: StepStatus,
+    pub output: String,
+    /// Whether the output of the step was captured when it ran.
+    pub captured: bool,
+}
+
+/// The commands of a suggestion, run one after the other in the same directory.
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub steps: Vec<Step>,
+    cwd: PathBuf,
+    /// Tokens the user said are not placeholders.
+    kept: Vec<String>,
+}
+
+impl Plan {
+    /// Splits a suggestion into one step per command, dropping code fences, comments and
+    /// leading `$ ` prompts. A command spans as many lines as the shell would read for it:
+    /// here-documents, compound commands, quotes, and lines continued with `\`, `|`, `&&`
+    /// or `||`.
+    pub fn parse(input: &str) -> Self {
+        let mut commands: Vec<String> = Vec::new();
+        let mut current = String::new();
+        for line in input.lines() {
+            let line = line.trim_end();
+            let trimmed = line.trim();
+            if trimmed.starts_with("```") {
+                commands.push(std::mem::take(&mut current));
+                continue;
+            }
+            if current.is_empty() {
+                if trimmed.is_empty() || trimmed.starts_with('#') {
+                    continue;
+                }
+                current = trimmed
+                    .strip_prefix("$ ")
+                    .unwrap_or(trimmed)
+                    .trim_matches('`')
+                    .to_string();
+            } else {
+                current.push('\n');
+                current.push_str(line);
+            }
+            if !is_incomplete(&current) {
+                commands.push(std::mem::take(&mut current));
+            }
+        }
+        commands.push(current);
+        let commands = commands.into_iter().filter(|c| !c.trim().is_empty());
+
+        Plan {
+            steps: commands
+                .into_iter()
+                .map(|command| Step {
+                    command,
+                    status: StepStatus::Pending,
+                    output: String::new(),
+                    captured: false,
+                })
+                .collect(),
+            cwd: env::current_dir().unwrap_or_default(),
+            kept: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// The exit status of the failed step, 0 when steps ran and none failed, or `None` when
+    /// nothing ran or a step was killed by a signal.
+    pub fn exit_status(&self) -> Option<i32> {
+        match self.steps.iter().find_map(|s| match s.status {
+            StepStatus::Failed(code) => Some(code),
+            _ => None,
+        }) {
+            Some(code) => code,
+            None => self
+                .steps
+                .iter()
+                .any(|s| s.status == StepStatus::Succeeded)
+                .then_some(0),
+        }
+    }
+
+    /// The commands of the pending steps, as they would be run.
+    pub fn commands(&self) -> String {
+        self.steps
+            .iter()
+            .filter(|s| s.status == StepStatus::Pending)
+            .map(|s| s.command.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n")
+    }
+
+    pub fn print(&self) {
+        println!("{}\n", style("Plan:").white().bold());
+        for (i, step) in self.steps.iter().enumerate() {
+            println!(
+                "  {} {}",
+                style(format!("{}.", i + 1)).dim(),
+                style(&step.command).yellow().bold()
+            );
+        }
+        println!();
+    }
+
+    /// Prints the status of every step once the plan is over.
+    pub fn print_report(&self) {
+        println!("\n{}\n", style("Report:").white().bold());
+        for (i, step) in self.steps.iter().enumerate() {
+            let mark = match step.status {
+                StepStatus::Succeeded => style("✔").green(),
+                StepStatus::Failed(_) | StepStatus::Blocked => style("✘").red(),
+                StepStatus::Skipped | StepStatus::Pending => style("-").dim(),
+            };
+            println!(
+                "  {} {} {} {}",
+                mark,
+                style(format!("{}.", i + 1)).dim(),
+                step.command,
+                style(format!("({})", step.status)).dim()
+            );
+            if let StepStatus::Failed(_) = step.status {
+                if !step.captured {
+                    println!(
+                        "      {}",
+                        style("its output was not captured, see it above").dim()
+                    );
+                }
+                let lines = step.output.lines().collect::<Vec<&str>>();
+                for line in &lines[lines.len().saturating_sub(5)..] {
+                    println!("      {}", style(line).dim());
+                }
+            }
+        }
+        println!();
+    }
+
+    pub fn skip(&mut self, index: usize) {
+        self.steps[index].status = StepStatus::Skipped;
+    }
+
+    /// Runs the steps with these tokens as written, since they are not placeholders.
+    pub fn keep(&mut self, tokens: &[String]) {
+        self.kept.extend_from_slice(tokens);
+    }
+
+    pub fn edit(&mut self, index: usize, command: &str) {
+        self.steps[index].command = command.to_string();
+    }
+
+    /// Runs a step with `sh -c` on the terminal, so interactive programs keep working. A
+    /// `cd` in a step applies to the following ones. Steps with placeholders left are not
+    /// run, unless they were kept.
+    ///
+    /// The output is captured for the report with the util-linux `script` on a Linux
+    /// terminal, and through pipes when the output is not a terminal. On the terminals of
+    /// other systems it is only shown as the step runs.
+    pub fn run(&mut self, index: usize) -> Result<bool, CopilotError> {
+        if detect_placeholders(&self.steps[index].command)
+            .iter()
+            .any(|p| !self.kept.contains(&p.token))
+        {
+            self.steps[index].status = StepStatus::Blocked;
+            return Ok(false);
+        }
+        let cwd_file = Builder::new()
+            .prefix("copilot-plan-")
+            .suffix(".cwd")
+            .tempfile()?;
+        let log_file = Builder::new()
+            .prefix("copilot-plan-")
+            .suffix(".log")
+            .tempfile()?;
+        let script = format!(
+            "{}\n__copilot_status=$?\npwd > \"$COPILOT_CWD_FILE\"\nexit $__copilot_status",
+            self.steps[index].command
+        );
+        println!(
+            "{} {}",
+            style(format!("[{}/{}]", index + 1, self.len()))
+                .cyan()
+                .bold(),
+            style(&self.steps[index].command).bold()
+        );
+
+        let recorded = can_record();
+        let mut command = match recorded {
+            // `script` runs the step in a terminal of its own and keeps a transcript of it.
+            true => {
+                let mut command = Command::new("script");
+                command
+                    .args(["-q", "-e", "-c", "sh -c \"$COPILOT_STEP\""])
+                    .arg(log_file.path())
+                    .env("COPILOT_STEP", &script);
+                command
+            }
+            false => {
+                let mut command = Command::new("sh");
+                command.arg("-c").arg(&script);
+                command
+            }
+        };
+        command
+            .current_dir(&self.cwd)
+            .env("COPILOT_CWD_FILE", cwd_file.path());
+        let piped = !recorded && !io::stdout().is_terminal();
+        let (status, output) = match piped {
+            true => run_piped(&mut command)?,
+            false => (command.status()?, String::new()),
+        };
+
+        // Steps that `exit` early leave it empty.
+        if let Some(cwd) = fs::read_to_string(cwd_file.path())
+            .ok()
+            .filter(|cwd| !cwd.trim().is_empty())
+        {
+            self.cwd = PathBuf::from(cwd.trim());
+        }
+        let step = &mut self.steps[index];
+        step.captured = recorded || piped;
+        step.output = match recorded {
+            true => fs::read(log_file.path())
+                .map(|log| transcript_text(&String::from_utf8_lossy(&log)))
+                .unwrap_or_default(),
+            false => output.trim_end().to_string(),
+        };
+        step.status = match status.success() {
+            true => StepStatus::Succeeded,
+            false => StepStatus::Failed(status.code()),
+        };
+        Ok(status.success())
+    }
+}
+
+/// Runs a command with its output and errors piped through, keeping a copy of both.
+fn run_piped(command: &mut Command) -> io::Result<(ExitStatus, String)> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let mut readers = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        readers.push(tee(stdout, io::stdout(), output.clone()));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        readers.push(tee(stderr, io::stderr(), output.clone()));
+    }
+    let status = child.wait()?;
+    for reader in readers {
+        let _ = reader.join();
+    }
+    //safe to unwrap, the lock is never held across a panic
+    let output = String::from_utf8_lossy(&output.lock().unwrap()).to_string();
+    Ok((status, output))
+}
+
+/// Copies everything `reader` gives to `writer` and to `copy`, as it comes.
+fn tee(
+    mut reader: impl Read + Send + 'static,
+    mut writer: impl Write + Send + 'static,
+    copy: Arc<Mutex<Vec<u8>>>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        while let Ok(read) = reader.read(&mut buffer) {
+            if read == 0 {
+                break;
+            }
+            let _ = writer.write_all(&buffer[..read]);
+            let _ = writer.flush();
+            //safe to unwrap, the lock is never held across a panic
+            copy.lock().unwrap().extend_from_slice(&buffer[..read]);
+        }
+    })
+}
+
+/// Whether steps can be recorded while keeping the terminal: on Linux, with the util-linux
+/// `script`, when the output goes to a terminal.
+fn can_record() -> bool {
+    static SCRIPT: OnceLock<bool> = OnceLock::new();
+    cfg!(target_os = "linux")
+        && io::stdout().is_terminal()
+        && *SCRIPT.get_or_init(|| {
+            Command::new("script")
+                .arg("--version")
+                .output()
+                .is_ok_and(|output| {
+                    output.status.success()
+                        && String::from_utf8_lossy(&output.stdout).contains("util-linux")
+                })
+        })
+}
+
+/// The text of a `script` transcript, without its header, footer, escape sequences and
+/// carriage returns.
+fn transcript_text(log: &str) -> String {
+    static ESCAPES: OnceLock<Regex> = OnceLock::new();
+    let escapes = ESCAPES.get_or_init(|| {
+        Regex::new(r"\x1b(\[[0-9;?]*[ -/]*[@-~]|\][^\x07\x1b]*(\x07|\x1b\\)|.)").unwrap()
+    });
+    escapes
+        .replace_all(log, "")
+        .lines()
+        .map(|line| {
+            let line = line.trim_end_matches('\r');
+            line.rsplit('\r').next().unwrap_or(line)
+        })
+        .filter(|line| {
+            !line.starts_with("Script started on ") && !line.starts_with("Script done on ")
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps(input: &str) -> Vec<String> {
+        Plan::parse(input)
+            .steps
+            .into_iter()
+            .map(|step| step.command)
+            .collect()
+    }
+
+    #[test]
+    fn one_step_per_line() {
+        assert_eq!(
+            steps("```sh\n# build it\n$ cargo build\n\ncargo test\n```"),
+            ["cargo build", "cargo test"]
+        );
+    }
+
+    #[test]
+    fn continued_lines_are_one_step() {
+        assert_eq!(
+            steps("docker run \\\n  -it ubuntu\nls"),
+            ["docker run \\\n  -it ubuntu", "ls"]
+        );
+        assert_eq!(
+            steps("cat log |\n  grep error &&\n  echo found"),
+            ["cat log |\n  grep error &&\n  echo found"]
+        );
+    }
+
+    #[test]
+    fn heredoc_is_one_step() {
+        assert_eq!(
+            steps("cat <<EOF > notes.txt\nfirst\n# not a comment\nEOF\nls"),
+            ["cat <<EOF > notes.txt\nfirst\n# not a comment\nEOF", "ls"]
+        );
+        assert_eq!(
+            steps("cat <<-'END'\n\tindented\n\tEND\necho done"),
+            ["cat <<-'END'\n\tindented\n\tEND", "echo done"]
+        );
+    }
+
+    #[test]
+    fn compound_commands_are_one_step() {
+        assert_eq!(
+            steps("for f in *.txt; do\n  wc -l \"$f\"\ndone\necho ok"),
+            ["for f in *.txt; do\n  wc -l \"$f\"\ndone", "echo ok"]
+        );
+        assert_eq!(
+            steps("if [ -d build ]; then\n  rm -r build\nelse\n  mkdir build\nfi"),
+            ["if [ -d build ]; then\n  rm -r build\nelse\n  mkdir build\nfi"]
+        );
+        assert_eq!(
+            steps("while read line; do\n  echo \"$line\"\ndone < input.txt"),
+            ["while read line; do\n  echo \"$line\"\ndone < input.txt"]
+        );
+        assert_eq!(
+            steps("{\n  echo a\n  echo b\n} > out.txt\ncat out.txt"),
+            ["{\n  echo a\n  echo b\n} > out.txt", "cat out.txt"]
+        );
+    }
+
+    #[test]
+    fn quoted_strings_spanning_lines_are_one_step() {
+        assert_eq!(
+            steps("git commit -m \"subject\n\nbody\"\ngit log -1"),
+            ["git commit -m \"subject\n\nbody\"", "git log -1"]
+        );
+    }
+
+    #[test]
+    fn unfinished_command_is_still_a_step() {
+        assert_eq!(steps("echo 'unterminated\nls"), ["echo 'unterminated\nls"]);
+        assert_eq!(steps("fi\nls"), ["fi", "ls"]);
+    }
+
+    #[test]
+    fn transcript_text_drops_the_script_header_and_escapes() {
+        let log = "Script started on 2024-01-01 [COMMAND=\"sh\"]\nhi\r\n\x1b[1mbold\x1b[0m\r\n\nScript done on 2024-01-01 [COMMAND_EXIT_CODE=\"3\"]\n";
+        assert_eq!(transcript_text(log), "hi\nbold");
+    }
+
+    #[test]
+    fn cd_carries_over_to_the_next_steps() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        let mut plan = Plan::parse("cd sub\ntouch here\nexit 3\ntouch after");
+        plan.cwd = dir.path().to_path_buf();
+        assert!(plan.run(0).unwrap());
+        assert!(plan.run(1).unwrap());
+        assert!(dir.path().join("sub").join("here").exists());
+        assert!(!plan.run(2).unwrap());
+        assert_eq!(plan.steps[2].status, StepStatus::Failed(Some(3)));
+        assert!(plan.steps[2].captured);
+        assert!(plan.run(3).unwrap());
+        assert!(dir.path().join("sub").join("after").exists());
+    }
+
+    #[test]
+    fn output_is_captured_without_a_terminal() {
+        let mut plan = Plan::parse("echo out; echo err >&2; exit 1");
+        assert!(!plan.run(0).unwrap());
+        assert!(plan.steps[0].captured);
+        let mut lines = plan.steps[0].output.lines().collect::<Vec<&str>>();
+        lines.sort();
+        assert_eq!(lines, ["err", "out"]);
+    }
+
+    #[test]
+    fn kept_tokens_are_run_as_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut plan = Plan::parse("echo \"<none>\" > out.txt");
+        plan.cwd = dir.path().to_path_buf();
+        assert!(!plan.run(0).unwrap());
+        assert_eq!(plan.steps[0].status, StepStatus::Blocked);
+        plan.keep(&["<none>".to_string()]);
+        assert!(plan.run(0).unwrap());
+        assert!(dir.path().join("out.txt").exists());
+    }
+}
@@ -0,0 +1,203 @@
+use std::{collections::BTreeMap, path::PathBuf, process::Command};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::CopilotError, COPILOT_PATH};
+
+use super::{config::LLMConfig, environment::EnvironmentConfig, safety::SafetyConfig};
+
+pub const PROJECT_CONFIG_PATH: &str = ".copilot-cli.toml";
+
+/// The provider used when no profile is configured.
+pub const DEFAULT_LLM_TYPE: &str = "openai";
+
+/// The `[llm]` settings read from any repository. The provider, endpoint, key and fixture
+/// could send the user's key to a server of the repository or write to a path it picks, so
+/// they are only read from trusted repositories.
+const UNTRUSTED_LLM_KEYS: [&str; 1] = ["model"];
+
+/// Settings for the current repository, read from `.copilot-cli.toml` at the git root and
+/// merged over the user config.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ProjectConfig {
+    /// Profile of the user config to use in this repository.
+    pub profile: Option<String>,
+    /// Provider settings merged field by field over the selected profile. Only `model` is
+    /// read unless the user trusts the repository.
+    pub llm: Option<toml::Table>,
+    #[serde(default)]
+    pub commit: CommitConfig,
+    /// Prompt templates by chain name.
+    #[serde(default)]
+    pub prompts: BTreeMap<String, String>,
+    /// Deny patterns added to the ones of the user config, `allow` is only read from the
+    /// user config.
+    #[serde(default)]
+    pub safety: SafetyConfig,
+    /// Overrides of the detected environment, over the ones of the user config.
+    #[serde(default)]
+    pub environment: EnvironmentConfig,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CommitConfig {
+    /// Files always excluded from the commit diff, on top of `--excluded`.
+    #[serde(default)]
+    pub excluded: Vec<String>,
+    /// Extra rules the generated commit messages must follow.
+    #[serde(default)]
+    pub rules: Vec<String>,
+    /// Hold generated messages to Conventional Commits, by default only when the history of
+    /// the repository follows them.
+    pub conventional: Option<bool>,
+    /// Write commit messages with a body and footers, like `commit --full`.
+    #[serde(default)]
+    pub full: bool,
+    /// How many tokens of the staged diff are sent, half the context window by default.
+    pub max_diff_tokens: Option<usize>,
+}
+
+impl ProjectConfig {
+    /// Reads the project config, or returns an empty one outside a git repository or when
+    /// the repository has no config.
+    pub fn load() -> Result<Self, CopilotError> {
+        let Some(path) = ProjectConfig::path() else {
+            return Ok(ProjectConfig::default());
+        };
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Ok(ProjectConfig::default());
+        };
+        toml::from_str(&contents)
+            .map_err(|e| CopilotError::Config(format!("Error reading {}: {}", path.display(), e)))
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        git_root().map(|root| root.join(PROJECT_CONFIG_PATH))
+    }
+
+    /// Overlays the `[llm]` table on the profile selected from the user config, or on the
+    /// default `openai` provider without one. A repository that is not `trusted` may only
+    /// set the model.
+    pub fn merge_llm(
+        &self,
+        cfg: Option<LLMConfig>,
+        trusted: bool,
+    ) -> Result<Option<LLMConfig>, CopilotError> {
+        let Some(overrides) = &self.llm else {
+            return Ok(cfg);
+        };
+        if let Some(key) = overrides
+            .keys()
+            .find(|key| !trusted && !UNTRUSTED_LLM_KEYS.contains(&key.as_str()))
+        {
+            return Err(CopilotError::Config(format!(
+                "`{}` in the [llm] table of {} is only read from trusted repositories, add the repository root to `trusted_repositories` in ~/{} to allow it",
+                key, PROJECT_CONFIG_PATH, COPILOT_PATH
+            )));
+        }
+
+        let mut table = match cfg {
+            Some(cfg) => {
+                toml::Table::try_from(cfg).map_err(|e| CopilotError::Config(e.to_string()))?
+            }
+            None => toml::Table::from_iter([(
+                "llm_type".to_string(),
+                toml::Value::from(DEFAULT_LLM_TYPE),
+            )]),
+        };
+        table.extend(overrides.clone());
+        toml::Value::Table(table)
+            .try_into::<LLMConfig>()
+            .map(Some)
+            .map_err(|e| {
+                CopilotError::Config(format!("Invalid [llm] in {}: {}", PROJECT_CONFIG_PATH, e))
+            })
+    }
+}
+
+/// The root of the git repository containing the current directory.
+pub fn git_root() -> Option<PathBuf> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(PathBuf::from(
+        String::from_utf8_lossy(&output.stdout).trim(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(llm: &str) -> ProjectConfig {
+        ProjectConfig {
+            llm: Some(toml::from_str(llm).unwrap()),
+            ..ProjectConfig::default()
+        }
+    }
+
+    fn profile() -> LLMConfig {
+        LLMConfig {
+            api_key: Some("secret".to_string()),
+            api_base: Some("https://api.openai.com/v1".to_string()),
+            ..LLMConfig::new_ollama()
+        }
+    }
+
+    #[test]
+    fn untrusted_project_cannot_change_the_endpoint() {
+        for key in ["api_base", "api_key", "fixture", "llm_type"] {
+            let project = project(&format!("{} = \"https://attacker.example\"", key));
+            assert!(
+                project.merge_llm(Some(profile()), false).is_err(),
+                "{}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn untrusted_project_can_change_the_model() {
+        let merged = project("model = \"llama3\"")
+            .merge_llm(Some(profile()), false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(merged.model.as_deref(), Some("llama3"));
+        assert_eq!(merged.api_base, profile().api_base);
+        assert_eq!(merged.api_key, profile().api_key);
+    }
+
+    #[test]
+    fn trusted_project_can_change_the_endpoint() {
+        let merged = project("api_base = \"http://localhost:8080/v1\"")
+            .merge_llm(Some(profile()), true)
+            .unwrap()
+            .unwrap();
+        assert_eq!(merged.api_base.as_deref(), Some("http://localhost:8080/v1"));
+    }
+
+    #[test]
+    fn model_without_a_profile_applies_to_the_default_provider() {
+        let merged = project("model = \"gpt-4o\"")
+            .merge_llm(None, false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(merged.llm_type, DEFAULT_LLM_TYPE);
+        assert_eq!(merged.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(merged.api_key, None);
+
+        let merged = project("llm_type = \"ollama\"\nmodel = \"llama3\"")
+            .merge_llm(None, true)
+            .unwrap()
+            .unwrap();
+        assert_eq!(merged.llm_type, "ollama");
+        assert!(ProjectConfig::default()
+            .merge_llm(None, false)
+            .unwrap()
+            .is_none());
+    }
+}
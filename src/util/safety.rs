@@ -0,0 +1,291 @@
+use std::fmt;
+
+use dialoguer::console::style;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::CopilotError;
+
+use super::shell::parse_suggestion;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for RiskLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RiskLevel::Low => write!(f, "low"),
+            RiskLevel::Medium => write!(f, "medium"),
+            RiskLevel::High => write!(f, "high"),
+        }
+    }
+}
+
+/// How risky it is to run a command, and why.
+#[derive(Debug, Clone, Serialize)]
+pub struct Risk {
+    pub level: RiskLevel,
+    pub reasons: Vec<String>,
+}
+
+impl Risk {
+    fn flag(&mut self, level: RiskLevel, reason: String) {
+        self.level = self.level.max(level);
+        if !self.reasons.contains(&reason) {
+            self.reasons.push(reason);
+        }
+    }
+
+    pub fn print(&self) {
+        let level = match self.level {
+            RiskLevel::Low => style(format!("{} risk", self.level)).green().bold(),
+            RiskLevel::Medium => style(format!("{} risk", self.level)).yellow().bold(),
+            RiskLevel::High => style(format!("{} risk", self.level)).red().bold(),
+        };
+        println!("{} {}", style("Warning:").yellow().bold(), level);
+        for reason in &self.reasons {
+            println!("  - {}", reason);
+        }
+        println!();
+    }
+}
+
+/// Regexes matched against each command: `allow` silences the built-in rules, `deny`
+/// always makes the command high risk.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SafetyConfig {
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl SafetyConfig {
+    /// Adds the `deny` patterns of a repository config to these ones. Its `allow` patterns
+    /// are ignored, a repository could otherwise silence the rules for any command.
+    pub fn merge_project(mut self, project: &SafetyConfig) -> Self {
+        self.deny.extend(project.deny.iter().cloned());
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), CopilotError> {
+        for pattern in self.allow.iter().chain(&self.deny) {
+            Regex::new(pattern).map_err(|e| {
+                CopilotError::Config(format!("Invalid [safety] pattern '{}': {}", pattern, e))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// A built-in destructive pattern. Only the first matching rule of each kind counts, so
+/// the more specific rules of a kind come first.
+struct Rule {
+    kind: &'static str,
+    pattern: &'static str,
+    level: RiskLevel,
+    reason: &'static str,
+}
+
+const RULES: [Rule; 15] = [
+    Rule {
+        kind: "rm",
+        pattern: r"\brm\s+(-\S+\s+)*(/|~|\*|/\*|\$HOME)(\s|$)",
+        level: RiskLevel::High,
+        reason: "deletes the root, the home or every file of a directory",
+    },
+    Rule {
+        kind: "rm",
+        pattern: r"\brm\s+(-\S*[rR]\S*f|-\S*f\S*[rR]|(-\S+\s+)*(-[rR]|--recursive)\s+(-\S+\s+)*(-f|--force))\b",
+        level: RiskLevel::High,
+        reason: "recursively force-deletes files",
+    },
+    Rule {
+        kind: "rm",
+        pattern: r"\brm\s+(-\S+\s+)*(-[a-zA-Z]*[rR][a-zA-Z]*|--recursive)\s",
+        level: RiskLevel::Medium,
+        reason: "recursively deletes files",
+    },
+    Rule {
+        kind: "dd",
+        pattern: r"\bdd\s.*\bof=",
+        level: RiskLevel::High,
+        reason: "writes raw data over a device or file",
+    },
+    Rule {
+        kind: "mkfs",
+        pattern: r"\bmkfs(\.\w+)?\b",
+        level: RiskLevel::High,
+        reason: "formats a filesystem",
+    },
+    Rule {
+        kind: "chmod",
+        pattern: r"\bchmod\s+(-\S+\s+)*(-R|--recursive)\s+(-\S+\s+)*0?777\b",
+        level: RiskLevel::High,
+        reason: "makes a whole tree writable by everyone",
+    },
+    Rule {
+        kind: "chmod",
+        pattern: r"\bchmod\s+(-\S+\s+)*0?777\b",
+        level: RiskLevel::Medium,
+        reason: "makes files writable by everyone",
+    },
+    Rule {
+        kind: "pipe",
+        pattern: r"\b(curl|wget)\b[^|]*\|\s*(sudo\s+)?(ba|z|da|k)?sh\b",
+        level: RiskLevel::High,
+        reason: "runs a script downloaded from the internet",
+    },
+    Rule {
+        kind: "push",
+        pattern: r"\bgit\s+push\b.*\s(--force|-f|\+\S+)(\s|$)",
+        level: RiskLevel::High,
+        reason: "force-pushes and may overwrite remote history",
+    },
+    Rule {
+        kind: "push",
+        pattern: r"\bgit\s+push\b.*\s--force-with-lease\b",
+        level: RiskLevel::Medium,
+        reason: "force-pushes over remote history",
+    },
+    Rule {
+        kind: "sql",
+        pattern: r"(?i)\b(drop\s+(table|database|schema)|truncate\s+table)\b",
+        level: RiskLevel::High,
+        reason: "drops or empties database tables",
+    },
+    Rule {
+        kind: "etc",
+        pattern: r"(>>?\s*|\btee\s+(-a\s+)?|\b(cp|mv|install|ln|sed\s+-i\S*)\s.*\s)/etc/",
+        level: RiskLevel::High,
+        reason: "writes under /etc",
+    },
+    Rule {
+        kind: "device",
+        pattern: r">\s*/dev/(sd|nvme|hd|disk)",
+        level: RiskLevel::High,
+        reason: "writes directly to a disk device",
+    },
+    Rule {
+        kind: "git",
+        pattern: r"\bgit\s+(reset\s+--hard|clean\s+-\S*f)",
+        level: RiskLevel::Medium,
+        reason: "discards local changes",
+    },
+    Rule {
+        kind: "sudo",
+        pattern: r"\bsudo\b",
+        level: RiskLevel::Medium,
+        reason: "runs with root privileges",
+    },
+];
+
+/// Checks every command of a suggestion against the built-in rules and the configured
+/// allow and deny lists. Pipelines are checked whole, so `curl ... | sh` is seen as one
+/// command.
+pub fn analyze(input: &str, config: &SafetyConfig) -> Risk {
+    let compile = |patterns: &[String]| {
+        patterns
+            .iter()
+            .filter_map(|p| Regex::new(p).ok())
+            .collect::<Vec<Regex>>()
+    };
+    let allow = compile(&config.allow);
+    let deny = compile(&config.deny);
+
+    let mut risk = Risk {
+        level: RiskLevel::Low,
+        reasons: Vec::new(),
+    };
+    // Lines the parser cannot make sense of are still checked as they are.
+    let (nodes, unparsed) = parse_suggestion(input);
+    let commands = nodes
+        .iter()
+        .flat_map(|node| node.pipelines())
+        .map(|node| node.text.clone())
+        .chain(unparsed);
+    for command in commands {
+        if let Some(re) = deny.iter().find(|re| re.is_match(&command)) {
+            risk.flag(
+                RiskLevel::High,
+                format!("`{}` is denied by the pattern '{}'", command, re.as_str()),
+            );
+            continue;
+        }
+        if allow.iter().any(|re| re.is_match(&command)) {
+            continue;
+        }
+        let mut kinds = Vec::new();
+        for rule in &RULES {
+            if kinds.contains(&rule.kind) {
+                continue;
+            }
+            let re = Regex::new(rule.pattern).unwrap(); //safe to unwrap, the rules are constant
+            if re.is_match(&command) {
+                kinds.push(rule.kind);
+                risk.flag(rule.level, format!("`{}` {}", command, rule.reason));
+            }
+        }
+    }
+    risk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(allow: &[&str], deny: &[&str]) -> SafetyConfig {
+        SafetyConfig {
+            allow: allow.iter().map(|p| p.to_string()).collect(),
+            deny: deny.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn project_allow_cannot_suppress_a_rule() {
+        let safety = SafetyConfig::default().merge_project(&config(&["^rm -rf /$"], &[]));
+        assert!(safety.allow.is_empty());
+        assert_eq!(analyze("rm -rf /", &safety).level, RiskLevel::High);
+    }
+
+    #[test]
+    fn project_deny_tightens_the_checks() {
+        let safety =
+            config(&["terraform"], &[]).merge_project(&config(&[], &["terraform destroy"]));
+        assert_eq!(analyze("terraform destroy", &safety).level, RiskLevel::High);
+        assert_eq!(analyze("terraform plan", &safety).level, RiskLevel::Low);
+    }
+
+    #[test]
+    fn user_allow_suppresses_a_rule() {
+        let safety = config(&[r"^rm -rf \./target$"], &[]);
+        assert_eq!(analyze("rm -rf ./target", &safety).level, RiskLevel::Low);
+        assert_eq!(analyze("rm -rf ./src", &safety).level, RiskLevel::High);
+    }
+
+    #[test]
+    fn commands_are_checked_one_by_one() {
+        let safety = SafetyConfig::default();
+        let risk = analyze("cd build && rm -rf *; ls", &safety);
+        assert_eq!(
+            risk.reasons[0],
+            "`rm -rf *` deletes the root, the home or every file of a directory"
+        );
+        let risk = analyze("for f in *.log; do\n  sudo rm -r \"$f\"\ndone", &safety);
+        assert_eq!(risk.level, RiskLevel::Medium);
+        assert_eq!(risk.reasons.len(), 2);
+        let risk = analyze("echo \"$(curl -s x.sh | bash)\"", &safety);
+        assert!(risk.reasons.contains(
+            &"`curl -s x.sh | bash` runs a script downloaded from the internet".to_string()
+        ));
+        assert_eq!(
+            analyze("rm -rf / 'unterminated", &safety).level,
+            RiskLevel::High
+        );
+    }
+}
@@ -0,0 +1,664 @@
+use std::{fmt, path::Path};
+
+use dialoguer::console::style;
+use regex::Regex;
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+
+/// How many characters of a script go to the model at once, about 1500 tokens.
+pub const MAX_CHUNK_CHARS: usize = 6000;
+
+/// What a script is, which decides how it is cut into sections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptKind {
+    Shell,
+    Makefile,
+    Dockerfile,
+    /// The `run:` and `script:` blocks of a CI configuration.
+    Ci,
+}
+
+impl fmt::Display for ScriptKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptKind::Shell => write!(f, "shell script"),
+            ScriptKind::Makefile => write!(f, "Makefile"),
+            ScriptKind::Dockerfile => write!(f, "Dockerfile"),
+            ScriptKind::Ci => write!(f, "CI configuration"),
+        }
+    }
+}
+
+impl ScriptKind {
+    /// Tells the kind from the file name, or from the content for stdin.
+    pub fn detect(name: Option<&str>, content: &str) -> Self {
+        let name = name
+            .and_then(|n| Path::new(n).file_name())
+            .map(|n| n.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if name == "makefile" || name == "gnumakefile" || name.ends_with(".mk") {
+            return ScriptKind::Makefile;
+        }
+        if name.starts_with("dockerfile")
+            || name.ends_with(".dockerfile")
+            || name == "containerfile"
+        {
+            return ScriptKind::Dockerfile;
+        }
+        if name.ends_with(".yml") || name.ends_with(".yaml") {
+            return ScriptKind::Ci;
+        }
+        if !name.is_empty() {
+            return ScriptKind::Shell;
+        }
+
+        let first = content
+            .lines()
+            .map(str::trim)
+            .find(|l| !l.is_empty() && !l.starts_with('#'))
+            .unwrap_or_default();
+        let rule = Regex::new(r"(?m)^[^\s#=:]+\s*:[^=]*\n\t").unwrap(); //safe to unwrap
+        let ci = Regex::new(r"(?m)^\s*(-\s+)?(run|script|steps|jobs):").unwrap(); //safe to unwrap
+        if first.to_uppercase().starts_with("FROM ") {
+            ScriptKind::Dockerfile
+        } else if rule.is_match(content) {
+            ScriptKind::Makefile
+        } else if ci.is_match(content) && !content.starts_with("#!") {
+            ScriptKind::Ci
+        } else {
+            ScriptKind::Shell
+        }
+    }
+}
+
+/// A part of a script explained on its own, with its lines numbered from 1.
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub title: String,
+    pub lines: Vec<(usize, String)>,
+    pub explanation: Option<String>,
+}
+
+impl Serialize for Section {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut section = serializer.serialize_struct("Section", 4)?;
+        section.serialize_field("title", &self.title)?;
+        section.serialize_field("start", &self.start())?;
+        section.serialize_field("end", &self.end())?;
+        section.serialize_field("explanation", &self.explanation)?;
+        section.end()
+    }
+}
+
+impl Section {
+    pub fn start(&self) -> usize {
+        self.lines.first().map(|(n, _)| *n).unwrap_or_default()
+    }
+
+    pub fn end(&self) -> usize {
+        self.lines.last().map(|(n, _)| *n).unwrap_or_default()
+    }
+
+    /// The section with a heading and numbered lines, for the prompts.
+    pub fn numbered(&self) -> String {
+        let mut text = format!("# Lines {}-{}: {}\n", self.start(), self.end(), self.title);
+        for (number, line) in &self.lines {
+            text.push_str(&format!("{:>4}| {}\n", number, line));
+        }
+        text
+    }
+
+    fn new(title: String, lines: Vec<(usize, String)>) -> Self {
+        Section {
+            title,
+            lines,
+            explanation: None,
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.lines.iter().map(|(_, l)| l.len() + 7).sum::<usize>() + self.title.len()
+    }
+}
+
+/// Cuts a script into sections: paragraphs of a shell script or a Makefile, instructions
+/// of a Dockerfile, and the shell blocks of a CI configuration.
+pub fn sections(kind: ScriptKind, content: &str) -> Vec<Section> {
+    let lines = content
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l.to_string()))
+        .collect::<Vec<(usize, String)>>();
+    match kind {
+        ScriptKind::Shell | ScriptKind::Makefile => paragraphs(kind, &lines),
+        ScriptKind::Dockerfile => instructions(&lines),
+        ScriptKind::Ci => ci_blocks(&lines),
+    }
+}
+
+/// Packs sections into chunks of at most `max_chars`, splitting sections that are longer.
+pub fn chunks(sections: Vec<Section>, max_chars: usize) -> Vec<Vec<Section>> {
+    let mut chunks: Vec<Vec<Section>> = Vec::new();
+    let mut size = 0;
+    for section in sections.into_iter().flat_map(|s| split(s, max_chars)) {
+        match chunks.last_mut() {
+            Some(chunk) if size + section.size() <= max_chars => {
+                size += section.size();
+                chunk.push(section);
+            }
+            _ => {
+                size = section.size();
+                chunks.push(vec![section]);
+            }
+        }
+    }
+    chunks
+}
+
+fn split(section: Section, max_chars: usize) -> Vec<Section> {
+    if section.size() <= max_chars {
+        return vec![section];
+    }
+    let mut parts: Vec<Section> = Vec::new();
+    for line in section.lines {
+        match parts.last_mut() {
+            Some(part) if part.size() + line.1.len() + 7 <= max_chars => part.lines.push(line),
+            _ => parts.push(Section::new(
+                format!("{} (part {})", section.title, parts.len() + 1),
+                vec![line],
+            )),
+        }
+    }
+    parts
+}
+
+/// Blocks of lines separated by blank lines, titled after their first comment, function
+/// or Makefile target.
+fn paragraphs(kind: ScriptKind, lines: &[(usize, String)]) -> Vec<Section> {
+    let target = Regex::new(r"^([^\s#=:][^=:]*?)\s*::?(\s|$)").unwrap(); //safe to unwrap
+    let function = Regex::new(r"^(function\s+)?([\w.-]+)\s*\(\)").unwrap(); //safe to unwrap
+    lines
+        .split(|(_, l)| l.trim().is_empty())
+        .filter(|block| !block.is_empty())
+        .map(|block| {
+            let title = block
+                .iter()
+                .find_map(|(_, l)| match kind {
+                    ScriptKind::Makefile => target
+                        .captures(l)
+                        .map(|c| format!("target {}", c[1].trim())),
+                    _ => function
+                        .captures(l)
+                        .map(|c| format!("function {}", &c[2]))
+                        .or_else(|| {
+                            l.trim()
+                                .strip_prefix('#')
+                                .filter(|c| !c.starts_with('!') && !c.trim().is_empty())
+                                .map(|c| c.trim().to_string())
+                        }),
+                })
+                .unwrap_or_else(|| {
+                    first_code_line(block).unwrap_or_else(|| block[0].1.trim().to_string())
+                });
+            Section::new(title, block.to_vec())
+        })
+        .collect()
+}
+
+/// One section per instruction, continuation lines and comments included.
+fn instructions(lines: &[(usize, String)]) -> Vec<Section> {
+    let mut sections: Vec<Section> = Vec::new();
+    let mut continued = false;
+    let mut comments: Vec<(usize, String)> = Vec::new();
+    for (number, line) in lines {
+        let trimmed = line.trim();
+        if trimmed.is_empty() && !continued {
+            continue;
+        }
+        if trimmed.starts_with('#') && !continued {
+            comments.push((*number, line.clone()));
+            continue;
+        }
+        match sections.last_mut() {
+            Some(section) if continued => section.lines.push((*number, line.clone())),
+            _ => {
+                let keyword = trimmed.split_whitespace().next().unwrap_or_default();
+                let mut lines = std::mem::take(&mut comments);
+                lines.push((*number, line.clone()));
+                sections.push(Section::new(keyword.to_uppercase(), lines));
+            }
+        }
+        continued = trimmed.ends_with('\\');
+    }
+    sections
+}
+
+/// The shell of `run:` keys, like GitHub Actions has, and of `script:` lists, like GitLab
+/// CI has, titled after the step or job they belong to.
+fn ci_blocks(lines: &[(usize, String)]) -> Vec<Section> {
+    let key = Regex::new(r"^(\s*)(-\s+)?(run|script|before_script|after_script):\s*(.*)$").unwrap(); //safe to unwrap
+    let name = Regex::new(r"^(\s*)(-\s+)?name:\s*(.+)$").unwrap(); //safe to unwrap
+    let job = Regex::new(r"^(\s*)([\w.-]+):\s*$").unwrap(); //safe to unwrap
+    let mut sections = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let Some(cap) = key.captures(&lines[i].1) else {
+            i += 1;
+            continue;
+        };
+        let indent = cap[1].len() + cap.get(2).map(|m| m.len()).unwrap_or(0);
+        let value = cap[4].trim().to_string();
+        let title = lines[..i]
+            .iter()
+            .rev()
+            .find_map(|(_, l)| {
+                let step = name
+                    .captures(l)
+                    .map(|c| c[3].trim().trim_matches('"').to_string());
+                let job = job
+                    .captures(l)
+                    .filter(|c| c[1].len() < indent)
+                    .map(|c| format!("job {}", &c[2]));
+                step.or(job)
+            })
+            .unwrap_or_else(|| cap[3].to_string());
+
+        let mut block = Vec::new();
+        let scalar = value.is_empty() || value.starts_with('|') || value.starts_with('>');
+        if !scalar {
+            block.push((lines[i].0, value.trim_matches(['"', '\'']).to_string()));
+        }
+        i += 1;
+        if scalar {
+            // An indented block scalar, or a list of commands.
+            while i < lines.len() {
+                let line = &lines[i].1;
+                let line_indent = line.len() - line.trim_start().len();
+                if !line.trim().is_empty() && line_indent <= indent {
+                    break;
+                }
+                let command = line.trim().strip_prefix("- ").unwrap_or(line.trim());
+                if !command.is_empty() {
+                    block.push((lines[i].0, command.trim_matches(['"', '\'']).to_string()));
+                }
+                i += 1;
+            }
+        }
+        if !block.is_empty() {
+            sections.push(Section::new(title, block));
+        }
+    }
+    sections
+}
+
+fn first_code_line(block: &[(usize, String)]) -> Option<String> {
+    block
+        .iter()
+        .map(|(_, l)| l.trim())
+        .find(|l| !l.starts_with('#'))
+        .map(|l| l.to_string())
+}
+
+/// Gives each section the part of an answer under its `Lines <start>-<end>` heading.
+pub fn annotate(sections: &mut [Section], answer: &str) {
+    let heading = Regex::new(r"(?m)^\W*Lines?\s+(\d+)\b.*$").unwrap(); //safe to unwrap
+    let headings = heading.captures_iter(answer).collect::<Vec<_>>();
+    for (i, cap) in headings.iter().enumerate() {
+        //safe to unwrap, group 0 always takes part in a match
+        let start = cap.get(0).unwrap().end();
+        let end = headings
+            .get(i + 1)
+            .and_then(|c| c.get(0))
+            .map(|m| m.start())
+            .unwrap_or(answer.len());
+        let line = cap[1].parse::<usize>().unwrap_or_default();
+        if let Some(section) = sections.iter_mut().find(|s| s.start() == line) {
+            section.explanation = Some(answer[start..end].trim().to_string());
+        }
+    }
+}
+
+/// Something a script does outside of its own process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SideEffectKind {
+    Network,
+    FilesystemWrite,
+    PrivilegeEscalation,
+}
+
+impl fmt::Display for SideEffectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SideEffectKind::Network => write!(f, "Network"),
+            SideEffectKind::FilesystemWrite => write!(f, "Filesystem writes"),
+            SideEffectKind::PrivilegeEscalation => write!(f, "Privilege escalation"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SideEffect {
+    pub kind: SideEffectKind,
+    pub line: usize,
+    pub text: String,
+}
+
+/// Patterns of the side effects looked for in every line.
+const SIDE_EFFECTS: [(SideEffectKind, &str); 11] = [
+    (
+        SideEffectKind::Network,
+        r"\b(curl|wget|ssh|scp|sftp|rsync|nc|ncat|telnet|ftp)\s",
+    ),
+    (
+        SideEffectKind::Network,
+        r"\bgit\s+(clone|fetch|pull|push|submodule\s+update)\b",
+    ),
+    (
+        SideEffectKind::Network,
+        r"\b(apt|apt-get|yum|dnf|apk|brew|pacman|zypper)\s+(-\S+\s+)*(install|update|upgrade|add|-S\w*)\b",
+    ),
+    (
+        SideEffectKind::Network,
+        r"\b(pip3?|npm|yarn|pnpm|gem|cargo|go|composer)\s+(install|add|get|ci|publish)\b",
+    ),
+    (SideEffectKind::Network, r"\bdocker\s+(pull|push|login)\b"),
+    (
+        SideEffectKind::FilesystemWrite,
+        r"(^|[\s;&|])(rm|rmdir|mv|cp|mkdir|touch|ln|tee|truncate|dd|chmod|chown|chgrp|unzip)\s",
+    ),
+    (
+        SideEffectKind::FilesystemWrite,
+        r"\bsed\s+(-\w+\s+)*-\w*i\b|\btar\s+-?\w*x",
+    ),
+    (
+        SideEffectKind::PrivilegeEscalation,
+        r"(^|[\s;&|(])(sudo|doas|su|pkexec)(\s|$)",
+    ),
+    (
+        SideEffectKind::PrivilegeEscalation,
+        r"\bchmod\s+(-\w+\s+)*[ugoa]*\+\w*s|\bsetcap\b",
+    ),
+    (SideEffectKind::PrivilegeEscalation, r"--privileged\b"),
+    (SideEffectKind::PrivilegeEscalation, r"^\s*USER\s+root\b"),
+];
+
+/// The lines of the sections that reach the network, write files or gain privileges.
+pub fn side_effects(sections: &[Section]) -> Vec<SideEffect> {
+    let rules = SIDE_EFFECTS
+        .iter()
+        .map(|(kind, pattern)| (*kind, Regex::new(pattern).unwrap())) //safe to unwrap
+        .collect::<Vec<(SideEffectKind, Regex)>>();
+    // Output redirected to a file, but not to `/dev/null` or another descriptor.
+    let redirect = Regex::new(r"(^|[^<>&=-])\d?>>?\s*([^\s&>|;()]+)").unwrap(); //safe to unwrap
+    let mut effects: Vec<SideEffect> = Vec::new();
+    for (number, line) in sections.iter().flat_map(|s| &s.lines) {
+        let code = line.trim();
+        if code.starts_with('#') {
+            continue;
+        }
+        let writes = redirect
+            .captures_iter(code)
+            .any(|c| !c[2].starts_with("/dev/"));
+        for (kind, re) in &rules {
+            let known = effects.iter().any(|e| e.kind == *kind && e.line == *number);
+            let found = re.is_match(code) || (*kind == SideEffectKind::FilesystemWrite && writes);
+            if !known && found {
+                effects.push(SideEffect {
+                    kind: *kind,
+                    line: *number,
+                    text: code.to_string(),
+                });
+            }
+        }
+    }
+    effects.sort_by_key(|e| (e.kind, e.line));
+    effects
+}
+
+/// The side effects grouped by kind, styled for the terminal or not.
+pub fn describe_side_effects(effects: &[SideEffect], styled: bool) -> String {
+    let title = "Side effects:";
+    let mut lines = vec![match styled {
+        true => style(title).white().bold().to_string(),
+        false => title.to_string(),
+    }];
+    if effects.is_empty() {
+        lines.push("  None found.".to_string());
+    }
+    let mut current = None;
+    for effect in effects {
+        if current != Some(effect.kind) {
+            current = Some(effect.kind);
+            lines.push(format!("  {}", effect.kind));
+        }
+        let number = format!("{:>4}:", effect.line);
+        let number = match styled {
+            true => style(number).dim().to_string(),
+            false => number,
+        };
+        lines.push(format!("  {} {}", number, effect.text));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHELL: &str = "#!/bin/sh
+set -e
+
+# Download the release
+curl -fsSL https://example.com/app.tar.gz -o app.tar.gz
+tar -xzf app.tar.gz
+
+install_app() {
+  sudo cp app /usr/local/bin/
+}
+install_app > install.log 2>/dev/null
+";
+
+    const MAKEFILE: &str = "CC = gcc
+
+build: main.c
+\t$(CC) -o app main.c
+
+clean:
+\trm -f app
+";
+
+    const DOCKERFILE: &str = "FROM rust:1.80
+# Build the app
+RUN apt-get update && \\
+    apt-get install -y libssl-dev
+COPY . /app
+
+USER root
+";
+
+    const GITHUB: &str = "name: CI
+on: push
+jobs:
+  test:
+    runs-on: ubuntu-latest
+    steps:
+      - uses: actions/checkout@v4
+      - name: Run tests
+        run: cargo test
+      - name: Lint
+        run: |
+          cargo fmt --check
+          cargo clippy
+";
+
+    const GITLAB: &str = "stages:
+  - test
+unit:
+  stage: test
+  script:
+    - npm ci
+    - npm test
+";
+
+    fn titles(kind: ScriptKind, content: &str) -> Vec<(String, usize, usize)> {
+        sections(kind, content)
+            .into_iter()
+            .map(|s| (s.title.clone(), s.start(), s.end()))
+            .collect()
+    }
+
+    #[test]
+    fn kinds_are_detected_from_the_name_or_the_content() {
+        let cases = [
+            (Some("Makefile"), "", ScriptKind::Makefile),
+            (Some("build/rules.mk"), "", ScriptKind::Makefile),
+            (Some("Dockerfile.dev"), "", ScriptKind::Dockerfile),
+            (Some("app.dockerfile"), "", ScriptKind::Dockerfile),
+            (Some("Containerfile"), "", ScriptKind::Dockerfile),
+            (Some(".github/workflows/ci.yml"), "", ScriptKind::Ci),
+            (Some(".gitlab-ci.yaml"), "", ScriptKind::Ci),
+            (Some("deploy.sh"), GITHUB, ScriptKind::Shell),
+            (None, SHELL, ScriptKind::Shell),
+            (None, MAKEFILE, ScriptKind::Makefile),
+            (None, DOCKERFILE, ScriptKind::Dockerfile),
+            (None, "# base\nfrom alpine\n", ScriptKind::Dockerfile),
+            (None, GITHUB, ScriptKind::Ci),
+            (None, GITLAB, ScriptKind::Ci),
+            (None, "#!/bin/sh\nscript: x\n", ScriptKind::Shell),
+        ];
+        for (name, content, kind) in cases {
+            assert_eq!(ScriptKind::detect(name, content), kind, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn scripts_are_cut_into_sections() {
+        let cases = [
+            (
+                ScriptKind::Shell,
+                SHELL,
+                vec![
+                    ("set -e", 1, 2),
+                    ("Download the release", 4, 6),
+                    ("function install_app", 8, 11),
+                ],
+            ),
+            (
+                ScriptKind::Makefile,
+                MAKEFILE,
+                vec![
+                    ("CC = gcc", 1, 1),
+                    ("target build", 3, 4),
+                    ("target clean", 6, 7),
+                ],
+            ),
+            (
+                ScriptKind::Dockerfile,
+                DOCKERFILE,
+                vec![
+                    ("FROM", 1, 1),
+                    ("RUN", 2, 4),
+                    ("COPY", 5, 5),
+                    ("USER", 7, 7),
+                ],
+            ),
+            (
+                ScriptKind::Ci,
+                GITHUB,
+                vec![("Run tests", 9, 9), ("Lint", 12, 13)],
+            ),
+            (ScriptKind::Ci, GITLAB, vec![("job unit", 6, 7)]),
+        ];
+        for (kind, content, expected) in cases {
+            let expected = expected
+                .into_iter()
+                .map(|(title, start, end)| (title.to_string(), start, end))
+                .collect::<Vec<_>>();
+            assert_eq!(titles(kind, content), expected, "{}", kind);
+        }
+    }
+
+    #[test]
+    fn ci_blocks_hold_only_the_commands() {
+        let lines = sections(ScriptKind::Ci, GITHUB)
+            .into_iter()
+            .flat_map(|s| s.lines)
+            .map(|(_, line)| line)
+            .collect::<Vec<String>>();
+        assert_eq!(lines, ["cargo test", "cargo fmt --check", "cargo clippy"]);
+        let lines = sections(ScriptKind::Ci, GITLAB)
+            .into_iter()
+            .flat_map(|s| s.lines)
+            .map(|(_, line)| line)
+            .collect::<Vec<String>>();
+        assert_eq!(lines, ["npm ci", "npm test"]);
+    }
+
+    #[test]
+    fn chunks_hold_whole_sections_within_the_limit() {
+        let sections = sections(ScriptKind::Shell, SHELL);
+        let one = chunks(sections.clone(), MAX_CHUNK_CHARS);
+        assert_eq!(one.len(), 1);
+        assert_eq!(one[0].len(), 3);
+
+        let limit = sections.iter().map(Section::size).max().unwrap();
+        let several = chunks(sections.clone(), limit);
+        assert!(several.len() > 1);
+        assert!(several
+            .iter()
+            .all(|chunk| chunk.iter().map(Section::size).sum::<usize>() <= limit));
+
+        // A section longer than the limit is split into parts.
+        let parts = chunks(sections[2..].to_vec(), 40);
+        let titles = parts
+            .iter()
+            .flatten()
+            .map(|s| s.title.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(titles[0], "function install_app (part 1)");
+        assert_eq!(
+            parts.iter().flatten().map(|s| s.lines.len()).sum::<usize>(),
+            4
+        );
+    }
+
+    #[test]
+    fn side_effects_are_found_per_line() {
+        let cases = [
+            (
+                ScriptKind::Shell,
+                SHELL,
+                vec![
+                    (SideEffectKind::Network, 5),
+                    (SideEffectKind::FilesystemWrite, 6),
+                    (SideEffectKind::FilesystemWrite, 9),
+                    (SideEffectKind::FilesystemWrite, 11),
+                    (SideEffectKind::PrivilegeEscalation, 9),
+                ],
+            ),
+            (
+                ScriptKind::Makefile,
+                MAKEFILE,
+                vec![(SideEffectKind::FilesystemWrite, 7)],
+            ),
+            (
+                ScriptKind::Dockerfile,
+                DOCKERFILE,
+                vec![
+                    (SideEffectKind::Network, 3),
+                    (SideEffectKind::Network, 4),
+                    (SideEffectKind::PrivilegeEscalation, 7),
+                ],
+            ),
+            (ScriptKind::Ci, GITHUB, vec![]),
+            (ScriptKind::Ci, GITLAB, vec![(SideEffectKind::Network, 6)]),
+        ];
+        for (kind, content, expected) in cases {
+            let found = side_effects(&sections(kind, content))
+                .into_iter()
+                .map(|e| (e.kind, e.line))
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected, "{}", kind);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::{fs, path::PathBuf};
+
+use langchain_rust::{
+    memory::WindowBufferMemory,
+    schemas::{memory::BaseMemory, Message, MessageType},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::CopilotError;
+
+use super::shared::expand_home;
+
+/// Directory holding one `<name>.json` file per saved chat session.
+pub const SESSIONS_PATH: &str = "~/.free_copilot_sessions";
+
+/// How many messages of a conversation are kept and sent back to the model.
+const MAX_HISTORY_MESSAGES: usize = 20;
+
+/// A chat session saved to disk.
+#[derive(Serialize, Deserialize)]
+struct SessionFile {
+    messages: Vec<Message>,
+    #[serde(default)]
+    last_command: Option<String>,
+}
+
+/// The memory of a chat: its latest messages and the last command the model suggested.
+pub struct Session {
+    pub name: Option<String>,
+    memory: WindowBufferMemory,
+    pub last_command: Option<String>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session {
+            name: None,
+            memory: WindowBufferMemory::new(MAX_HISTORY_MESSAGES),
+            last_command: None,
+        }
+    }
+}
+
+impl Session {
+    /// Loads the session saved as `name`, or starts a new one with that name.
+    pub fn load(name: &str) -> Result<Self, CopilotError> {
+        let mut session = Session {
+            name: Some(name.to_string()),
+            ..Session::default()
+        };
+        let path = session_file(name)?;
+        if !path.exists() {
+            return Ok(session);
+        }
+        let file: SessionFile = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| CopilotError::Config(format!("Invalid session {}: {}", name, e)))?;
+        for message in file.messages {
+            session.memory.add_message(message);
+        }
+        session.last_command = file.last_command;
+        Ok(session)
+    }
+
+    /// Saves the session under its name and returns the file it was saved to.
+    pub fn save(&self) -> Result<PathBuf, CopilotError> {
+        let name = self.name.as_deref().ok_or_else(|| {
+            CopilotError::Usage("Give the session a name: /save <name>".to_string())
+        })?;
+        let path = session_file(name)?;
+        //safe to unwrap, the sessions directory is always a parent
+        fs::create_dir_all(path.parent().unwrap())?;
+        let file = SessionFile {
+            messages: self.memory.messages(),
+            last_command: self.last_command.clone(),
+        };
+        //safe to unwrap, messages are always serializable
+        fs::write(&path, serde_json::to_string_pretty(&file).unwrap())?;
+        Ok(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.memory.messages().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Records a message of the user and the answer to it.
+    pub fn add(&mut self, input: &str, answer: &str) {
+        self.memory.add_user_message(&input);
+        self.memory.add_ai_message(&answer);
+    }
+
+    /// Records something the user did outside of the conversation, like running a command.
+    pub fn note(&mut self, note: &str) {
+        self.memory.add_user_message(&note);
+    }
+
+    pub fn reset(&mut self) {
+        self.memory.clear();
+        self.last_command = None;
+    }
+
+    /// The conversation so far, for the prompt.
+    pub fn history(&self) -> String {
+        let messages = self.memory.messages();
+        if messages.is_empty() {
+            return "Nothing yet.".to_string();
+        }
+        messages
+            .iter()
+            .map(|m| match m.message_type {
+                MessageType::AIMessage => format!("Assistant: {}", m.content),
+                _ => format!("User: {}", m.content),
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n")
+    }
+}
+
+/// The file a session is saved to. Names are kept to a single path component.
+pub fn session_file(name: &str) -> Result<PathBuf, CopilotError> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        || name.starts_with('.')
+    {
+        return Err(CopilotError::Usage(format!(
+            "Invalid session name '{}', use letters, digits, '-', '_' and '.'",
+            name
+        )));
+    }
+    Ok(expand_home(SESSIONS_PATH).join(format!("{}.json", name)))
+}
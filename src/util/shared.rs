@@ -1,119 +1,392 @@
-use dialoguer::console::{style, StyledObject};
+use std::{collections::BTreeMap, path::PathBuf, sync::OnceLock};
+
+use dialoguer::console::{style, StyledObject, Term};
 use langchain_rust::{
     llm::{AzureConfig, Claude, OpenAI},
     tools::OpenAIConfig,
 };
 use regex::Regex;
 
-use crate::COPILOT_PATH;
+use crate::error::CopilotError;
 
-use super::{config::LLMConfig, llm::LLMVariant};
+use super::{
+    cache::{CacheConfig, CachedLLM},
+    config::{ConfigFile, LLMConfig},
+    environment::{Environment, EnvironmentConfig},
+    llm::LLMVariant,
+    mock::MockLLM,
+    output::OutputFormat,
+    project::{git_root, ProjectConfig, DEFAULT_LLM_TYPE},
+    safety::SafetyConfig,
+    templates::load_prompts,
+};
 
 pub struct SharedState {
     llm: LLMVariant,
     os: String,
+    provider: String,
+    model: String,
+    api_base: Option<String>,
+    format: OutputFormat,
+    interactive: bool,
+    stream: bool,
+    cache: CacheConfig,
+    caching: bool,
+    profile: Option<String>,
+    project: ProjectConfig,
+    prompts: BTreeMap<String, String>,
+    safety: SafetyConfig,
+    shell_output: Option<PathBuf>,
+    environment_config: EnvironmentConfig,
+    environment: OnceLock<Environment>,
 }
 
 impl SharedState {
-    pub fn new(llm: LLMVariant, os: &str) -> Self {
+    pub fn new(llm: LLMVariant, os: &str, provider: &str, model: &str) -> Self {
         SharedState {
             llm,
             os: os.to_string(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            api_base: None,
+            format: OutputFormat::Text,
+            interactive: true,
+            stream: true,
+            cache: CacheConfig::default(),
+            caching: true,
+            profile: None,
+            project: ProjectConfig::default(),
+            prompts: BTreeMap::new(),
+            safety: SafetyConfig::default(),
+            shell_output: None,
+            environment_config: EnvironmentConfig::default(),
+            environment: OnceLock::new(),
+        }
+    }
+
+    /// Sets how results are printed. Any format other than text disables the menus.
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        if format != OutputFormat::Text {
+            self.interactive = false;
         }
+        self
+    }
+
+    pub fn with_interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive && self.format == OutputFormat::Text;
+        self
+    }
+
+    pub fn with_stream(mut self, stream: bool) -> Self {
+        self.stream = self.stream && stream;
+        self
+    }
+
+    pub fn with_cache(mut self, cache: CacheConfig) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn with_caching(mut self, caching: bool) -> Self {
+        self.caching = self.caching && caching;
+        self
+    }
+
+    pub fn with_api_base(mut self, api_base: Option<String>) -> Self {
+        self.api_base = api_base;
+        self
+    }
+
+    pub fn with_profile(mut self, profile: &str) -> Self {
+        self.profile = Some(profile.to_string());
+        self
+    }
+
+    pub fn with_project(mut self, project: ProjectConfig) -> Self {
+        self.project = project;
+        self
+    }
+
+    pub fn with_prompts(mut self, prompts: BTreeMap<String, String>) -> Self {
+        self.prompts = prompts;
+        self
+    }
+
+    pub fn with_safety(mut self, safety: SafetyConfig) -> Self {
+        self.safety = safety;
+        self
+    }
+
+    /// Hands accepted commands to a shell wrapper through this file instead of running them.
+    pub fn with_shell_output(mut self, shell_output: Option<PathBuf>) -> Self {
+        self.shell_output = shell_output;
+        self
+    }
+
+    pub fn with_environment(mut self, environment: EnvironmentConfig) -> Self {
+        self.environment_config = environment;
+        self
     }
 
+    /// The provider, behind the answer cache unless it is disabled. Answers of the `mock`
+    /// provider are never cached, so edits to its fixture show up at once.
     pub fn llm(&self) -> LLMVariant {
-        self.llm.clone()
+        match self.caching && self.cache.enabled() && self.provider != "mock" {
+            true => LLMVariant::Cached(CachedLLM::new(
+                self.llm.clone(),
+                &self.provider,
+                self.api_base(),
+                &self.model,
+                self.cache.clone(),
+            )),
+            false => self.llm.clone(),
+        }
+    }
+
+    pub fn cache(&self) -> &CacheConfig {
+        &self.cache
     }
 
     pub fn os(&self) -> &str {
         &self.os
     }
+
+    /// The environment commands run in, detected the first time it is needed.
+    pub fn environment(&self) -> &Environment {
+        self.environment
+            .get_or_init(|| Environment::detect(&self.os, &self.environment_config))
+    }
+
+    pub fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn api_base(&self) -> Option<&str> {
+        self.api_base.as_deref()
+    }
+
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    pub fn project(&self) -> &ProjectConfig {
+        &self.project
+    }
+
+    /// The template overriding the built-in prompt of the chain `name`, if any.
+    pub fn prompt(&self, name: &str) -> Option<&str> {
+        self.prompts.get(name).map(|p| p.as_str())
+    }
+
+    pub fn safety(&self) -> &SafetyConfig {
+        &self.safety
+    }
+
+    pub fn shell_output(&self) -> Option<&PathBuf> {
+        self.shell_output.as_ref()
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    pub fn interactive(&self) -> bool {
+        self.interactive
+    }
+
+    /// Answers are streamed token by token only for text output on a terminal.
+    pub fn streaming(&self) -> bool {
+        self.stream && self.format == OutputFormat::Text && Term::stdout().is_term()
+    }
 }
 
-impl Default for SharedState {
-    fn default() -> Self {
-        let os = std::env::consts::OS;
-        match read_config() {
-            Some(cfg) => {
-                match cfg.llm_type.as_str() {
-                    "openai" => {
-                        let mut llm = OpenAI::default().with_config(OpenAIConfig::default());
-                        let mut config = OpenAIConfig::default();
-                        if let Some(api_key) = cfg.api_key {
-                            config = config.with_api_key(api_key);
-                        }
-                        if let Some(api_base) = cfg.api_base {
-                            llm = llm.with_config(config.with_api_base(api_base));
-                        }
-                        if let Some(model) = cfg.model {
-                            llm = llm.with_model(&model);
-                        }
-                        return SharedState::new(LLMVariant::OpenAI(llm), os);
-                    }
-                    "ollama" => {
-                        let mut llm = OpenAI::default();
-                        let mut config = OpenAIConfig::default();
-                        config = config.with_api_key(cfg.api_key.unwrap_or("ollama".to_string()));
-                        config = config.with_api_base(
-                            cfg.api_base
-                                .unwrap_or("http://localhost:11434/v1".to_string()),
-                        );
-                        llm = llm.with_model(&cfg.model.unwrap_or("llama2".to_string()));
-                        llm = llm.with_config(config);
-
-                        return SharedState::new(LLMVariant::OpenAI(llm), os);
-                    }
-
-                    "anthropic" => {
-                        let mut llm = Claude::default();
-                        if let Some(api_key) = cfg.api_key {
-                            llm = llm.with_api_key(api_key);
-                        }
-                        if let Some(model) = cfg.model {
-                            llm = llm.with_model(&model);
-                        }
-                        return SharedState::new(LLMVariant::Anthropic(llm), os);
-                    }
-
-                    "azure_openai" => {
-                        let azure_config = AzureConfig::default()
-                            .with_api_key(cfg.api_key.expect("Azure API key not found"))
-                            .with_api_base(cfg.api_base.expect("Azure API base not found"))
-                            .with_api_version(cfg.api_version.expect("Azure API version not found"))
-                            .with_deployment_id(
-                                cfg.deployment.expect("Azure deployment ID not found"),
-                            );
-                        let llm = OpenAI::new(azure_config);
-                        return SharedState::new(LLMVariant::AzureOpenAI(llm), os);
-                    }
-
-                    // Handle other LLM types here
-                    _ => panic!("Unsupported LLM type"),
+impl SharedState {
+    pub fn from_config(cfg: LLMConfig, os: &str) -> Result<Self, CopilotError> {
+        let provider = cfg.llm_type.clone();
+        let api_base = cfg.api_base.clone();
+        let fixture = cfg.fixture.as_deref().map(expand_home);
+        let stream = cfg.stream.unwrap_or(true);
+        let (llm, model) = match cfg.llm_type.as_str() {
+            "openai" => {
+                let mut llm = OpenAI::default().with_config(OpenAIConfig::default());
+                let mut config = OpenAIConfig::default();
+                if let Some(api_key) = cfg.api_key {
+                    config = config.with_api_key(api_key);
+                }
+                if let Some(api_base) = cfg.api_base {
+                    llm = llm.with_config(config.with_api_base(api_base));
                 }
+                let model = cfg.model.unwrap_or("gpt-3.5-turbo".to_string());
+                llm = llm.with_model(&model);
+                (LLMVariant::OpenAI(llm), model)
             }
-            None => SharedState {
-                llm: LLMVariant::OpenAI(OpenAI::default()),
-                os: os.to_string(),
-            },
-        }
+            "ollama" => {
+                let mut llm = OpenAI::default();
+                let mut config = OpenAIConfig::default();
+                config = config.with_api_key(cfg.api_key.unwrap_or("ollama".to_string()));
+                config = config.with_api_base(
+                    api_base
+                        .clone()
+                        .unwrap_or("http://localhost:11434/v1".to_string()),
+                );
+                let model = cfg.model.unwrap_or("llama2".to_string());
+                llm = llm.with_model(&model);
+                llm = llm.with_config(config);
+
+                (LLMVariant::OpenAI(llm), model)
+            }
+
+            "anthropic" => {
+                let mut llm = Claude::default();
+                if let Some(api_key) = anthropic_key(&cfg, |name| std::env::var(name).ok()) {
+                    llm = llm.with_api_key(api_key);
+                }
+                let model = cfg.model.unwrap_or("claude-3-opus-20240229".to_string());
+                llm = llm.with_model(&model);
+                (LLMVariant::Anthropic(llm), model)
+            }
+
+            "azure_openai" => {
+                let deployment = cfg.deployment.ok_or_else(|| missing("deployment"))?;
+                let azure_config = AzureConfig::default()
+                    .with_api_key(cfg.api_key.ok_or_else(|| missing("api_key"))?)
+                    .with_api_base(cfg.api_base.ok_or_else(|| missing("api_base"))?)
+                    .with_api_version(cfg.api_version.ok_or_else(|| missing("api_version"))?)
+                    .with_deployment_id(deployment.clone());
+                let llm = OpenAI::new(azure_config);
+                (LLMVariant::AzureOpenAI(llm), deployment)
+            }
+
+            "mock" => {
+                let fixture = fixture.ok_or_else(|| missing("fixture"))?;
+                return Ok(SharedState::new(
+                    LLMVariant::Mock(MockLLM::new(fixture)),
+                    os,
+                    &provider,
+                    "fixture",
+                )
+                .with_stream(stream));
+            }
+
+            // Handle other LLM types here
+            other => {
+                return Err(CopilotError::Config(format!(
+                    "Unsupported llm_type '{}', run `config` to pick a provider",
+                    other
+                )))
+            }
+        };
+
+        // A fixture on a real provider records every answer so it can be replayed later.
+        let llm = match fixture {
+            Some(fixture) => LLMVariant::Mock(MockLLM::new(fixture).with_recorder(llm)),
+            None => llm,
+        };
+        Ok(SharedState::new(llm, os, &provider, &model)
+            .with_api_base(api_base)
+            .with_stream(stream))
     }
 }
 
-fn read_config() -> Option<LLMConfig> {
-    let mut config_path = home::home_dir()?;
-    config_path.push(COPILOT_PATH);
+/// The key of the profile, or the one of `CLAUDE_API_KEY` or `ANTHROPIC_API_KEY`. The client
+/// itself only reads the misspelled `CLOUDE_API_KEY`, which is still honored when neither
+/// is set. Profiles written by older wizards hold an empty key or the `ollama`
+/// placeholder, which are not keys.
+fn anthropic_key(cfg: &LLMConfig, env: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let configured = cfg
+        .api_key
+        .clone()
+        .filter(|key| !key.trim().is_empty() && key != "ollama");
+    configured.or_else(|| {
+        ["CLAUDE_API_KEY", "ANTHROPIC_API_KEY"]
+            .iter()
+            .find_map(|name| env(name).filter(|key| !key.is_empty()))
+    })
+}
 
-    let config_contents = std::fs::read_to_string(config_path).ok()?;
-    let config: Option<LLMConfig> = toml::from_str(&config_contents)
-        .map_err(|e| {
-            println!("Error reading config: {}", e);
-        })
-        .ok();
-    if config.is_none() {
-        return None;
+fn missing(field: &str) -> CopilotError {
+    CopilotError::Config(format!(
+        "The azure_openai profile has no {}, run `config` to set it",
+        field
+    ))
+}
+
+impl SharedState {
+    /// Builds the state from the profile selected by `--profile`, the repository config, the
+    /// subcommand mapping or the default profile, falling back to OpenAI when there is no
+    /// config at all. The repository `[llm]` settings are merged over that profile.
+    pub fn resolve(profile: Option<&str>, command: Option<&str>) -> Result<Self, CopilotError> {
+        let os = std::env::consts::OS;
+        let project = ProjectConfig::load()?;
+        let user = ConfigFile::load()?;
+        let prompts = load_prompts(user.as_ref(), &project)?
+            .into_iter()
+            .map(|(name, (template, _))| (name, template))
+            .collect();
+        let safety = user
+            .as_ref()
+            .map(|u| u.safety.clone())
+            .unwrap_or_default()
+            .merge_project(&project.safety);
+        safety.validate()?;
+        let environment = user
+            .as_ref()
+            .map(|u| u.environment.clone())
+            .unwrap_or_default()
+            .merge(&project.environment);
+        let cache = user.as_ref().map(|u| u.cache.clone()).unwrap_or_default();
+        let profile = profile.or(project.profile.as_deref());
+        let resolved = match &user {
+            Some(file) => file.resolve(profile, command)?,
+            None if profile.is_some() => {
+                return Err(CopilotError::Config(
+                    "No config found, create one with `config`".to_string(),
+                ))
+            }
+            None => None,
+        };
+        let (name, cfg) = match resolved {
+            Some((name, cfg)) => (Some(name), Some(cfg)),
+            None => (None, None),
+        };
+
+        let trusted = match (&user, git_root()) {
+            (Some(user), Some(root)) => user.trusts(&root),
+            _ => false,
+        };
+        let state = match project.merge_llm(cfg, trusted)? {
+            Some(cfg) => SharedState::from_config(cfg, os)?,
+            None => SharedState::new(
+                LLMVariant::OpenAI(OpenAI::default()),
+                os,
+                DEFAULT_LLM_TYPE,
+                "gpt-3.5-turbo",
+            ),
+        };
+        let state = match name {
+            Some(name) => state.with_profile(&name),
+            None => state,
+        };
+        Ok(state
+            .with_project(project)
+            .with_prompts(prompts)
+            .with_safety(safety)
+            .with_environment(environment)
+            .with_cache(cache))
+    }
+}
+
+/// Resolves a leading `~/` against the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
     }
-    Some(config.unwrap())
 }
 
 pub fn intro() {
@@ -140,3 +413,70 @@ pub fn apply_styles_to_backticks(text: &str) -> String {
 
     result_text
 }
+
+/// Splits an answer around its last markdown code block: the text before the block and the
+/// code in it, without the language of the block.
+pub fn split_code_block(answer: &str) -> Option<(String, String)> {
+    let answer = answer.trim();
+    let fences = answer
+        .match_indices("```")
+        .map(|(i, _)| i)
+        .collect::<Vec<usize>>();
+    let (open, close) = match fences.len() {
+        n if n >= 2 => (fences[n - 2], fences[n - 1]),
+        _ => return None,
+    };
+    let block = &answer[open + 3..close];
+    // Skip the language of the block, e.g. ```sh.
+    let code = match block.split_once('\n') {
+        Some((_, code)) => code,
+        None => block,
+    };
+    Some((answer[..open].trim().to_string(), code.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    fn profile(toml: &str) -> LLMConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn wizard_profiles_read_claude_api_key() {
+        let mut wizard = LLMConfig::new_anthropic();
+        wizard.api_key = None;
+        let written = profile(&toml::to_string(&wizard).unwrap());
+        let key = anthropic_key(&written, env(&[("CLAUDE_API_KEY", "sk-claude")]));
+        assert_eq!(key.as_deref(), Some("sk-claude"));
+        let key = anthropic_key(&written, env(&[("ANTHROPIC_API_KEY", "sk-anthropic")]));
+        assert_eq!(key.as_deref(), Some("sk-anthropic"));
+        assert_eq!(
+            anthropic_key(&written, env(&[("CLAUDE_API_KEY", "")])),
+            None
+        );
+    }
+
+    #[test]
+    fn old_wizard_placeholders_are_not_keys() {
+        let old = profile("llm_type = \"anthropic\"\napi_key = \"ollama\"");
+        let key = anthropic_key(&old, env(&[("CLAUDE_API_KEY", "sk-claude")]));
+        assert_eq!(key.as_deref(), Some("sk-claude"));
+    }
+
+    #[test]
+    fn anthropic_key_prefers_the_profile() {
+        let own = profile("llm_type = \"anthropic\"\napi_key = \"sk-profile\"");
+        let key = anthropic_key(&own, env(&[("CLAUDE_API_KEY", "sk-claude")]));
+        assert_eq!(key.as_deref(), Some("sk-profile"));
+    }
+}
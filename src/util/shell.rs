@@ -0,0 +1,1347 @@
+use std::collections::HashMap;
+
+use dialoguer::console::style;
+use regex::Regex;
+use serde::Serialize;
+
+use super::shared::apply_styles_to_backticks;
+
+/// Operators in the order they are matched, longest first.
+const OPERATORS: [&str; 22] = [
+    "<<<", "<<-", "&>>", "&&", "||", ";;", "<<", ">>", ">&", "<&", "&>", ">|", "<>", "|&", "|",
+    "&", ";", "<", ">", "(", ")", "\n",
+];
+
+/// Operators separating the pipelines of a list.
+const SEPARATORS: [&str; 4] = ["&&", "||", ";", "&"];
+
+/// A word of a command as it is written, with the commands substituted in it.
+#[derive(Debug, Clone)]
+pub struct Word {
+    pub text: String,
+    pub substitutions: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+}
+
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    /// The operator with its file descriptor, e.g. `2>`.
+    pub operator: String,
+    pub target: Word,
+    /// The body of a here-document.
+    pub heredoc: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum NodeKind {
+    Command {
+        assignments: Vec<Assignment>,
+        words: Vec<Word>,
+    },
+    Pipeline {
+        negated: bool,
+        commands: Vec<Node>,
+    },
+    /// Pipelines each followed by the operator after it, if any.
+    List {
+        items: Vec<(Node, Option<String>)>,
+    },
+    Subshell {
+        body: Box<Node>,
+    },
+    Group {
+        body: Box<Node>,
+    },
+    For {
+        variable: String,
+        items: Vec<Word>,
+        body: Box<Node>,
+    },
+    Loop {
+        until: bool,
+        condition: Box<Node>,
+        body: Box<Node>,
+    },
+    If {
+        branches: Vec<(Node, Node)>,
+        otherwise: Option<Box<Node>>,
+    },
+    Case {
+        subject: Word,
+        arms: Vec<(Vec<Word>, Node)>,
+    },
+    Function {
+        name: String,
+        body: Box<Node>,
+    },
+}
+
+/// A node of the syntax tree of a shell command, with the text it was parsed from.
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub text: String,
+    pub kind: NodeKind,
+    /// The redirects of a command, simple or compound.
+    pub redirects: Vec<Redirect>,
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Word(Word),
+    Operator(String),
+    End,
+}
+
+/// Parses a POSIX shell command with the bash additions models use most.
+pub fn parse(input: &str) -> Result<Node, String> {
+    Parser::new(input).parse()
+}
+
+/// Parses the commands of a suggestion, leaving out its code fences. When the whole does
+/// not parse, its lines are parsed one by one, and those that still do not parse are
+/// returned as they are.
+pub fn parse_suggestion(input: &str) -> (Vec<Node>, Vec<String>) {
+    let script = input
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .collect::<Vec<&str>>()
+        .join("\n");
+    if let Ok(node) = parse(&script) {
+        return (vec![node], Vec::new());
+    }
+    let mut nodes = Vec::new();
+    let mut unparsed = Vec::new();
+    for line in script.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse(line) {
+            Ok(node) => nodes.push(node),
+            Err(_) => unparsed.push(line.to_string()),
+        }
+    }
+    (nodes, unparsed)
+}
+
+/// A word without its quotes and escapes, as the program receives it.
+pub fn unquote(text: &str) -> String {
+    let mut unquoted = String::new();
+    let mut quote: Option<char> = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => unquoted.push(c),
+            (_, '\\') => unquoted.extend(chars.next()),
+            (None, '\'' | '"') => quote = Some(c),
+            (_, c) => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+/// Whether `input` stops in the middle of a command, so that the lines after it belong to
+/// the same command: inside quotes, a compound command or a here-document, or after `|`,
+/// `&&`, `||` or a `\`.
+pub fn is_incomplete(input: &str) -> bool {
+    if input.trim_end_matches('\n').ends_with('\\') {
+        return true;
+    }
+    let mut parser = Parser::new(input);
+    match parser.parse() {
+        Ok(_) => parser.open_heredoc,
+        Err(error) => {
+            error.starts_with("Unterminated")
+                || error.starts_with("Expected a command after")
+                || error.ends_with(&describe(&Token::End))
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// The next token with where it starts and ends.
+    peeked: Option<(Token, usize, usize)>,
+    /// Where the last consumed token ends.
+    last_end: usize,
+    /// Whether a here-document has no closing delimiter.
+    open_heredoc: bool,
+}
+
+impl Parser {
+    fn new(input: &str) -> Self {
+        Parser {
+            chars: input.chars().collect(),
+            pos: 0,
+            peeked: None,
+            last_end: 0,
+            open_heredoc: false,
+        }
+    }
+
+    fn parse(&mut self) -> Result<Node, String> {
+        let node = self.list(&[])?;
+        match self.next()? {
+            Token::End => (),
+            token => return Err(format!("Unexpected {}", describe(&token))),
+        }
+        match &node.kind {
+            NodeKind::List { items } if items.is_empty() => Err("There is no command".to_string()),
+            _ => Ok(node),
+        }
+    }
+
+    fn peek(&mut self) -> Result<&Token, String> {
+        if self.peeked.is_none() {
+            let token = self.lex()?;
+            self.peeked = Some(token);
+        }
+        //safe to unwrap, set above
+        Ok(&self.peeked.as_ref().unwrap().0)
+    }
+
+    fn start(&mut self) -> Result<usize, String> {
+        self.peek()?;
+        //safe to unwrap, peek sets it
+        Ok(self.peeked.as_ref().unwrap().1)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        self.peek()?;
+        //safe to unwrap, peek sets it
+        let (token, _, end) = self.peeked.take().unwrap();
+        self.last_end = end;
+        Ok(token)
+    }
+
+    fn text_from(&self, start: usize) -> String {
+        self.chars[start..self.last_end.max(start)]
+            .iter()
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+
+    fn at_keyword(&mut self, keywords: &[&str]) -> Result<bool, String> {
+        Ok(matches!(self.peek()?, Token::Word(w) if keywords.contains(&w.text.as_str())))
+    }
+
+    fn at_operator(&mut self, operators: &[&str]) -> Result<bool, String> {
+        Ok(matches!(self.peek()?, Token::Operator(o) if operators.contains(&o.as_str())))
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        match self.next()? {
+            Token::Word(w) if w.text == keyword => Ok(()),
+            token => Err(format!(
+                "Expected `{}`, found {}",
+                keyword,
+                describe(&token)
+            )),
+        }
+    }
+
+    fn expect_operator(&mut self, operator: &str) -> Result<(), String> {
+        match self.next()? {
+            Token::Operator(o) if o == operator => Ok(()),
+            token => Err(format!(
+                "Expected `{}`, found {}",
+                operator,
+                describe(&token)
+            )),
+        }
+    }
+
+    fn skip_newlines(&mut self) -> Result<(), String> {
+        while self.at_operator(&["\n"])? {
+            self.next()?;
+        }
+        Ok(())
+    }
+
+    /// Pipelines separated by `;`, `&`, `&&`, `||` or newlines, up to one of `keywords`,
+    /// a `)`, a `;;` or the end.
+    fn list(&mut self, keywords: &[&str]) -> Result<Node, String> {
+        self.skip_newlines()?;
+        let start = self.start()?;
+        let mut items: Vec<(Node, Option<String>)> = Vec::new();
+        loop {
+            self.skip_newlines()?;
+            if matches!(self.peek()?, Token::End)
+                || self.at_operator(&[")", ";;"])?
+                || self.at_keyword(keywords)?
+            {
+                break;
+            }
+            let pipeline = self.pipeline()?;
+            let operator = match self.peek()? {
+                Token::Operator(o) if SEPARATORS.contains(&o.as_str()) => Some(o.clone()),
+                _ => None,
+            };
+            let last = operator.is_none() && !self.at_operator(&["\n"])?;
+            if operator.is_some() {
+                self.next()?;
+            }
+            items.push((pipeline, operator));
+            if last {
+                break;
+            }
+        }
+        match items.last_mut() {
+            Some((_, Some(operator))) if operator == "&&" || operator == "||" => {
+                return Err(format!("Expected a command after `{}`", operator))
+            }
+            // A trailing `;` changes nothing.
+            Some((_, operator)) if operator.as_deref() == Some(";") => *operator = None,
+            _ => (),
+        }
+        let text = self.text_from(start);
+        match items.len() {
+            1 if items[0].1.is_none() => Ok(items.remove(0).0),
+            _ => Ok(Node {
+                text,
+                kind: NodeKind::List { items },
+                redirects: Vec::new(),
+            }),
+        }
+    }
+
+    fn pipeline(&mut self) -> Result<Node, String> {
+        let start = self.start()?;
+        let negated = self.at_keyword(&["!"])?;
+        if negated {
+            self.next()?;
+        }
+        let mut commands = vec![self.command()?];
+        while self.at_operator(&["|", "|&"])? {
+            self.next()?;
+            self.skip_newlines()?;
+            commands.push(self.command()?);
+        }
+        if commands.len() == 1 && !negated {
+            return Ok(commands.remove(0));
+        }
+        Ok(Node {
+            text: self.text_from(start),
+            kind: NodeKind::Pipeline { negated, commands },
+            redirects: Vec::new(),
+        })
+    }
+
+    fn command(&mut self) -> Result<Node, String> {
+        let start = self.start()?;
+        let keyword = match self.peek()? {
+            Token::Operator(o) if o == "(" => "(".to_string(),
+            Token::Word(w) => w.text.clone(),
+            _ => String::new(),
+        };
+        let kind = match keyword.as_str() {
+            "(" => {
+                self.next()?;
+                let body = Box::new(self.list(&[])?);
+                self.expect_operator(")")?;
+                NodeKind::Subshell { body }
+            }
+            "{" => {
+                self.next()?;
+                let body = Box::new(self.list(&["}"])?);
+                self.expect_keyword("}")?;
+                NodeKind::Group { body }
+            }
+            "if" => self.if_clause()?,
+            "for" => self.for_clause()?,
+            "while" | "until" => {
+                self.next()?;
+                let condition = Box::new(self.list(&["do"])?);
+                self.expect_keyword("do")?;
+                let body = Box::new(self.list(&["done"])?);
+                self.expect_keyword("done")?;
+                NodeKind::Loop {
+                    until: keyword == "until",
+                    condition,
+                    body,
+                }
+            }
+            "case" => self.case_clause()?,
+            "function" => {
+                self.next()?;
+                let name = match self.next()? {
+                    Token::Word(w) => w.text,
+                    token => {
+                        return Err(format!(
+                            "Expected a function name, found {}",
+                            describe(&token)
+                        ))
+                    }
+                };
+                if self.at_operator(&["("])? {
+                    self.next()?;
+                    self.expect_operator(")")?;
+                }
+                self.skip_newlines()?;
+                NodeKind::Function {
+                    name,
+                    body: Box::new(self.command()?),
+                }
+            }
+            _ => return self.simple_command(),
+        };
+        let redirects = self.redirects()?;
+        Ok(Node {
+            text: self.text_from(start),
+            kind,
+            redirects,
+        })
+    }
+
+    fn if_clause(&mut self) -> Result<NodeKind, String> {
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+        self.next()?;
+        loop {
+            let condition = self.list(&["then"])?;
+            self.expect_keyword("then")?;
+            let body = self.list(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+            if !self.at_keyword(&["elif"])? {
+                break;
+            }
+            self.next()?;
+        }
+        if self.at_keyword(&["else"])? {
+            self.next()?;
+            otherwise = Some(Box::new(self.list(&["fi"])?));
+        }
+        self.expect_keyword("fi")?;
+        Ok(NodeKind::If {
+            branches,
+            otherwise,
+        })
+    }
+
+    fn for_clause(&mut self) -> Result<NodeKind, String> {
+        self.next()?;
+        let variable = match self.next()? {
+            Token::Word(w) => w.text,
+            token => {
+                return Err(format!(
+                    "Expected a variable name, found {}",
+                    describe(&token)
+                ))
+            }
+        };
+        self.skip_newlines()?;
+        let mut items = Vec::new();
+        if self.at_keyword(&["in"])? {
+            self.next()?;
+            while let Token::Word(_) = self.peek()? {
+                if let Token::Word(w) = self.next()? {
+                    items.push(w);
+                }
+            }
+        }
+        if self.at_operator(&[";"])? {
+            self.next()?;
+        }
+        self.skip_newlines()?;
+        self.expect_keyword("do")?;
+        let body = Box::new(self.list(&["done"])?);
+        self.expect_keyword("done")?;
+        Ok(NodeKind::For {
+            variable,
+            items,
+            body,
+        })
+    }
+
+    fn case_clause(&mut self) -> Result<NodeKind, String> {
+        self.next()?;
+        let subject = match self.next()? {
+            Token::Word(w) => w,
+            token => {
+                return Err(format!(
+                    "Expected a word after `case`, found {}",
+                    describe(&token)
+                ))
+            }
+        };
+        self.skip_newlines()?;
+        self.expect_keyword("in")?;
+        let mut arms = Vec::new();
+        loop {
+            self.skip_newlines()?;
+            if self.at_keyword(&["esac"])? {
+                break;
+            }
+            if self.at_operator(&["("])? {
+                self.next()?;
+            }
+            let mut patterns = Vec::new();
+            loop {
+                match self.next()? {
+                    Token::Word(w) => patterns.push(w),
+                    token => return Err(format!("Expected a pattern, found {}", describe(&token))),
+                }
+                if !self.at_operator(&["|"])? {
+                    break;
+                }
+                self.next()?;
+            }
+            self.expect_operator(")")?;
+            let body = self.list(&["esac"])?;
+            if self.at_operator(&[";;"])? {
+                self.next()?;
+            }
+            arms.push((patterns, body));
+        }
+        self.expect_keyword("esac")?;
+        Ok(NodeKind::Case { subject, arms })
+    }
+
+    fn simple_command(&mut self) -> Result<Node, String> {
+        let start = self.start()?;
+        let mut assignments = Vec::new();
+        let mut words: Vec<Word> = Vec::new();
+        let mut redirects = Vec::new();
+        loop {
+            match self.peek()? {
+                Token::Word(_) => {
+                    let Token::Word(word) = self.next()? else {
+                        unreachable!()
+                    };
+                    match assignment(&word) {
+                        Some(a) if words.is_empty() => assignments.push(a),
+                        _ => words.push(word),
+                    }
+                }
+                Token::Operator(o) if is_redirect(o) => redirects.push(self.redirect()?),
+                Token::Operator(o) if o == "(" && words.len() == 1 && assignments.is_empty() => {
+                    self.next()?;
+                    self.expect_operator(")")?;
+                    self.skip_newlines()?;
+                    let body = Box::new(self.command()?);
+                    return Ok(Node {
+                        text: self.text_from(start),
+                        kind: NodeKind::Function {
+                            name: words.remove(0).text,
+                            body,
+                        },
+                        redirects: Vec::new(),
+                    });
+                }
+                _ => break,
+            }
+        }
+        if assignments.is_empty() && words.is_empty() && redirects.is_empty() {
+            let token = self.next()?;
+            return Err(format!("Expected a command, found {}", describe(&token)));
+        }
+        Ok(Node {
+            text: self.text_from(start),
+            kind: NodeKind::Command { assignments, words },
+            redirects,
+        })
+    }
+
+    fn redirects(&mut self) -> Result<Vec<Redirect>, String> {
+        let mut redirects = Vec::new();
+        while matches!(self.peek()?, Token::Operator(o) if is_redirect(o)) {
+            redirects.push(self.redirect()?);
+        }
+        Ok(redirects)
+    }
+
+    fn redirect(&mut self) -> Result<Redirect, String> {
+        let Token::Operator(operator) = self.next()? else {
+            unreachable!()
+        };
+        let target = match self.next()? {
+            Token::Word(w) => w,
+            token => {
+                return Err(format!(
+                    "Expected a target after `{}`, found {}",
+                    operator,
+                    describe(&token)
+                ))
+            }
+        };
+        let bare = operator.trim_start_matches(|c: char| c.is_ascii_digit());
+        let heredoc = match bare {
+            "<<" | "<<-" => Some(self.heredoc(&target.text, bare == "<<-")),
+            _ => None,
+        };
+        Ok(Redirect {
+            operator,
+            target,
+            heredoc,
+        })
+    }
+
+    /// Takes the body of a here-document out of the lines following the current one.
+    fn heredoc(&mut self, delimiter: &str, strip_tabs: bool) -> String {
+        let delimiter = delimiter.replace(['\'', '"', '\\'], "");
+        let Some(newline) = self.chars[self.pos..].iter().position(|c| *c == '\n') else {
+            self.open_heredoc = true;
+            return String::new();
+        };
+        let body_start = self.pos + newline + 1;
+        let mut end = body_start;
+        let mut lines = Vec::new();
+        let mut closed = false;
+        while end < self.chars.len() {
+            let line_end = self.chars[end..]
+                .iter()
+                .position(|c| *c == '\n')
+                .map(|i| end + i)
+                .unwrap_or(self.chars.len());
+            let line = self.chars[end..line_end].iter().collect::<String>();
+            let line = match strip_tabs {
+                true => line.trim_start_matches('\t').to_string(),
+                false => line,
+            };
+            end = (line_end + 1).min(self.chars.len());
+            if line == delimiter {
+                closed = true;
+                break;
+            }
+            lines.push(line);
+        }
+        self.open_heredoc |= !closed;
+        self.chars.drain(body_start..end);
+        lines.join("\n")
+    }
+
+    fn lex(&mut self) -> Result<(Token, usize, usize), String> {
+        loop {
+            match (self.current(), self.chars.get(self.pos + 1)) {
+                (Some(' ' | '\t'), _) => self.pos += 1,
+                (Some('\\'), Some('\n')) => self.pos += 2,
+                (Some('#'), _) => {
+                    while self.current().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+        let start = self.pos;
+        if self.current().is_none() {
+            return Ok((Token::End, start, start));
+        }
+        // A file descriptor right before a redirect, like `2>`.
+        let digits = self.chars[start..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if matches!(self.chars.get(start + digits), Some('<' | '>')) {
+            self.pos += digits;
+            let operator = self.operator().unwrap_or_default();
+            let text = format!(
+                "{}{}",
+                self.chars[start..start + digits].iter().collect::<String>(),
+                operator
+            );
+            return Ok((Token::Operator(text), start, self.pos));
+        }
+        if let Some(operator) = self.operator() {
+            return Ok((Token::Operator(operator), start, self.pos));
+        }
+        let word = self.word()?;
+        Ok((Token::Word(word), start, self.pos))
+    }
+
+    fn current(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn operator(&mut self) -> Option<String> {
+        let operator = OPERATORS.iter().find(|o| {
+            o.chars()
+                .enumerate()
+                .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+        })?;
+        self.pos += operator.chars().count();
+        Some(operator.to_string())
+    }
+
+    fn word(&mut self) -> Result<Word, String> {
+        let start = self.pos;
+        let mut substitutions = Vec::new();
+        while let Some(c) = self.current() {
+            match c {
+                c if c.is_whitespace() || "|&;<>()".contains(c) => break,
+                '\\' => self.pos = (self.pos + 2).min(self.chars.len()),
+                '\'' => {
+                    let close = self.chars[self.pos + 1..]
+                        .iter()
+                        .position(|c| *c == '\'')
+                        .ok_or("Unterminated single quote")?;
+                    self.pos += close + 2;
+                }
+                '"' => self.double_quoted(&mut substitutions)?,
+                '$' | '`' => self.expansion(&mut substitutions)?,
+                _ => self.pos += 1,
+            }
+        }
+        Ok(Word {
+            text: self.chars[start..self.pos].iter().collect(),
+            substitutions,
+        })
+    }
+
+    fn double_quoted(&mut self, substitutions: &mut Vec<Node>) -> Result<(), String> {
+        self.pos += 1;
+        loop {
+            match self.current() {
+                None => return Err("Unterminated double quote".to_string()),
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                Some('\\') => self.pos = (self.pos + 2).min(self.chars.len()),
+                Some('$' | '`') => self.expansion(substitutions)?,
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+
+    /// Skips a `$` expansion or a backtick substitution, parsing the commands it runs.
+    fn expansion(&mut self, substitutions: &mut Vec<Node>) -> Result<(), String> {
+        let next = |i: usize| self.chars.get(self.pos + i).copied();
+        match (next(0), next(1), next(2)) {
+            (Some('`'), _, _) => {
+                let start = self.pos + 1;
+                let mut end = start;
+                while end < self.chars.len() && self.chars[end] != '`' {
+                    end += if self.chars[end] == '\\' { 2 } else { 1 };
+                }
+                if end >= self.chars.len() {
+                    return Err("Unterminated backtick".to_string());
+                }
+                let inner = self.chars[start..end].iter().collect::<String>();
+                substitutions.push(parse(&inner)?);
+                self.pos = end + 1;
+            }
+            (Some('$'), Some('('), Some('(')) => self.skip_balanced('(', ')', 2)?,
+            (Some('$'), Some('('), _) => {
+                self.pos += 2;
+                let outer = self.last_end;
+                let node = self.list(&[])?;
+                self.expect_operator(")")?;
+                self.last_end = outer;
+                if !matches!(&node.kind, NodeKind::List { items } if items.is_empty()) {
+                    substitutions.push(node);
+                }
+            }
+            (Some('$'), Some('{'), _) => self.skip_balanced('{', '}', 1)?,
+            _ => self.pos += 1,
+        }
+        Ok(())
+    }
+
+    /// Skips an expansion up to the `close` matching its `open`, like `${...}`.
+    fn skip_balanced(&mut self, open: char, close: char, skip: usize) -> Result<(), String> {
+        self.pos += skip + 1;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.current() {
+                None => return Err(format!("Unterminated `{}`", open)),
+                Some(c) if c == open => depth += 1,
+                Some(c) if c == close => depth -= 1,
+                Some('\\') => self.pos += 1,
+                _ => (),
+            }
+            self.pos += 1;
+        }
+        if open == '(' {
+            // Arithmetic ends with `))`.
+            match self.current() {
+                Some(')') => self.pos += 1,
+                _ => return Err("Unterminated `$((`".to_string()),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(w) => format!("`{}`", w.text),
+        Token::Operator(o) if o == "\n" => "a new line".to_string(),
+        Token::Operator(o) => format!("`{}`", o),
+        Token::End => "the end of the command".to_string(),
+    }
+}
+
+fn is_redirect(operator: &str) -> bool {
+    operator.contains(['<', '>'])
+}
+
+fn assignment(word: &Word) -> Option<Assignment> {
+    let re = Regex::new(r"^([A-Za-z_][A-Za-z0-9_]*)\+?=").unwrap(); //safe to unwrap
+    let name = re.captures(&word.text)?.get(1)?.as_str().to_string();
+    let value = word.text[re.find(&word.text)?.end()..].to_string();
+    Some(Assignment {
+        name,
+        value: Word {
+            text: value,
+            substitutions: word.substitutions.clone(),
+        },
+    })
+}
+
+/// A node of the tree an explanation follows: a command, one of its words or redirects,
+/// or a construct grouping commands.
+#[derive(Debug, Clone, Serialize)]
+pub struct Part {
+    pub kind: &'static str,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Part>,
+}
+
+impl Part {
+    fn new(kind: &'static str, text: &str, children: Vec<Part>) -> Self {
+        Part {
+            kind,
+            text: text.to_string(),
+            explanation: None,
+            children,
+        }
+    }
+
+    /// Every part numbered depth first and indented by depth, for the prompts.
+    pub fn outline(&self) -> String {
+        let mut lines = Vec::new();
+        self.walk(0, &mut 0, &mut |part, depth, number| {
+            lines.push(format!(
+                "{}{}. {} `{}`",
+                "  ".repeat(depth),
+                number,
+                part.kind,
+                first_line(&part.text)
+            ));
+        });
+        lines.join("\n")
+    }
+
+    /// Attaches the explanations of an answer made of `<number>: <explanation>` lines and
+    /// returns how many it had.
+    pub fn annotate(&mut self, answer: &str) -> usize {
+        let re = Regex::new(r"(?m)^\s*(\d+)\s*[.:)]\s*(.+?)\s*$").unwrap(); //safe to unwrap
+        let explanations = re
+            .captures_iter(answer)
+            .filter_map(|cap| Some((cap[1].parse::<usize>().ok()?, cap[2].to_string())))
+            .collect::<HashMap<usize, String>>();
+        self.walk_mut(&mut 0, &mut |part, number| {
+            part.explanation = explanations.get(&number).cloned();
+        });
+        explanations.len()
+    }
+
+    /// The tree drawn with box characters, styled for the terminal or not.
+    pub fn render(&self, styled: bool) -> String {
+        let mut lines = Vec::new();
+        self.render_into("", None, styled, &mut lines);
+        lines.join("\n")
+    }
+
+    fn render_into(&self, prefix: &str, last: Option<bool>, styled: bool, lines: &mut Vec<String>) {
+        let text = first_line(&self.text);
+        let label = match (styled, &self.explanation) {
+            (true, Some(e)) => format!(
+                "{} {}",
+                style(text).yellow().bold(),
+                apply_styles_to_backticks(e)
+            ),
+            (true, None) => style(text).yellow().bold().to_string(),
+            (false, Some(e)) => format!("{}: {}", text, e),
+            (false, None) => text,
+        };
+        let branch = match last {
+            None => "",
+            Some(true) => "└─ ",
+            Some(false) => "├─ ",
+        };
+        lines.push(format!("{}{}{}", prefix, branch, label));
+        let prefix = match last {
+            None => String::new(),
+            Some(true) => format!("{}   ", prefix),
+            Some(false) => format!("{}│  ", prefix),
+        };
+        for (i, child) in self.children.iter().enumerate() {
+            child.render_into(&prefix, Some(i + 1 == self.children.len()), styled, lines);
+        }
+    }
+
+    /// Puts the operators of a list between its pipelines.
+    fn with_items(mut self, items: &[(Node, Option<String>)]) -> Self {
+        self.children = items
+            .iter()
+            .flat_map(|(node, operator)| {
+                let mut parts = vec![node.parts()];
+                parts.extend(
+                    operator
+                        .iter()
+                        .map(|o| Part::new("operator", o, Vec::new())),
+                );
+                parts
+            })
+            .collect();
+        self
+    }
+
+    fn walk(&self, depth: usize, number: &mut usize, f: &mut impl FnMut(&Part, usize, usize)) {
+        *number += 1;
+        f(self, depth, *number);
+        for child in &self.children {
+            child.walk(depth + 1, number, f);
+        }
+    }
+
+    fn walk_mut(&mut self, number: &mut usize, f: &mut impl FnMut(&mut Part, usize)) {
+        *number += 1;
+        f(self, *number);
+        for child in &mut self.children {
+            child.walk_mut(number, f);
+        }
+    }
+}
+
+impl Node {
+    /// Every node of the tree, parents before their children, with the commands of the
+    /// substitutions in their words.
+    pub fn nodes(&self) -> Vec<&Node> {
+        let mut nodes = vec![self];
+        for node in self.substitutions().into_iter().chain(self.children()) {
+            nodes.extend(node.nodes());
+        }
+        nodes
+    }
+
+    /// Every pipeline of the tree and every command outside of one, whole so that
+    /// `curl ... | sh` is seen as one command. Compound commands are broken down into their
+    /// commands, and kept whole too when they have redirects.
+    pub fn pipelines(&self) -> Vec<&Node> {
+        let mut pipelines = Vec::new();
+        self.collect_pipelines(false, &mut pipelines);
+        pipelines
+    }
+
+    fn collect_pipelines<'a>(&'a self, piped: bool, pipelines: &mut Vec<&'a Node>) {
+        let whole = match self.kind {
+            NodeKind::Command { .. } | NodeKind::Pipeline { .. } => true,
+            NodeKind::List { .. } => false,
+            _ => !self.redirects.is_empty(),
+        };
+        if whole && !piped {
+            pipelines.push(self);
+        }
+        for node in self.substitutions() {
+            node.collect_pipelines(false, pipelines);
+        }
+        let piped = piped || matches!(self.kind, NodeKind::Pipeline { .. });
+        for node in self.children() {
+            node.collect_pipelines(piped, pipelines);
+        }
+    }
+
+    /// The commands and constructs this node is made of.
+    fn children(&self) -> Vec<&Node> {
+        match &self.kind {
+            NodeKind::Command { .. } => Vec::new(),
+            NodeKind::Pipeline { commands, .. } => commands.iter().collect(),
+            NodeKind::List { items } => items.iter().map(|(node, _)| node).collect(),
+            NodeKind::Subshell { body }
+            | NodeKind::Group { body }
+            | NodeKind::For { body, .. }
+            | NodeKind::Function { body, .. } => vec![body],
+            NodeKind::Loop {
+                condition, body, ..
+            } => vec![condition, body],
+            NodeKind::If {
+                branches,
+                otherwise,
+            } => branches
+                .iter()
+                .flat_map(|(condition, body)| [condition, body])
+                .chain(otherwise.as_deref())
+                .collect(),
+            NodeKind::Case { arms, .. } => arms.iter().map(|(_, body)| body).collect(),
+        }
+    }
+
+    /// The commands substituted in the words of this node, not in those of its children.
+    fn substitutions(&self) -> Vec<&Node> {
+        let words: Vec<&Word> = match &self.kind {
+            NodeKind::Command { assignments, words } => assignments
+                .iter()
+                .map(|a| &a.value)
+                .chain(words.iter())
+                .collect(),
+            NodeKind::For { items, .. } => items.iter().collect(),
+            NodeKind::Case { subject, arms } => std::iter::once(subject)
+                .chain(arms.iter().flat_map(|(patterns, _)| patterns))
+                .collect(),
+            _ => Vec::new(),
+        };
+        words
+            .into_iter()
+            .chain(self.redirects.iter().map(|r| &r.target))
+            .flat_map(|word| &word.substitutions)
+            .collect()
+    }
+
+    /// The tree of parts to explain, where commands are named after their program.
+    pub fn parts(&self) -> Part {
+        let mut part = match &self.kind {
+            NodeKind::Command { assignments, words } => {
+                let mut children = assignments
+                    .iter()
+                    .map(|a| {
+                        let text = format!("{}={}", a.name, a.value.text);
+                        Part::new("assignment", &text, substitution_parts(&a.value))
+                    })
+                    .collect::<Vec<Part>>();
+                let program = words.first();
+                if let Some(program) = program {
+                    children.extend(substitution_parts(program));
+                }
+                children.extend(words.iter().skip(1).map(|w| {
+                    let kind = match w.text.starts_with('-') && w.text.len() > 1 {
+                        true => "option",
+                        false => "argument",
+                    };
+                    Part::new(kind, &w.text, substitution_parts(w))
+                }));
+                let text = program.map(|p| p.text.as_str()).unwrap_or(&self.text);
+                Part::new("command", text, children)
+            }
+            NodeKind::Pipeline { negated, commands } => {
+                let mut children = Vec::new();
+                if *negated {
+                    children.push(Part::new("operator", "!", Vec::new()));
+                }
+                children.extend(commands.iter().map(Node::parts));
+                Part::new("pipeline", &self.text, children)
+            }
+            NodeKind::List { items } => Part::new("list", &self.text, Vec::new()).with_items(items),
+            NodeKind::Subshell { body } => Part::new("subshell", &self.text, body_parts(body)),
+            NodeKind::Group { body } => Part::new("group", &self.text, body_parts(body)),
+            NodeKind::For {
+                variable,
+                items,
+                body,
+            } => {
+                let words = items.iter().map(|w| w.text.as_str()).collect::<Vec<&str>>();
+                let text = format!("for {} in {}", variable, words.join(" "));
+                let mut children = items
+                    .iter()
+                    .flat_map(substitution_parts)
+                    .collect::<Vec<Part>>();
+                children.extend(body_parts(body));
+                Part::new("for", &text, children)
+            }
+            NodeKind::Loop {
+                until,
+                condition,
+                body,
+            } => {
+                let kind = if *until { "until" } else { "while" };
+                let text = format!("{} {}", kind, condition.text);
+                let mut children = vec![Part::new(
+                    "condition",
+                    &condition.text,
+                    body_parts(condition),
+                )];
+                children.extend(body_parts(body));
+                Part::new(kind, &text, children)
+            }
+            NodeKind::If {
+                branches,
+                otherwise,
+            } => {
+                let mut children = Vec::new();
+                for (condition, body) in branches {
+                    children.push(Part::new(
+                        "condition",
+                        &condition.text,
+                        body_parts(condition),
+                    ));
+                    children.push(Part::new("then", &body.text, body_parts(body)));
+                }
+                if let Some(otherwise) = otherwise {
+                    children.push(Part::new("else", &otherwise.text, body_parts(otherwise)));
+                }
+                let text = format!("if {}", branches[0].0.text);
+                Part::new("if", &text, children)
+            }
+            NodeKind::Case { subject, arms } => {
+                let children = arms
+                    .iter()
+                    .map(|(patterns, body)| {
+                        let patterns = patterns
+                            .iter()
+                            .map(|p| p.text.as_str())
+                            .collect::<Vec<&str>>();
+                        Part::new(
+                            "pattern",
+                            &format!("{})", patterns.join("|")),
+                            body_parts(body),
+                        )
+                    })
+                    .collect();
+                Part::new("case", &format!("case {} in", subject.text), children)
+            }
+            NodeKind::Function { name, body } => {
+                Part::new("function", &format!("{}()", name), body_parts(body))
+            }
+        };
+        part.children
+            .extend(self.redirects.iter().map(redirect_part));
+        part
+    }
+}
+
+/// The parts of the commands a construct runs, without a level for their list.
+fn body_parts(node: &Node) -> Vec<Part> {
+    match &node.kind {
+        NodeKind::List { items } => {
+            Part::new("list", &node.text, Vec::new())
+                .with_items(items)
+                .children
+        }
+        _ => vec![node.parts()],
+    }
+}
+
+fn substitution_parts(word: &Word) -> Vec<Part> {
+    word.substitutions
+        .iter()
+        .map(|node| {
+            Part::new(
+                "substitution",
+                &format!("$({})", node.text),
+                body_parts(node),
+            )
+        })
+        .collect()
+}
+
+fn redirect_part(redirect: &Redirect) -> Part {
+    let mut children = substitution_parts(&redirect.target);
+    if let Some(body) = &redirect.heredoc {
+        children.push(Part::new("heredoc", body, Vec::new()));
+    }
+    let text = format!("{}{}", redirect.operator, redirect.target.text);
+    Part::new("redirect", &text, children)
+}
+
+/// The first line of a text, marked as cut when there are more.
+fn first_line(text: &str) -> String {
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or_default().to_string();
+    match lines.next() {
+        Some(_) => format!("{} …", first),
+        None => first,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(node: &Node) -> Vec<&str> {
+        match &node.kind {
+            NodeKind::Command { words, .. } => words.iter().map(|w| w.text.as_str()).collect(),
+            kind => panic!("not a command: {:?}", kind),
+        }
+    }
+
+    fn list(node: &Node) -> Vec<(&str, Option<&str>)> {
+        match &node.kind {
+            NodeKind::List { items } => items
+                .iter()
+                .map(|(node, operator)| (node.text.as_str(), operator.as_deref()))
+                .collect(),
+            kind => panic!("not a list: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn quotes_keep_words_whole() {
+        let node = parse(r#"echo 'a b' "c $HOME d" e\ f "g\"h""#).unwrap();
+        assert_eq!(
+            words(&node),
+            vec!["echo", "'a b'", r#""c $HOME d""#, r"e\ f", r#""g\"h""#]
+        );
+        assert_eq!(words(&parse("echo 'a | b; c'").unwrap()).len(), 2);
+    }
+
+    #[test]
+    fn pipes_make_pipelines() {
+        let node = parse("ls -la | grep foo |& wc -l").unwrap();
+        match &node.kind {
+            NodeKind::Pipeline { negated, commands } => {
+                assert!(!negated);
+                let programs = commands.iter().map(|c| words(c)[0]).collect::<Vec<&str>>();
+                assert_eq!(programs, vec!["ls", "grep", "wc"]);
+            }
+            kind => panic!("not a pipeline: {:?}", kind),
+        }
+        assert!(matches!(
+            parse("! grep -q foo file").unwrap().kind,
+            NodeKind::Pipeline { negated: true, .. }
+        ));
+    }
+
+    #[test]
+    fn redirects_are_attached_to_their_command() {
+        let node = parse("make 2>&1 >> build.log < /dev/null").unwrap();
+        assert_eq!(words(&node), vec!["make"]);
+        let redirects = node
+            .redirects
+            .iter()
+            .map(|r| (r.operator.as_str(), r.target.text.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(
+            redirects,
+            vec![("2>&", "1"), (">>", "build.log"), ("<", "/dev/null")]
+        );
+    }
+
+    #[test]
+    fn heredocs_keep_their_body() {
+        let node = parse("cat <<'EOF' > out.txt\nhello | world\nEOF").unwrap();
+        assert_eq!(words(&node), vec!["cat"]);
+        assert_eq!(node.redirects.len(), 2);
+        assert_eq!(node.redirects[0].heredoc.as_deref(), Some("hello | world"));
+    }
+
+    #[test]
+    fn lists_keep_their_operators() {
+        let node = parse("make && make test || echo failed; echo done").unwrap();
+        assert_eq!(
+            list(&node),
+            vec![
+                ("make", Some("&&")),
+                ("make test", Some("||")),
+                ("echo failed", Some(";")),
+                ("echo done", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn subshells_and_groups_nest() {
+        let node = parse("(cd src && ls) > files.txt").unwrap();
+        match &node.kind {
+            NodeKind::Subshell { body } => assert_eq!(list(body).len(), 2),
+            kind => panic!("not a subshell: {:?}", kind),
+        }
+        assert_eq!(node.redirects.len(), 1);
+        assert!(matches!(
+            parse("{ echo a; echo b; }").unwrap().kind,
+            NodeKind::Group { .. }
+        ));
+        let node = parse("echo $(git rev-parse `pwd`)").unwrap();
+        match &node.kind {
+            NodeKind::Command { words, .. } => assert_eq!(words[1].substitutions.len(), 1),
+            kind => panic!("not a command: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn compound_commands_parse() {
+        for input in [
+            "for f in *.txt; do wc -l \"$f\"; done",
+            "while read line; do echo \"$line\"; done < input",
+            "if [ -f a ]; then cat a; elif [ -f b ]; then cat b; else echo none; fi",
+            "case \"$1\" in start|run) up ;; *) down ;; esac",
+            "greet() { echo hi; }",
+        ] {
+            assert!(parse(input).is_ok(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        for input in [
+            "",
+            "echo 'x",
+            "echo \"x",
+            "| ls",
+            "ls |",
+            "&&",
+            "ls &&",
+            ")",
+            "(ls",
+            "$(",
+            "echo ${",
+            "echo `ls",
+            "if true; then",
+            "for in",
+            "case x in",
+            "{ ls",
+            "f() {",
+            ";;",
+        ] {
+            assert!(parse(input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn cut_commands_do_not_panic() {
+        for input in [
+            "for f in $(ls *.txt); do { echo \"${f%.txt}\" | tr a-z A-Z; } >> \"$out\" 2>&1; done",
+            "case \"$(uname)\" in Linux) cat <<-'EOF'\n\tlinux\n\tEOF\n;; *) (echo `uname -a`) ;; esac",
+            "if ! grep -q 'a\\b' <<< \"é $x\"; then f() { :; }; fi &",
+        ] {
+            for (end, _) in input.char_indices() {
+                let _ = parse(&input[..end]);
+                let _ = is_incomplete(&input[..end]);
+            }
+            assert!(parse(input).is_ok(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn unfinished_commands_are_incomplete() {
+        for input in [
+            "echo 'x",
+            "ls |",
+            "make &&",
+            "if true; then",
+            "cat <<EOF\nhello",
+            "ls \\",
+        ] {
+            assert!(is_incomplete(input), "{:?}", input);
+        }
+        for input in ["ls", "echo 'x'", ")", "ls | | wc", "cat <<EOF\nhello\nEOF"] {
+            assert!(!is_incomplete(input), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn parts_are_numbered_for_the_answer() {
+        let mut tree = parse("ls -la | wc -l").unwrap().parts();
+        assert_eq!(
+            tree.outline(),
+            "1. pipeline `ls -la | wc -l`\n  2. command `ls`\n    3. option `-la`\n  4. command `wc`\n    5. option `-l`"
+        );
+        assert_eq!(tree.annotate("1: Counts.\n3: Long.\nnoise"), 2);
+        assert_eq!(tree.explanation.as_deref(), Some("Counts."));
+        assert_eq!(
+            tree.children[0].children[0].explanation.as_deref(),
+            Some("Long.")
+        );
+    }
+}
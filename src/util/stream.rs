@@ -0,0 +1,175 @@
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use dialoguer::console::{style, Term};
+use futures::StreamExt;
+use langchain_rust::{
+    chain::{Chain, ChainError},
+    prompt::PromptArgs,
+};
+use tokio::task::JoinHandle;
+
+use super::{output::Answer, shared::SharedState};
+
+const SPINNER_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// A spinner drawn on stderr until it is stopped.
+pub struct Spinner {
+    running: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl Spinner {
+    pub fn start(message: &str) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let flag = running.clone();
+        let message = message.to_string();
+        let handle = tokio::spawn(async move {
+            let term = Term::stderr();
+            let mut frame = 0;
+            while flag.load(Ordering::Relaxed) {
+                let _ = term.write_str(&format!(
+                    "\r{} {}",
+                    style(SPINNER_FRAMES[frame % SPINNER_FRAMES.len()]).cyan(),
+                    style(&message).dim()
+                ));
+                frame += 1;
+                tokio::time::sleep(Duration::from_millis(80)).await;
+            }
+            let _ = term.clear_line();
+        });
+        Spinner { running, handle }
+    }
+
+    pub async fn stop(self) {
+        self.running.store(false, Ordering::Relaxed);
+        let _ = self.handle.await;
+    }
+}
+
+/// Styles streamed chunks the same way `apply_styles_to_backticks` styles a whole answer,
+/// holding back the text of a backtick span until it is closed.
+#[derive(Default)]
+pub struct BacktickHighlighter {
+    in_code: bool,
+    code: String,
+}
+
+impl BacktickHighlighter {
+    pub fn push(&mut self, chunk: &str) -> String {
+        let mut rendered = String::new();
+        for c in chunk.chars() {
+            match (c, self.in_code) {
+                ('`', true) => {
+                    rendered.push_str(&style(&self.code).yellow().bold().to_string());
+                    self.code.clear();
+                    self.in_code = false;
+                }
+                ('`', false) => self.in_code = true,
+                (c, true) => self.code.push(c),
+                (c, false) => rendered.push(c),
+            }
+        }
+        rendered
+    }
+
+    pub fn finish(&mut self) -> String {
+        if !self.in_code {
+            return String::new();
+        }
+        self.in_code = false;
+        format!("`{}", std::mem::take(&mut self.code))
+    }
+}
+
+/// Runs the chain, streaming its answer to the terminal token by token when streaming is
+/// enabled. Returns the full answer either way.
+pub async fn run_chain<C: Chain>(
+    shared_state: &SharedState,
+    chain: &C,
+    input_variables: PromptArgs,
+    answer: Answer,
+) -> Result<String, ChainError> {
+    if !shared_state.streaming() {
+        return chain.invoke(input_variables).await;
+    }
+
+    let spinner = Spinner::start("Thinking...");
+    let mut stream = match chain.stream(input_variables).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            spinner.stop().await;
+            return Err(e);
+        }
+    };
+
+    let mut spinner = Some(spinner);
+    let mut highlighter = BacktickHighlighter::default();
+    let mut content = String::new();
+    let mut stdout = io::stdout();
+    while let Some(data) = stream.next().await {
+        let data = match data {
+            Ok(data) => data,
+            Err(e) => {
+                if let Some(spinner) = spinner.take() {
+                    spinner.stop().await;
+                }
+                return Err(e);
+            }
+        };
+        if let Some(spinner) = spinner.take() {
+            spinner.stop().await;
+            println!("{}\n", answer.title());
+        }
+        content.push_str(&data.content);
+        let rendered = match answer {
+            Answer::Explanation => highlighter.push(&data.content),
+            _ => style(&data.content).yellow().bold().to_string(),
+        };
+        let _ = write!(stdout, "{}", rendered);
+        let _ = stdout.flush();
+    }
+
+    if let Some(spinner) = spinner.take() {
+        spinner.stop().await;
+        println!("{}\n", answer.title());
+    }
+    println!("{}\n", highlighter.finish());
+    Ok(content)
+}
+
+/// Runs the chain without printing its answer, for answers that are shown once they are
+/// processed. A spinner is drawn meanwhile on terminals.
+pub async fn invoke_chain<C: Chain>(
+    shared_state: &SharedState,
+    chain: &C,
+    input_variables: PromptArgs,
+) -> Result<String, ChainError> {
+    if !shared_state.streaming() {
+        return chain.invoke(input_variables).await;
+    }
+    let spinner = Spinner::start("Thinking...");
+    let result = chain.invoke(input_variables).await;
+    spinner.stop().await;
+    result
+}
+
+/// Like `run_chain`, but also prints the answer when it was not streamed.
+pub async fn show_chain<C: Chain>(
+    shared_state: &SharedState,
+    chain: &C,
+    input_variables: PromptArgs,
+    answer: Answer,
+) -> Result<String, ChainError> {
+    let content = run_chain(shared_state, chain, input_variables, answer).await?;
+    if !shared_state.streaming() {
+        answer.print(&content);
+    }
+    Ok(content)
+}
@@ -0,0 +1,469 @@
+use std::{cmp::Reverse, collections::HashMap, sync::OnceLock};
+
+use regex::Regex;
+
+use crate::chains::{DEFAULT_COMMIT_EXAMPLE, DEFAULT_COMMIT_STYLE, DEFAULT_FULL_COMMIT_EXAMPLE};
+
+use super::{
+    commit::{header_regex, COMMIT_TYPES, MAX_SUBJECT_LENGTH},
+    git,
+    shared::SharedState,
+};
+
+/// How many recent commits the style is learned from.
+const SAMPLE_SIZE: usize = 50;
+
+/// Fewer commits than this say nothing about the style of a repository.
+const MIN_SAMPLE_SIZE: usize = 5;
+
+const MAX_EXAMPLES: usize = 5;
+const MAX_FULL_EXAMPLES: usize = 2;
+const MAX_SCOPES: usize = 8;
+
+/// Bodies of full examples are cut to this many lines.
+const MAX_EXAMPLE_BODY_LINES: usize = 12;
+
+/// What commit subjects start with, before the summary or the conventional type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Prefix {
+    /// An emoji or a `:shortcode:`, as in https://gitmoji.dev.
+    Gitmoji,
+    /// An issue tracker key, like `ABC` in `ABC-123: ...`.
+    Ticket(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Casing {
+    /// `fix: handle empty input`
+    Lower,
+    /// `Fix: Handle empty input`
+    Sentence,
+}
+
+/// How the commit messages of a repository are written, learned from its recent history.
+/// Each trait is kept only when most of the sampled commits share it.
+#[derive(Debug, Clone, Default)]
+pub struct CommitStyle {
+    /// How many commits it was learned from, none when the history is too short.
+    pub sampled: usize,
+    pub prefix: Option<Prefix>,
+    /// Whether subjects are `type(scope): summary`, after the prefix.
+    pub conventional: bool,
+    /// The conventional types in use, the most frequent first.
+    pub types: Vec<String>,
+    /// The conventional scopes in use, the most frequent first.
+    pub scopes: Vec<String>,
+    pub casing: Option<Casing>,
+    /// The length most subjects stay within.
+    pub max_length: Option<usize>,
+    pub trailing_period: bool,
+    /// Recent messages as they were written, the most recent first.
+    pub examples: Vec<String>,
+}
+
+/// A commit subject split in its parts.
+struct Subject<'a> {
+    prefix: Option<Prefix>,
+    kind: Option<&'a str>,
+    scope: Option<&'a str>,
+    summary: &'a str,
+}
+
+impl CommitStyle {
+    /// Learns the style of the repository containing the current directory from its last
+    /// commits. A repository without enough history gets the default style.
+    pub fn learn() -> Self {
+        let messages = git::recent_messages(SAMPLE_SIZE).unwrap_or_default();
+        CommitStyle::from_messages(&messages)
+    }
+
+    pub fn from_messages(messages: &[String]) -> Self {
+        let messages = messages
+            .iter()
+            .map(|message| message.trim())
+            .filter(|message| !is_generated(message))
+            .collect::<Vec<&str>>();
+        if messages.len() < MIN_SAMPLE_SIZE {
+            return CommitStyle::default();
+        }
+        let subjects = messages
+            .iter()
+            .map(|message| split_subject(message.lines().next().unwrap_or_default()))
+            .collect::<Vec<Subject>>();
+        let most = |count: usize| count * 2 > subjects.len();
+
+        let gitmoji = subjects
+            .iter()
+            .filter(|s| s.prefix == Some(Prefix::Gitmoji))
+            .count();
+        let tickets = ranked(subjects.iter().filter_map(|s| match &s.prefix {
+            Some(Prefix::Ticket(key)) => Some(key.as_str()),
+            _ => None,
+        }));
+        let prefix = match tickets.first() {
+            _ if most(gitmoji) => Some(Prefix::Gitmoji),
+            Some((key, count)) if most(*count) => Some(Prefix::Ticket(key.to_string())),
+            _ => None,
+        };
+
+        let conventional = subjects.iter().filter(|s| s.kind.is_some()).count();
+        let names = |counts: Vec<(&str, usize)>| {
+            counts
+                .into_iter()
+                .map(|(name, _)| name.to_string())
+                .collect::<Vec<String>>()
+        };
+        let types = names(ranked(subjects.iter().filter_map(|s| s.kind)));
+        let mut scopes = names(ranked(subjects.iter().filter_map(|s| s.scope)));
+        scopes.truncate(MAX_SCOPES);
+
+        let capitals = subjects
+            .iter()
+            .filter_map(|s| s.summary.chars().find(|c| c.is_alphabetic()))
+            .map(|c| c.is_uppercase())
+            .collect::<Vec<bool>>();
+        let upper = capitals.iter().filter(|upper| **upper).count();
+        let casing = match capitals.len() {
+            0 => None,
+            n if upper * 2 > n => Some(Casing::Sentence),
+            n if (n - upper) * 2 > n => Some(Casing::Lower),
+            _ => None,
+        };
+
+        let mut lengths = messages
+            .iter()
+            .map(|message| message.lines().next().unwrap_or_default().chars().count())
+            .collect::<Vec<usize>>();
+        lengths.sort();
+        // Nine subjects in ten are within it.
+        let max_length = lengths[lengths.len() * 9 / 10].clamp(50, 100);
+        let periods = subjects.iter().filter(|s| s.summary.ends_with('.')).count();
+
+        CommitStyle {
+            sampled: messages.len(),
+            prefix,
+            conventional: most(conventional),
+            types,
+            scopes,
+            casing,
+            max_length: Some(max_length),
+            trailing_period: most(periods),
+            examples: messages.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    /// Whether the history follows Conventional Commits, without any prefix before the
+    /// type, or says nothing.
+    pub fn is_conventional(&self) -> bool {
+        self.sampled == 0 || (self.conventional && self.prefix.is_none())
+    }
+
+    /// The longest subject accepted: the length most subjects of the history stay within,
+    /// between 50 and 100, or `MAX_SUBJECT_LENGTH` without a history.
+    pub fn subject_length(&self) -> usize {
+        self.max_length
+            .map_or(MAX_SUBJECT_LENGTH, |length| length.clamp(50, 100))
+    }
+
+    /// The commit types accepted: the standard ones and the ones the repository uses.
+    pub fn accepted_types(&self) -> Vec<&str> {
+        let mut types = COMMIT_TYPES.to_vec();
+        types.extend(
+            self.types
+                .iter()
+                .map(|kind| kind.as_str())
+                .filter(|kind| !COMMIT_TYPES.contains(kind)),
+        );
+        types
+    }
+
+    /// How to write the subject line, as prompt instructions.
+    pub fn instructions(&self) -> String {
+        if self.sampled == 0 {
+            return DEFAULT_COMMIT_STYLE.to_string();
+        }
+        let mut rules = vec![format!(
+            "- Follow the style of the last {} commits of this repository, shown in the examples.",
+            self.sampled
+        )];
+        match &self.prefix {
+            Some(Prefix::Gitmoji) => {
+                rules.push("- Start the subject with the gitmoji fitting the change.".to_string())
+            }
+            Some(Prefix::Ticket(key)) => rules.push(match branch_ticket(key) {
+                Some(ticket) => format!("- Start the subject with the ticket `{}`.", ticket),
+                None => format!(
+                    "- Start the subject with the `{}-<number>` ticket when the context names one.",
+                    key
+                ),
+            }),
+            None => {}
+        }
+        if self.conventional {
+            let scopes = match self.scopes.is_empty() {
+                true => "optionally a scope".to_string(),
+                false => format!("a scope such as {} when one fits", self.scopes.join(", ")),
+            };
+            rules.push(format!(
+                "- Use the conventional commit format, with the type of change ({}), {}, and a brief and imperative summary.",
+                self.types.join(", "),
+                scopes
+            ));
+        } else {
+            rules.push("- Write a brief and imperative summary of the changes.".to_string());
+        }
+        match self.casing {
+            Some(Casing::Sentence) => {
+                rules.push("- Start the summary with a capital letter.".to_string())
+            }
+            Some(Casing::Lower) => {
+                rules.push("- Start the summary with a lowercase letter.".to_string())
+            }
+            None => {}
+        }
+        let period = match self.trailing_period {
+            true => "ending with a period",
+            false => "without a final period",
+        };
+        rules.push(format!(
+            "- Keep the subject line within {} characters, {}.",
+            self.subject_length(),
+            period
+        ));
+        rules.join("\n")
+    }
+
+    /// Real messages of the repository to imitate: subjects, or whole messages with a
+    /// body for `full` ones.
+    pub fn examples(&self, full: bool) -> String {
+        let examples = match full {
+            true => self
+                .examples
+                .iter()
+                .filter(|message| message.contains("\n\n"))
+                .take(MAX_FULL_EXAMPLES)
+                .map(|message| {
+                    message
+                        .lines()
+                        .take(MAX_EXAMPLE_BODY_LINES + 2)
+                        .collect::<Vec<&str>>()
+                        .join("\n")
+                })
+                .collect::<Vec<String>>(),
+            false => self
+                .examples
+                .iter()
+                .filter_map(|message| message.lines().next())
+                .take(MAX_EXAMPLES)
+                .map(|subject| subject.to_string())
+                .collect::<Vec<String>>(),
+        };
+        match (examples.is_empty(), full) {
+            (true, true) => DEFAULT_FULL_COMMIT_EXAMPLE.to_string(),
+            (true, false) => DEFAULT_COMMIT_EXAMPLE.to_string(),
+            (false, true) => examples.join("\n\n---\n\n"),
+            (false, false) => examples.join("\n"),
+        }
+    }
+}
+
+/// The style generated messages are written in: the one of the repository, or the default
+/// one when the repository config asks for Conventional Commits and the history does not
+/// follow them.
+pub fn commit_style(shared_state: &SharedState) -> CommitStyle {
+    let style = CommitStyle::learn();
+    match shared_state.project().commit.conventional {
+        Some(true) if !style.is_conventional() => CommitStyle::default(),
+        _ => style,
+    }
+}
+
+/// Merges, reverts and fixups are written by git, not by people.
+fn is_generated(message: &str) -> bool {
+    ["Merge ", "Revert ", "fixup! ", "squash! ", "amend! "]
+        .iter()
+        .any(|start| message.starts_with(start))
+}
+
+fn split_subject(subject: &str) -> Subject<'_> {
+    static TICKET: OnceLock<Regex> = OnceLock::new();
+    static GITMOJI: OnceLock<Regex> = OnceLock::new();
+    let ticket = TICKET.get_or_init(|| {
+        Regex::new(r"^\[?(?P<key>[A-Z][A-Z0-9]+)-\d+\]?:?\s+(?P<rest>.*)$").unwrap()
+    });
+    let gitmoji = GITMOJI.get_or_init(|| {
+        Regex::new(r"^(?::[a-z0-9_+-]+:|\p{Extended_Pictographic}\x{FE0F}?)\s*(?P<rest>.*)$")
+            .unwrap()
+    });
+
+    let (prefix, rest) = if let Some(captures) = ticket.captures(subject) {
+        let rest = captures.name("rest").map_or("", |m| m.as_str());
+        (Some(Prefix::Ticket(captures["key"].to_string())), rest)
+    } else if let Some(captures) = gitmoji.captures(subject) {
+        (
+            Some(Prefix::Gitmoji),
+            captures.name("rest").map_or("", |m| m.as_str()),
+        )
+    } else {
+        (None, subject)
+    };
+    match header_regex().captures(rest) {
+        Some(captures)
+            if captures["kind"].chars().all(|c| c.is_ascii_lowercase())
+                && rest[captures["kind"].len()..].contains(": ") =>
+        {
+            Subject {
+                prefix,
+                kind: captures.name("kind").map(|m| m.as_str()),
+                scope: captures.name("scope").map(|m| m.as_str().trim()),
+                summary: captures.name("description").map_or("", |m| m.as_str()),
+            }
+        }
+        _ => Subject {
+            prefix,
+            kind: None,
+            scope: None,
+            summary: rest,
+        },
+    }
+}
+
+/// Every distinct name with how often it appears, the most frequent first.
+fn ranked<'a>(names: impl Iterator<Item = &'a str>) -> Vec<(&'a str, usize)> {
+    let mut counts = HashMap::new();
+    for name in names {
+        *counts.entry(name).or_insert(0) += 1;
+    }
+    let mut counts = counts.into_iter().collect::<Vec<(&str, usize)>>();
+    counts.sort_by_key(|&(name, count)| (Reverse(count), name));
+    counts
+}
+
+/// The ticket the current branch is named after.
+fn branch_ticket(key: &str) -> Option<String> {
+    ticket_in(key, &git::branch_name()?)
+}
+
+/// The ticket of `key` a branch is named after, like `ABC-123` in `feature/abc-123-login`.
+fn ticket_in(key: &str, branch: &str) -> Option<String> {
+    let ticket = Regex::new(&format!(r"(?i)\b{}-\d+", regex::escape(key))).ok()?;
+    ticket
+        .find(branch)
+        .map(|ticket| ticket.as_str().to_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(subjects: &[String]) -> CommitStyle {
+        CommitStyle::from_messages(subjects)
+    }
+
+    #[test]
+    fn subject_length_follows_the_history() {
+        assert_eq!(CommitStyle::default().subject_length(), MAX_SUBJECT_LENGTH);
+        let long = (0..10)
+            .map(|i| format!("feat: {} {}", "a".repeat(80), i))
+            .collect::<Vec<String>>();
+        assert_eq!(history(&long).subject_length(), 88);
+        let short = (0..10)
+            .map(|i| format!("fix: short {}", i))
+            .collect::<Vec<String>>();
+        assert_eq!(history(&short).subject_length(), 50);
+        let huge = (0..10)
+            .map(|i| format!("feat: {} {}", "a".repeat(200), i))
+            .collect::<Vec<String>>();
+        assert_eq!(history(&huge).subject_length(), 100);
+    }
+
+    fn messages(subjects: &[&str]) -> Vec<String> {
+        subjects.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn conventional_history_is_learned() {
+        let style = history(&messages(&[
+            "fix(parser): handle empty input",
+            "feat(cli): add the export menu",
+            "fix(parser): keep quotes",
+            "docs: explain the config file",
+            "fix(cli): exit with the status",
+            "Merge branch 'main' into export",
+            "Revert \"feat: add the export menu\"",
+        ]));
+        assert_eq!(style.sampled, 5);
+        assert_eq!(style.prefix, None);
+        assert!(style.conventional);
+        assert!(style.is_conventional());
+        assert_eq!(style.types, ["fix", "docs", "feat"]);
+        assert_eq!(style.scopes, ["cli", "parser"]);
+        assert_eq!(style.casing, Some(Casing::Lower));
+        assert!(!style.trailing_period);
+        let instructions = style.instructions();
+        assert!(instructions.contains("(fix, docs, feat), a scope such as cli, parser"));
+        assert!(instructions.contains("lowercase"));
+    }
+
+    #[test]
+    fn prefixes_and_casing_are_learned() {
+        let gitmoji = history(&messages(&[
+            "✨ Add the export menu",
+            ":bug: Fix the crash on empty input",
+            "📝 Document the config file.",
+            "♻️ Split the parser.",
+            "Test the exporter.",
+        ]));
+        assert_eq!(gitmoji.prefix, Some(Prefix::Gitmoji));
+        assert!(!gitmoji.conventional);
+        assert!(gitmoji.types.is_empty());
+        assert_eq!(gitmoji.casing, Some(Casing::Sentence));
+        assert!(gitmoji.trailing_period);
+
+        let tickets = history(&messages(&[
+            "ABC-1: Add the export menu",
+            "[ABC-2] Fix the crash on empty input",
+            "ABC-3 Document the config file",
+            "XY-4: Split the parser",
+            "Test the exporter",
+        ]));
+        assert_eq!(tickets.prefix, Some(Prefix::Ticket("ABC".to_string())));
+        assert!(!tickets.is_conventional());
+        let instructions = tickets.instructions();
+        let expected = match branch_ticket("ABC") {
+            Some(ticket) => format!("- Start the subject with the ticket `{}`.", ticket),
+            None => {
+                "- Start the subject with the `ABC-<number>` ticket when the context names one."
+                    .to_string()
+            }
+        };
+        assert!(instructions.lines().any(|line| line == expected));
+        assert!(instructions.contains("- Write a brief and imperative summary of the changes."));
+    }
+
+    #[test]
+    fn short_histories_say_nothing() {
+        let style = history(&messages(&[
+            "fix: one",
+            "fix: two",
+            "Merge pull request #3 from x/y",
+            "fix: three",
+            "fixup! fix: three",
+            "fix: four",
+        ]));
+        assert_eq!(style.sampled, 0);
+        assert!(style.is_conventional());
+        assert_eq!(style.instructions(), DEFAULT_COMMIT_STYLE);
+    }
+
+    #[test]
+    fn tickets_are_read_from_the_branch_name() {
+        assert_eq!(
+            ticket_in("ABC", "feature/abc-123-login"),
+            Some("ABC-123".to_string())
+        );
+        assert_eq!(ticket_in("ABC", "ABC-7"), Some("ABC-7".to_string()));
+        assert_eq!(ticket_in("ABC", "fix/xabc-12"), None);
+        assert_eq!(ticket_in("ABC", "main"), None);
+    }
+}
@@ -0,0 +1,96 @@
+use std::{collections::BTreeMap, fmt, fs, path::PathBuf};
+
+use crate::{
+    chains::{find_chain_prompt, CHAIN_PROMPTS},
+    error::CopilotError,
+};
+
+use super::{config::ConfigFile, project::ProjectConfig, shared::expand_home};
+
+/// Directory holding one `<chain>.txt` template per overridden prompt.
+pub const PROMPTS_PATH: &str = "~/.free_copilot_prompts";
+
+/// Where the template used for a chain comes from, lowest precedence first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptSource {
+    BuiltIn,
+    File,
+    UserConfig,
+    Project,
+}
+
+impl fmt::Display for PromptSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PromptSource::BuiltIn => write!(f, "built-in"),
+            PromptSource::File => write!(f, "file"),
+            PromptSource::UserConfig => write!(f, "user config"),
+            PromptSource::Project => write!(f, "repository config"),
+        }
+    }
+}
+
+pub fn prompt_file(name: &str) -> PathBuf {
+    expand_home(PROMPTS_PATH).join(format!("{}.txt", name))
+}
+
+/// Collects every prompt override without validating it.
+/// Repository prompts win over the user config, which wins over the prompt files.
+pub fn raw_prompts(
+    user: Option<&ConfigFile>,
+    project: &ProjectConfig,
+) -> BTreeMap<String, (String, PromptSource)> {
+    let mut templates = BTreeMap::new();
+    for chain_prompt in CHAIN_PROMPTS {
+        if let Ok(template) = fs::read_to_string(prompt_file(chain_prompt.name)) {
+            templates.insert(
+                chain_prompt.name.to_string(),
+                (template, PromptSource::File),
+            );
+        }
+    }
+
+    let configured = user
+        .map(|u| &u.prompts)
+        .into_iter()
+        .flatten()
+        .map(|(name, template)| (name, template, PromptSource::UserConfig))
+        .chain(
+            project
+                .prompts
+                .iter()
+                .map(|(name, template)| (name, template, PromptSource::Project)),
+        );
+    for (name, template, source) in configured {
+        templates.insert(name.clone(), (template.clone(), source));
+    }
+    templates
+}
+
+/// Loads every prompt override, validated against the variables of its chain.
+pub fn load_prompts(
+    user: Option<&ConfigFile>,
+    project: &ProjectConfig,
+) -> Result<BTreeMap<String, (String, PromptSource)>, CopilotError> {
+    raw_prompts(user, project)
+        .into_iter()
+        .map(|(name, (template, source))| {
+            let chain_prompt = find_chain_prompt(&name).ok_or_else(|| {
+                CopilotError::Config(format!(
+                    "Unknown prompt '{}' in the {}, see `prompts list`",
+                    name, source
+                ))
+            })?;
+            let template = chain_prompt.validate(&template).map_err(|e| {
+                CopilotError::Config(match source {
+                    PromptSource::File => format!(
+                        "{}, fix it with `prompts edit {}` or `prompts reset {}`",
+                        e, name, name
+                    ),
+                    _ => format!("{}, fix it in the {}", e, source),
+                })
+            })?;
+            Ok((name, (template, source)))
+        })
+        .collect()
+}
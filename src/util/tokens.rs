@@ -0,0 +1,85 @@
+use super::shared::SharedState;
+
+/// Context windows by model name prefix, the most specific prefix first.
+const CONTEXT_WINDOWS: [(&str, usize); 16] = [
+    ("gpt-3.5-turbo-instruct", 4_096),
+    ("gpt-3.5", 16_385),
+    ("gpt-4-32k", 32_768),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4o", 128_000),
+    ("gpt-4.1", 1_000_000),
+    ("gpt-4", 8_192),
+    ("gpt-5", 400_000),
+    ("o1", 128_000),
+    ("o3", 200_000),
+    ("claude", 200_000),
+    ("llama2", 4_096),
+    ("llama3.", 128_000),
+    ("llama3", 8_192),
+    ("mistral", 32_768),
+    ("qwen", 32_768),
+];
+
+/// The context window assumed for models not in the table.
+const DEFAULT_CONTEXT_WINDOW: usize = 8_192;
+
+/// Roughly how many tokens `text` takes for the provider. Claude tokenizers split text a
+/// bit finer than the OpenAI ones.
+pub fn estimate_tokens(provider: &str, text: &str) -> usize {
+    let chars_per_token = match provider {
+        "anthropic" => 3.5,
+        _ => 4.0,
+    };
+    (text.chars().count() as f64 / chars_per_token).ceil() as usize
+}
+
+/// How many tokens the model takes in a single request.
+pub fn context_window(model: &str) -> usize {
+    let model = model.to_lowercase();
+    let model = model.rsplit('/').next().unwrap_or(&model);
+    CONTEXT_WINDOWS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, window)| *window)
+        .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+}
+
+/// How many tokens of the staged diff a commit prompt may hold: `max_diff_tokens` of the
+/// repository config, or half the context window, leaving the rest for the prompt and the
+/// answer.
+pub fn diff_budget(shared_state: &SharedState) -> usize {
+    shared_state
+        .project()
+        .commit
+        .max_diff_tokens
+        .unwrap_or_else(|| context_window(shared_state.model()) / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_most_specific_prefix_wins() {
+        assert_eq!(context_window("gpt-4o-mini"), 128_000);
+        assert_eq!(context_window("gpt-4-0613"), 8_192);
+        assert_eq!(context_window("gpt-4-turbo-preview"), 128_000);
+        assert_eq!(context_window("gpt-3.5-turbo-instruct"), 4_096);
+        assert_eq!(context_window("llama3.1:8b"), 128_000);
+        assert_eq!(context_window("llama3:8b"), 8_192);
+    }
+
+    #[test]
+    fn model_names_are_read_without_their_vendor() {
+        assert_eq!(context_window("meta-llama/Llama3.2-3B"), 128_000);
+        assert_eq!(context_window("Claude-Sonnet-4"), 200_000);
+        assert_eq!(context_window("unknown-model"), DEFAULT_CONTEXT_WINDOW);
+    }
+
+    #[test]
+    fn claude_counts_more_tokens() {
+        let text = "a".repeat(70);
+        assert_eq!(estimate_tokens("openai", &text), 18);
+        assert_eq!(estimate_tokens("anthropic", &text), 20);
+    }
+}